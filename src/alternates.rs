@@ -0,0 +1,188 @@
+//! Alternate config files, for some machines only.
+//!
+//! A file named `<name>##<condition>` is an alternate of `<name>`: it
+//! is deployed as `<name>`, but only on machines that meet the
+//! condition.
+//!
+//! - `##os.<os>` matches the operating system (e.g., `linux`, `macos`,
+//!   as in Rust's `std::consts::OS`).
+//! - `##host.<host>` matches the host name (up to the first `.`).
+//!   `DEEZ_HOST` overrides the host name.
+//!
+//! The most specific match wins: host, then OS, then the plain file.
+//! Alternates with an unknown condition never match.
+
+use std::borrow::Cow;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::LazyLock;
+
+/// Separates the name of the file from the condition.
+pub const SEPARATOR: &str = "##";
+
+/// What alternates are picked for.
+#[derive(Debug)]
+pub struct Machine {
+    pub os: String,
+    pub host: Option<String>,
+}
+
+static MACHINE: LazyLock<Machine> = LazyLock::new(|| Machine {
+    os: env::consts::OS.to_string(),
+    host: get_host_name(),
+});
+
+fn get_host_name() -> Option<String> {
+    let host = env::var("DEEZ_HOST")
+        .ok()
+        .filter(|host| !host.is_empty())
+        .or_else(|| {
+            let output = Command::new("hostname").output().ok()?;
+            output
+                .status
+                .success()
+                .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+        })?;
+    let host = host.trim();
+    let host = host.split_once('.').map_or(host, |(host, _)| host);
+    (!host.is_empty()).then(|| host.to_string())
+}
+
+/// The host name alternates are picked for, if known.
+#[must_use]
+pub fn host() -> Option<&'static str> {
+    MACHINE.host.as_deref()
+}
+
+/// How specific an alternate is. Higher wins.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+enum Specificity {
+    Plain,
+    Os,
+    Host,
+}
+
+/// The path `path` is deployed as, without its condition (if any).
+#[must_use]
+pub fn base_path(path: &Path) -> Cow<'_, Path> {
+    match split(path) {
+        Some((name, _)) => Cow::Owned(path.with_file_name(name)),
+        None => Cow::Borrowed(path),
+    }
+}
+
+/// Whether `path` (relative to `root`) is to be deployed on this
+/// machine.
+///
+/// That is, whether it is meant for this machine, and no more specific
+/// alternate for this machine exists next to it in `root`.
+#[must_use]
+pub fn is_selected(root: &Path, path: &Path) -> bool {
+    is_selected_for(&MACHINE, path, |alternate| {
+        let alternate = root.join(alternate);
+        alternate.exists() || alternate.is_symlink()
+    })
+}
+
+fn is_selected_for(machine: &Machine, path: &Path, exists: impl Fn(&Path) -> bool) -> bool {
+    let (name, specificity) = match split(path) {
+        Some((name, condition)) => match specificity(machine, condition) {
+            Some(specificity) => (name, specificity),
+            // Another machine's.
+            None => return false,
+        },
+        None => match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => (name, Specificity::Plain),
+            None => return true,
+        },
+    };
+
+    let alternate = |condition: &str| -> PathBuf {
+        path.with_file_name(format!("{name}{SEPARATOR}{condition}"))
+    };
+
+    let is_overridden_by_host = specificity < Specificity::Host
+        && machine
+            .host
+            .as_ref()
+            .is_some_and(|host| exists(&alternate(&format!("host.{host}"))));
+    let is_overridden_by_os =
+        specificity < Specificity::Os && exists(&alternate(&format!("os.{}", machine.os)));
+
+    !is_overridden_by_host && !is_overridden_by_os
+}
+
+/// Split the file name of `path` into the name, and the condition.
+fn split(path: &Path) -> Option<(&str, &str)> {
+    path.file_name()?
+        .to_str()?
+        .split_once(SEPARATOR)
+        .filter(|(name, _)| !name.is_empty())
+}
+
+/// How specific `condition` is, if it matches `machine`.
+fn specificity(machine: &Machine, condition: &str) -> Option<Specificity> {
+    match condition.split_once('.')? {
+        ("os", os) if os == machine.os => Some(Specificity::Os),
+        ("host", host) if machine.host.as_deref() == Some(host) => Some(Specificity::Host),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine() -> Machine {
+        Machine {
+            os: String::from("linux"),
+            host: Some(String::from("laptop")),
+        }
+    }
+
+    fn is_selected(path: &str, existing: &[&str]) -> bool {
+        is_selected_for(&machine(), Path::new(path), |alternate| {
+            existing
+                .iter()
+                .any(|existing| Path::new(existing) == alternate)
+        })
+    }
+
+    #[test]
+    fn base_path_strips_condition() {
+        assert_eq!(
+            base_path(Path::new(".config/git/config##host.laptop")),
+            Path::new(".config/git/config")
+        );
+        assert_eq!(base_path(Path::new(".bashrc")), Path::new(".bashrc"));
+        // Not a condition, part of the name.
+        assert_eq!(base_path(Path::new("##os.linux")), Path::new("##os.linux"));
+    }
+
+    #[test]
+    fn plain_file_without_alternates_is_selected() {
+        assert!(is_selected(".bashrc", &[]));
+    }
+
+    #[test]
+    fn alternates_for_other_machines_are_not_selected() {
+        assert!(!is_selected(".bashrc##os.macos", &[]));
+        assert!(!is_selected(".bashrc##host.desktop", &[]));
+        assert!(!is_selected(".bashrc##unknown.linux", &[]));
+        // They don't override the plain file either.
+        assert!(is_selected(".bashrc", &[".bashrc##os.macos"]));
+    }
+
+    #[test]
+    fn most_specific_alternate_wins() {
+        let existing = [".bashrc", ".bashrc##os.linux", ".bashrc##host.laptop"];
+        assert!(!is_selected(".bashrc", &existing));
+        assert!(!is_selected(".bashrc##os.linux", &existing));
+        assert!(is_selected(".bashrc##host.laptop", &existing));
+
+        let existing = [".bashrc", ".bashrc##os.linux"];
+        assert!(!is_selected(".bashrc", &existing));
+        assert!(is_selected(".bashrc##os.linux", &existing));
+    }
+}
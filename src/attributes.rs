@@ -0,0 +1,319 @@
+//! Per-path settings, declared in the root's `.deezattributes` file.
+//!
+//! The file works like `.gitattributes`: each line is a pattern (with
+//! the same syntax as pathspecs), followed by the attributes to set on
+//! the matching paths. Prefixing an attribute with `-` unsets it. Blank
+//! lines and lines starting with `#` are ignored.
+//!
+//! ```text
+//! # Link Neovim's config as a whole.
+//! .config/nvim fold
+//!
+//! # Copy Git's config, but link everything else.
+//! *            link
+//! .gitconfig   copy
+//! ```
+//!
+//! When several lines match a path, the last one wins.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::alternates;
+use crate::pathspec::PathSpec;
+use crate::state::manifest::Kind;
+
+/// Name of the attributes file, at the root of a config root.
+pub const ATTRIBUTES_FILE: &str = ".deezattributes";
+
+/// A setting that can be given to paths.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Attribute {
+    /// `link` symlinks the directory as a whole, instead of the files
+    /// in it.
+    Fold,
+    /// `deploy` symlinks the file (and `sync` leaves it alone).
+    Link,
+    /// `deploy` copies the file (and `link` leaves it alone).
+    Copy,
+}
+
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fold => write!(f, "fold"),
+            Self::Link => write!(f, "link"),
+            Self::Copy => write!(f, "copy"),
+        }
+    }
+}
+
+impl FromStr for Attribute {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fold" => Ok(Self::Fold),
+            "link" => Ok(Self::Link),
+            "copy" => Ok(Self::Copy),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Rule {
+    pattern: PathSpec,
+    /// Attributes, and whether they are set (or unset, with `-`).
+    attributes: Vec<(Attribute, bool)>,
+}
+
+/// The attributes of a config root.
+///
+/// A root without a `.deezattributes` file has no attributes.
+#[derive(Debug, Default)]
+pub struct Attributes {
+    rules: Vec<Rule>,
+}
+
+impl Attributes {
+    /// Load the attributes of `root`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the attributes file exists but cannot be read, or is
+    /// invalid.
+    pub fn load(root: &Path) -> Result<Self, String> {
+        match fs::read_to_string(root.join(ATTRIBUTES_FILE)) {
+            Ok(content) => Self::parse(&content),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(format!("Could not read '{ATTRIBUTES_FILE}': {err}")),
+        }
+    }
+
+    /// Parse the content of an attributes file.
+    ///
+    /// # Errors
+    ///
+    /// Errors on invalid patterns, and on unknown attributes.
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut rules = Vec::new();
+
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |reason: String| format!("{ATTRIBUTES_FILE}, line {}: {reason}", i + 1);
+
+            let mut words = line.split_whitespace();
+            let pattern = words.next().expect("the line is not empty");
+            let pattern = PathSpec::parse(&[pattern.to_string()]).map_err(error)?;
+
+            let attributes = words
+                .map(|word| {
+                    let (name, is_set) = match word.strip_prefix('-') {
+                        Some(name) => (name, false),
+                        None => (word, true),
+                    };
+                    name.parse()
+                        .map(|attribute| (attribute, is_set))
+                        .map_err(|()| error(format!("Unknown attribute '{name}'")))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            rules.push(Rule {
+                pattern,
+                attributes,
+            });
+        }
+
+        Ok(Self { rules })
+    }
+
+    /// Whether `attribute` is set on `path` (relative to the root).
+    ///
+    /// Patterns match directories, and everything inside them.
+    #[must_use]
+    pub fn is_set(&self, path: &Path, attribute: Attribute) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .filter(|rule| rule.pattern.matches(path))
+            .find_map(|rule| {
+                rule.attributes
+                    .iter()
+                    .find(|(candidate, _)| *candidate == attribute)
+                    .map(|(_, is_set)| *is_set)
+            })
+            .unwrap_or(false)
+    }
+
+    /// How `path` (relative to the root) is declared to be deployed, if
+    /// it is.
+    ///
+    /// `link` and `copy` override each other; unsetting either one
+    /// (with `-`) goes back to no strategy. Alternates follow their
+    /// plain file.
+    #[must_use]
+    pub fn strategy(&self, path: &Path) -> Option<Kind> {
+        let path = alternates::base_path(path);
+        let path = path.as_ref();
+        self.rules
+            .iter()
+            .rev()
+            .filter(|rule| rule.pattern.matches(path))
+            .find_map(|rule| {
+                rule.attributes
+                    .iter()
+                    .rev()
+                    .find_map(|(attribute, is_set)| match (attribute, is_set) {
+                        (Attribute::Link, true) => Some(Some(Kind::Link)),
+                        (Attribute::Copy, true) => Some(Some(Kind::Copy)),
+                        (Attribute::Link | Attribute::Copy, false) => Some(None),
+                        (Attribute::Fold, _) => None,
+                    })
+            })
+            .flatten()
+    }
+
+    /// The outermost directory `path` is in that is folded, if any.
+    ///
+    /// Folded directories are linked as a whole (see [`Attribute::Fold`]).
+    #[must_use]
+    pub fn folded_dir<'a>(&self, path: &'a Path) -> Option<&'a Path> {
+        if self.rules.is_empty() {
+            return None;
+        }
+        // `ancestors()` goes from `path` up; skip `path` itself (it is
+        // a file), and the empty path at the top.
+        let mut dirs: Vec<&Path> = path
+            .ancestors()
+            .skip(1)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .collect();
+        dirs.reverse();
+        dirs.into_iter()
+            .find(|dir| self.is_set(dir, Attribute::Fold))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_file_sets_nothing() {
+        let attributes = Attributes::parse("").unwrap();
+        assert!(!attributes.is_set(Path::new(".config/nvim"), Attribute::Fold));
+        assert_eq!(
+            attributes.folded_dir(Path::new(".config/nvim/init.lua")),
+            None
+        );
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_ignored() {
+        let attributes = Attributes::parse("# Comment.\n\n  \n.config/nvim fold\n").unwrap();
+        assert!(attributes.is_set(Path::new(".config/nvim"), Attribute::Fold));
+    }
+
+    #[test]
+    fn pattern_matches_directory_and_its_content() {
+        let attributes = Attributes::parse(".config/nvim fold").unwrap();
+        assert!(attributes.is_set(Path::new(".config/nvim"), Attribute::Fold));
+        assert!(attributes.is_set(Path::new(".config/nvim/lua"), Attribute::Fold));
+        assert!(!attributes.is_set(Path::new(".config"), Attribute::Fold));
+        assert!(!attributes.is_set(Path::new(".config/fish"), Attribute::Fold));
+    }
+
+    #[test]
+    fn last_matching_line_wins() {
+        let attributes = Attributes::parse(".config/* fold\n.config/fish -fold\n").unwrap();
+        assert!(attributes.is_set(Path::new(".config/nvim"), Attribute::Fold));
+        assert!(!attributes.is_set(Path::new(".config/fish"), Attribute::Fold));
+
+        let attributes = Attributes::parse(".config/fish -fold\n.config/* fold\n").unwrap();
+        assert!(attributes.is_set(Path::new(".config/fish"), Attribute::Fold));
+    }
+
+    #[test]
+    fn lines_without_the_attribute_do_not_unset_it() {
+        let attributes = Attributes::parse(".config/nvim fold\n.config/nvim\n").unwrap();
+        assert!(attributes.is_set(Path::new(".config/nvim"), Attribute::Fold));
+    }
+
+    #[test]
+    fn folded_dir_is_the_outermost_one() {
+        let attributes = Attributes::parse(".config/nvim fold\n.config/nvim/lua fold\n").unwrap();
+        assert_eq!(
+            attributes.folded_dir(Path::new(".config/nvim/lua/plugins.lua")),
+            Some(Path::new(".config/nvim"))
+        );
+    }
+
+    #[test]
+    fn files_at_the_root_are_never_folded() {
+        let attributes = Attributes::parse("* fold").unwrap();
+        assert_eq!(attributes.folded_dir(Path::new(".bashrc")), None);
+        assert_eq!(
+            attributes.folded_dir(Path::new(".config/nvim/init.lua")),
+            Some(Path::new(".config"))
+        );
+    }
+
+    #[test]
+    fn strategy_is_undeclared_by_default() {
+        let attributes = Attributes::parse(".config/nvim fold").unwrap();
+        assert_eq!(attributes.strategy(Path::new(".gitconfig")), None);
+        assert_eq!(
+            attributes.strategy(Path::new(".config/nvim/init.lua")),
+            None
+        );
+    }
+
+    #[test]
+    fn strategy_last_matching_line_wins() {
+        let attributes = Attributes::parse("* link\n.gitconfig copy\n").unwrap();
+        assert_eq!(
+            attributes.strategy(Path::new(".config/fish/config.fish")),
+            Some(Kind::Link)
+        );
+        assert_eq!(
+            attributes.strategy(Path::new(".gitconfig")),
+            Some(Kind::Copy)
+        );
+    }
+
+    #[test]
+    fn strategy_can_be_unset() {
+        let attributes = Attributes::parse("* link\n.gitconfig -link\n").unwrap();
+        assert_eq!(attributes.strategy(Path::new(".gitconfig")), None);
+        assert_eq!(attributes.strategy(Path::new(".bashrc")), Some(Kind::Link));
+    }
+
+    #[test]
+    fn strategy_ignores_other_attributes() {
+        let attributes = Attributes::parse(".config/nvim link\n.config/nvim fold\n").unwrap();
+        assert_eq!(
+            attributes.strategy(Path::new(".config/nvim/init.lua")),
+            Some(Kind::Link)
+        );
+    }
+
+    #[test]
+    fn unknown_attribute_is_an_error() {
+        let err = Attributes::parse(".config/nvim fold\n.bashrc shiny\n").unwrap_err();
+        assert_eq!(err, ".deezattributes, line 2: Unknown attribute 'shiny'");
+    }
+
+    #[test]
+    fn invalid_pattern_is_an_error() {
+        let err = Attributes::parse("../outside fold").unwrap_err();
+        assert!(err.starts_with(".deezattributes, line 1: Invalid pathspec"));
+    }
+}
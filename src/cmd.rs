@@ -1,17 +1,36 @@
+pub mod add;
+pub mod apply;
 pub mod clean;
 pub mod cli;
 pub mod common;
+pub mod deploy;
 pub mod diff;
+pub mod forget;
+pub mod interactive;
+pub mod layers;
 pub mod link;
+pub mod relink;
+pub mod restore;
 pub mod rsync;
 pub mod run;
 pub mod status;
 pub mod sync;
+pub mod undo;
+pub mod unlink;
 
+pub use add::add;
+pub use apply::apply;
 pub use clean::clean;
+pub use deploy::deploy;
 pub use diff::{diff, diff_incoming};
+pub use forget::forget;
+pub use layers::layered;
 pub use link::link;
+pub use relink::relink;
+pub use restore::restore;
 pub use rsync::rsync;
 pub use run::run;
 pub use status::status;
 pub use sync::sync;
+pub use undo::undo;
+pub use unlink::unlink;
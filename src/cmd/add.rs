@@ -0,0 +1,291 @@
+use std::fs;
+use std::path::{self, Path, PathBuf};
+
+use deezconfigs::state::{self, Change, manifest};
+use deezconfigs::{ui, utils, walk};
+
+use super::common::{
+    backup_file, check_change, get_home_directory, get_state_directory, local_root_id,
+    record_change, resolve_config_root, update_manifest,
+};
+use super::undo::format_errors;
+
+/// Adopt files from home into the root.
+///
+/// 1. Collect the files given (recursively, for directories), skipping
+///    the ones the root ignores.
+/// 2. Copy them into the root, at the same path relative to the root as
+///    they are relative to `$HOME`.
+///
+/// In link mode, the files in `$HOME` are then replaced by symlinks to
+/// their copy in the root (they are backed up first).
+///
+/// Files end up in the manifest, as if they had been synced (or linked)
+/// from the root.
+///
+/// In dry-run mode, step 2. only reports what would be done.
+#[allow(clippy::too_many_lines)] // More a procedure than a function.
+pub fn add(paths: &[String], link: bool, dry_run: bool) -> Result<(), i32> {
+    if paths.is_empty() {
+        eprintln!(
+            "{error}: Add what? Please provide the path of a file.",
+            error = ui::Color::error("error")
+        );
+        return Err(2);
+    }
+
+    // A dry-run doesn't alter the file system, no need to check.
+    let root = resolve_config_root(None, !dry_run)?;
+    let root_id = local_root_id(&root);
+    // Links are made absolute, from the canonical root.
+    let root = PathBuf::from(&root_id);
+    let home = get_home_directory()?;
+    let state_dir = get_state_directory(&home);
+
+    let backup = state::Backup::new(&state_dir, "add", Some(&root));
+
+    let mut files = Vec::new();
+    let mut nb_errors = 0;
+
+    for path in paths {
+        let path = match path::absolute(path) {
+            Ok(path) => path,
+            Err(err) => {
+                nb_errors += 1;
+                eprintln!(
+                    "{error}: Invalid path '{path}': {err}",
+                    error = ui::Color::error("error")
+                );
+                continue;
+            }
+        };
+
+        let refusal = if !path.exists() && !path.is_symlink() {
+            Some("No such file or directory")
+        } else if is_inside(&path, &root) {
+            Some("It is in the root")
+        } else if is_inside(&path, &state_dir) {
+            Some("It is in the state directory")
+        } else if !is_inside(&path, &home) || path == home {
+            Some("It is not in the home")
+        } else {
+            None
+        };
+        if let Some(reason) = refusal {
+            nb_errors += 1;
+            eprintln!(
+                "{error}: Not adding '{}': {reason}.",
+                path.display(),
+                error = ui::Color::error("error"),
+            );
+            continue;
+        }
+
+        let p = path
+            .strip_prefix(&home)
+            .expect("the path is in the home")
+            .to_path_buf();
+
+        let is_dir = path.is_dir() && !path.is_symlink();
+        if walk::is_ignored(&root, &p, is_dir) {
+            eprintln!(
+                "{warning}: Not adding '{}': It is ignored by the root.",
+                path.display(),
+                warning = ui::Color::warning("warning"),
+            );
+            continue;
+        }
+
+        if is_dir {
+            collect_files(&root, &home, &state_dir, &p, &mut files);
+        } else {
+            files.push(p);
+        }
+    }
+
+    files.sort();
+    files.dedup();
+
+    let mut added = Vec::with_capacity(files.len());
+    let mut deployed = Vec::with_capacity(files.len());
+
+    for p in files {
+        let source = root.join(&p);
+        let destination = home.join(&p);
+
+        // Matches both files and symlinks (even broken ones).
+        if source.exists() || source.is_symlink() {
+            eprintln!(
+                "{warning}: Not adding '{}': It is already in the root.",
+                destination.display(),
+                warning = ui::Color::warning("warning"),
+            );
+            continue;
+        }
+
+        if !dry_run {
+            if let Err(err) = add_file(&p, &source, &destination, link, &backup) {
+                nb_errors += 1;
+                eprintln!("{err}");
+                continue;
+            }
+
+            let kind = if link {
+                manifest::Kind::Link
+            } else {
+                manifest::Kind::Copy
+            };
+            if let Ok(entry) = manifest::Entry::new(kind, &source, &p, &destination) {
+                deployed.push(entry);
+            }
+        }
+
+        added.push(p);
+    }
+
+    if !dry_run
+        && let Err(err) = update_manifest(&home, &root_id, |manifest| {
+            for entry in deployed {
+                manifest.insert(entry);
+            }
+        })
+    {
+        nb_errors += 1;
+        eprintln!("{err}");
+    }
+
+    let operation = if link {
+        ui::Operation::ReplaceSymlink
+    } else {
+        ui::Operation::Create
+    };
+    if dry_run {
+        ui::print_operations(
+            &added
+                .iter()
+                .map(|p| (p.display().to_string(), operation))
+                .collect::<Vec<_>>(),
+        );
+    } else {
+        ui::print_files(
+            &added
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    let nb_files = added.len();
+    let plural = if nb_files == 1 { "" } else { "s" };
+    let errors = format_errors(nb_errors);
+    if dry_run {
+        println!(
+            "Would add {nb_files} file{plural} to '{}'{errors} (dry run).",
+            root.display()
+        );
+    } else {
+        println!(
+            "Added {nb_files} file{plural} to '{}'{errors}.",
+            root.display()
+        );
+    }
+
+    if nb_errors > 0 { Err(1) } else { Ok(()) }
+}
+
+/// Whether `path` is `dir`, or is inside it.
+///
+/// `path` is absolute; `dir` is compared both as is and canonicalized.
+fn is_inside(path: &Path, dir: &Path) -> bool {
+    path.starts_with(dir)
+        || path::absolute(dir).is_ok_and(|dir| path.starts_with(dir))
+        || fs::canonicalize(dir).is_ok_and(|dir| path.starts_with(dir))
+}
+
+/// Collect the files of the home directory `dir` (relative to `home`),
+/// recursively, skipping what the root ignores.
+///
+/// Symlinks to directories are skipped: there is nothing to copy.
+fn collect_files(root: &Path, home: &Path, state_dir: &Path, dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(home.join(dir)) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let p = dir.join(entry.file_name());
+
+        // Don't adopt the root, or the state, if they are in there.
+        if is_inside(&path, root) || is_inside(&path, state_dir) {
+            continue;
+        }
+
+        let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+        if (path.is_dir() && !is_dir) || walk::is_ignored(root, &p, is_dir) {
+            continue;
+        }
+
+        if is_dir {
+            collect_files(root, home, state_dir, &p, files);
+        } else {
+            files.push(p);
+        }
+    }
+}
+
+/// Copy a single file from home into the root, and link it back in
+/// link mode.
+///
+/// The copy is journaled as created, so `undo` removes it from the root.
+/// In link mode, the home file is backed up and journaled first, so
+/// `undo` puts it back.
+fn add_file(
+    p: &Path,
+    source: &Path,
+    destination: &Path,
+    link: bool,
+    backup: &state::Backup,
+) -> Result<(), String> {
+    check_change(backup, source)?;
+    if link {
+        check_change(backup, destination)?;
+    }
+
+    if let Err(err) = fs::create_dir_all(
+        source
+            .parent()
+            .expect("at the bare minimum, `parent` is the root"),
+    ) {
+        return Err(format!(
+            "{error}: Could not add '{}' to root: {err}",
+            p.display(),
+            error = ui::Color::error("error"),
+        ));
+    }
+
+    // Like `rsync`, this follows symlinks: the root gets the content.
+    if let Err(err) = utils::copy_atomically(destination, source) {
+        return Err(format!(
+            "{error}: Could not copy '{}' from home: {err}",
+            p.display(),
+            error = ui::Color::error("error"),
+        ));
+    }
+    record_change(backup, Change::Created, source, None)?;
+
+    if !link {
+        return Ok(());
+    }
+
+    let backup_path = backup_file(backup, destination)?;
+
+    if let Err(err) = utils::symlink_atomically(source, destination) {
+        return Err(format!(
+            "{error}: Could not create link to '{}': {err}",
+            source.display(),
+            error = ui::Color::error("error"),
+        ));
+    }
+
+    record_change(backup, Change::Replaced, destination, Some(&backup_path))
+}
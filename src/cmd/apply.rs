@@ -0,0 +1,327 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use deezconfigs::pathspec::PathSpec;
+use deezconfigs::state::manifest;
+use deezconfigs::templates::Templates;
+use deezconfigs::{state, ui, utils, walk};
+
+use super::common::{
+    backup_file, check_change, get_home_directory, get_state_directory, load_targets,
+    load_templates, local_root_id, record_change, resolve_and_pull_config_root,
+    resolve_config_root, update_manifest,
+};
+use super::diff::{Hunks, color_diff};
+use super::undo::format_errors;
+
+/// Apply changes between root and home, hunk by hunk.
+///
+/// 1. Collect all files in `configs`.
+/// 2. Diff with files in `$HOME`.
+/// 3. Apply the hunks to the home (or to the root, if `reversed`).
+///
+/// In patch mode, the user picks the hunks to apply in step 3.
+/// Otherwise, all of them are applied. Files that get changed are
+/// backed up first.
+///
+/// In dry-run mode, the hunks are only printed, and none is asked
+/// about.
+pub fn apply(
+    root: Option<&String>,
+    verbose: bool,
+    pull_before_command: bool,
+    reversed: bool,
+    patch: bool,
+    dry_run: bool,
+    pathspec: &PathSpec,
+) -> Result<(), i32> {
+    let root = if pull_before_command {
+        resolve_and_pull_config_root(root)?
+    } else {
+        resolve_config_root(root, true)?
+    };
+    let home = get_home_directory()?;
+    let root_id = local_root_id(&root);
+    let targets = load_targets(&root)?;
+    let templates = load_templates(&root)?;
+
+    let backup = state::Backup::new(&get_state_directory(&home), "apply", Some(&root));
+
+    let mut files = Vec::new();
+    let mut deployed = Vec::new();
+    let mut nb_hunks_applied = 0;
+    let mut nb_errors = 0;
+    let mut is_quit = false;
+
+    // Hunks are picked one at a time, so files are too.
+    for p in walk::collect_files_recursively(&root, pathspec) {
+        let source = match templates.source(&root, &p) {
+            Ok(source) => source,
+            Err(err) => {
+                nb_errors += 1;
+                eprintln!("{error}: {err}.", error = ui::Color::error("error"));
+                continue;
+            }
+        };
+        let destination = targets.destination(&home, &p);
+
+        // Changes flow from `source` to `destination` (from root to
+        // home), or the other way around if reversed.
+        let (from, to) = if reversed {
+            (&destination, &source)
+        } else {
+            (&source, &destination)
+        };
+
+        // Nothing to take changes from.
+        if !from.is_file() {
+            continue;
+        }
+
+        let texts = read_text(from).and_then(|from| Ok((from, read_text(to)?)));
+        let (from_text, to_text) = match texts {
+            Ok((Some(from_text), Some(to_text))) => (from_text, to_text),
+            // Hunks only make sense for text, there's nothing to pick
+            // from in other files (and they're likely the same anyway).
+            Ok(_) => {
+                if !utils::are_files_equal(from, to).unwrap_or(false) {
+                    eprintln!(
+                        "{warning}: Skipping '{}': It is not a text file.",
+                        p.display(),
+                        warning = ui::Color::warning("warning"),
+                    );
+                }
+                continue;
+            }
+            Err(err) => {
+                nb_errors += 1;
+                eprintln!(
+                    "{error}: Could not compare '{}': {err}.",
+                    p.display(),
+                    error = ui::Color::error("error"),
+                );
+                continue;
+            }
+        };
+
+        let hunks = Hunks::new(&to_text, &from_text);
+        if hunks.is_empty() {
+            continue;
+        }
+
+        // The rendered file is thrown away, the template is what to edit.
+        if reversed && Templates::is_template(&p) {
+            eprintln!(
+                "{warning}: Skipping '{}': It is a template, edit it in the root.",
+                p.display(),
+                warning = ui::Color::warning("warning"),
+            );
+            continue;
+        }
+
+        // Writing through a symlink would change its target instead.
+        if from.is_symlink() || to.is_symlink() {
+            eprintln!(
+                "{warning}: Skipping '{}': Symlinks can't be patched.",
+                p.display(),
+                warning = ui::Color::warning("warning"),
+            );
+            continue;
+        }
+
+        if dry_run {
+            println!("{}", ui::Color::file_name(&p.to_string_lossy()));
+            for i in 0..hunks.len() {
+                println!("{}", color_diff(&hunks.format(i)));
+            }
+            nb_hunks_applied += hunks.len();
+            files.push(p.to_string_lossy().to_string());
+            continue;
+        }
+
+        let accepted = if patch {
+            let (accepted, is_done) = pick_hunks(&p, &hunks, reversed);
+            is_quit = is_done;
+            accepted
+        } else {
+            vec![true; hunks.len()]
+        };
+
+        let nb_accepted = accepted.iter().filter(|&&is_accepted| is_accepted).count();
+        if nb_accepted > 0 {
+            let text = hunks.apply(&accepted);
+            if let Err(err) = write_file_with_backup(&p, from, to, &text, &backup) {
+                nb_errors += 1;
+                eprintln!("{err}");
+            } else {
+                nb_hunks_applied += nb_accepted;
+                files.push(p.to_string_lossy().to_string());
+
+                // The root's version is the new base, whichever way the
+                // hunks went: the hunks left out show up as changes made
+                // in the home, not as conflicts.
+                if let Ok(entry) =
+                    manifest::Entry::new(manifest::Kind::Copy, &source, &p, &destination)
+                {
+                    deployed.push(entry);
+                }
+            }
+        }
+
+        if is_quit {
+            break;
+        }
+    }
+
+    if dry_run {
+        println!(
+            "Would apply {nb_hunks_applied} hunk{} to {} file{}{} (dry run).",
+            if nb_hunks_applied == 1 { "" } else { "s" },
+            files.len(),
+            if files.len() == 1 { "" } else { "s" },
+            format_errors(nb_errors),
+        );
+        return if nb_errors > 0 { Err(1) } else { Ok(()) };
+    }
+
+    if !deployed.is_empty()
+        && let Err(err) = update_manifest(&home, &root_id, |manifest| {
+            for entry in deployed {
+                manifest.insert(entry);
+            }
+        })
+    {
+        nb_errors += 1;
+        eprintln!("{err}");
+    }
+
+    if verbose {
+        ui::print_files(&files);
+    }
+
+    if verbose
+        && backup.nb_files() > 0
+        && let Some(backup_dir) = backup.directory()
+    {
+        ui::print_backup_summary(backup_dir, backup.nb_files());
+    }
+
+    println!(
+        "Applied {nb_hunks_applied} hunk{} to {} file{}{}.",
+        if nb_hunks_applied == 1 { "" } else { "s" },
+        files.len(),
+        if files.len() == 1 { "" } else { "s" },
+        format_errors(nb_errors),
+    );
+
+    if nb_errors > 0 { Err(1) } else { Ok(()) }
+}
+
+/// Read a file as text; a missing file is empty.
+///
+/// Files that are not text (not UTF-8, or with NUL bytes in them) are
+/// `None`.
+fn read_text(path: &Path) -> io::Result<Option<String>> {
+    let bytes = match fs::read(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Some(String::new())),
+        res => res?,
+    };
+    if bytes.contains(&0) {
+        return Ok(None);
+    }
+    Ok(String::from_utf8(bytes).ok())
+}
+
+/// Ask the user which hunks of `p` to apply, `git add -p`-style.
+///
+/// Returns the hunks to apply, and whether the user quit (in which case
+/// the remaining files must not be asked about).
+fn pick_hunks(p: &Path, hunks: &Hunks, reversed: bool) -> (Vec<bool>, bool) {
+    let target = if reversed { "root" } else { "home" };
+
+    let mut accepted = vec![false; hunks.len()];
+    let mut i = 0;
+
+    println!("{}", ui::Color::file_name(&p.to_string_lossy()));
+
+    while i < hunks.len() {
+        println!("{}", color_diff(&hunks.format(i)));
+
+        let prompt = format!("({}/{}) Apply this hunk to {target}?", i + 1, hunks.len());
+        match ui::ask_choice_with_prompt(&prompt, &['y', 'n', 'a', 'd', 'q', '?']) {
+            Some('y') => accepted[i] = true,
+            Some('n') => (),
+            Some('a') => {
+                accepted[i..].fill(true);
+                break;
+            }
+            Some('d') => break,
+            Some('?') => {
+                println!("y - apply this hunk");
+                println!("n - do not apply this hunk");
+                println!("a - apply this hunk, and all later hunks in the file");
+                println!("d - do not apply this hunk, nor any later hunk in the file");
+                println!("q - quit; do not apply this hunk, nor any of the remaining ones");
+                println!("? - print help");
+                continue;
+            }
+            // `q`, or no more input.
+            _ => return (accepted, true),
+        }
+        i += 1;
+    }
+
+    (accepted, false)
+}
+
+/// Write the patched version of a file, backing up what it replaces.
+///
+/// The file is replaced atomically, and keeps its permissions. Files
+/// that get created take the permissions of `origin`, the file the
+/// hunks come from.
+///
+/// If the backup fails, the file is left untouched.
+fn write_file_with_backup(
+    p: &Path,
+    origin: &Path,
+    file: &Path,
+    text: &str,
+    backup: &state::Backup,
+) -> Result<(), String> {
+    check_change(backup, file)?;
+    let backup_path = if file.is_file() {
+        Some(backup_file(backup, file)?)
+    } else {
+        None
+    };
+
+    let write = || -> io::Result<()> {
+        fs::create_dir_all(
+            file.parent()
+                .expect("at the bare minimum, `parent` is the root or the home"),
+        )?;
+        let permissions = match fs::metadata(file) {
+            Ok(metadata) => metadata.permissions(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                fs::metadata(origin)?.permissions()
+            }
+            Err(err) => return Err(err),
+        };
+        utils::write_atomically(file, text.as_bytes(), permissions)
+    };
+    if let Err(err) = write() {
+        return Err(format!(
+            "{error}: Could not write '{}': {err}",
+            p.display(),
+            error = ui::Color::error("error"),
+        ));
+    }
+
+    let change = if backup_path.is_some() {
+        state::Change::Replaced
+    } else {
+        state::Change::Created
+    };
+    record_change(backup, change, file, backup_path.as_deref())
+}
@@ -1,23 +1,36 @@
+use std::collections::HashMap;
 use std::fs;
+use std::path::Path;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, PoisonError};
 
 use deezconfigs::pathspec::PathSpec;
-use deezconfigs::{ui, walk};
+use deezconfigs::{state, ui};
 
 use super::common::{
-    get_config_root_from_git, get_home_directory, get_hooks_for_command, is_git_remote_uri,
-    resolve_and_pull_config_root, resolve_config_root, run_hooks,
+    find_folded_dir, get_config_root_from_git, get_home_directory, get_hooks_for_command,
+    get_state_directory, is_dir_empty, is_git_remote_uri, load_targets, remove_file_with_backup,
+    resolve_and_pull_config_root, resolve_config_root, run_hooks, update_manifest,
 };
+use super::interactive::{Decision, Prompter};
 
 /// Remove config files from home.
 ///
 /// 1. Collect all files in `configs`.
 /// 2. Remove matching files in `$HOME`.
+///
+/// Removed files are backed up first.
+///
+/// In interactive mode, the user is asked before each file in step 2.
+///
+/// In dry-run mode, step 2. only reports what would be done.
+#[allow(clippy::too_many_lines)] // More a procedure than a function.
 pub fn clean(
     root: Option<&String>,
     verbose: bool,
     pull_before_command: bool,
+    dry_run: bool,
+    interactive: bool,
     pathspec: &PathSpec,
 ) -> Result<(), i32> {
     let root = if pull_before_command {
@@ -25,10 +38,21 @@ pub fn clean(
     } else if is_git_remote_uri(root) {
         get_config_root_from_git(root.expect("not empty, contains a `git:` prefix"), verbose)?
     } else {
+        // Even a dry-run runs hooks, which must come from a root the
+        // user trusts.
         resolve_config_root(root, true)?.into()
     };
+    // Remote roots can point to a single file too.
+    let narrowed = root.narrow_pathspec(pathspec)?;
+    let pathspec = narrowed.as_ref().unwrap_or(pathspec);
     let home = get_home_directory()?;
-    let hooks = get_hooks_for_command(&root, &home, verbose)?;
+    let targets = load_targets(root.as_ref())?;
+    let mut hooks = get_hooks_for_command(&root, &home, verbose)?;
+    if dry_run {
+        hooks.set_env_var("DEEZ_DRY_RUN", "true");
+    }
+
+    let backup = state::Backup::new(&get_state_directory(&home), "clean", Some(root.as_ref()));
 
     let mut nb_hooks_ran = 0;
 
@@ -38,66 +62,84 @@ pub fn clean(
     // given there are rarely _that_ many config files (and the syscalls
     // we issue are a bigger bottleneck anyway).
     let files = Arc::new(Mutex::new(Vec::with_capacity(20)));
+    let cleaned = Mutex::new(Vec::with_capacity(20));
+    // Folded directories are removed once, with their first file. The
+    // others follow suit (i.e., they are skipped if it was).
+    let unlinked_dirs = Mutex::new(HashMap::new());
     let nb_files_removed = AtomicUsize::new(0);
     let nb_errors = AtomicUsize::new(0);
 
-    walk::find_files_recursively(&root, pathspec, |p| {
+    // Nothing to ask in a dry-run, nothing gets done.
+    let prompter = Prompter::new(interactive && !dry_run);
+
+    prompter.walk(&root, pathspec, |p| {
         debug_assert!(!p.is_dir());
 
-        let destination = home.join(p);
+        let mut file = p.to_path_buf();
+        let mut destination = targets.destination(&home, p);
 
-        if destination.is_dir() {
-            // If destination exists and is a directory, try to `rmdir`
-            // it. If it works, the directory was empty anyway. If it
-            // doesn't work, the directory is not empty so we abort
-            // because it is too risky to remove an entire tree.
-            if let Err(err) = fs::remove_dir(&destination) {
-                nb_errors.fetch_add(1, Ordering::Relaxed);
-                eprintln!(
-                    "{error}: Could not remove exising directory '{}': {err}",
-                    destination.display(),
-                    error = ui::Color::error("error"),
-                );
+        // Removing files through a folded directory would remove them
+        // from the root: the directory's link is removed instead.
+        let mut unlinking = None;
+        if let Some(dir) = find_folded_dir(root.as_ref(), &home, &targets, p) {
+            // Held until the directory is removed, for the others to
+            // know how it went.
+            let mut unlinked_dirs = unlinked_dirs.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(&is_removed) = unlinked_dirs.get(&dir) {
+                if is_removed {
+                    if !dry_run && let Ok(mut cleaned) = cleaned.lock() {
+                        cleaned.push(p.to_path_buf());
+                    }
+                    nb_files_removed.fetch_add(1, Ordering::Relaxed);
+                }
                 return;
             }
+            unlinked_dirs.insert(dir.clone(), false);
+            destination = targets.destination(&home, &dir);
+            file.clone_from(&dir);
+            unlinking = Some((unlinked_dirs, dir));
         }
 
-        // Matches both files and symlinks.
-        if destination.is_file() {
-            if let Err(err) = fs::remove_file(&destination) {
+        let operation = match plan_clean(&destination) {
+            Ok(operation) => operation,
+            Err(err) => {
                 nb_errors.fetch_add(1, Ordering::Relaxed);
-                eprintln!(
-                    "{error}: Could not remove file '{}': {err}",
-                    destination.display(),
-                    error = ui::Color::error("error"),
-                );
+                eprintln!("{err}");
                 return;
             }
+        };
 
-            // Remove all parent dirs until not empty or home.
-            #[allow(clippy::items_after_statements)]
-            const DEPTH_LIMIT: usize = 20;
-            // `skip()` self (file).
-            for (i, dir) in destination.ancestors().skip(1).enumerate() {
-                // Don't remove home or above.
-                if dir == home {
-                    break;
-                }
-                // Basically, remove until it fails, since it fails if
-                // `dir` is not empty.
-                if fs::remove_dir(dir).is_err() {
-                    break;
-                }
-                if i == DEPTH_LIMIT {
-                    break;
-                }
-            }
+        if operation != ui::Operation::NoOp
+            && let Decision::Skip = prompter.ask(&file, operation, &destination, None)
+        {
+            return;
         }
 
-        if verbose {
-            let file = p.to_string_lossy().to_string();
+        if !dry_run
+            && operation != ui::Operation::NoOp
+            && let Err(err) = clean_file(&home, &destination, &backup)
+        {
+            nb_errors.fetch_add(1, Ordering::Relaxed);
+            eprintln!("{err}");
+            return;
+        }
+
+        if let Some((mut unlinked_dirs, dir)) = unlinking {
+            unlinked_dirs.insert(dir, true);
+        }
+
+        if !dry_run && let Ok(mut cleaned) = cleaned.lock() {
+            cleaned.push(p.to_path_buf());
+        }
+
+        if verbose || dry_run {
+            let file = if file == p {
+                p.to_string_lossy().to_string()
+            } else {
+                format!("{}/", file.display())
+            };
             if let Ok(mut files) = files.lock() {
-                files.push(file);
+                files.push((file, operation));
                 // Release the lock ASAP.
                 drop(files);
             } else {
@@ -110,6 +152,20 @@ pub fn clean(
         nb_files_removed.fetch_add(1, Ordering::Relaxed);
     });
 
+    // The files are gone from the home, and so is any trace of them
+    // having been deployed.
+    if !dry_run {
+        let cleaned = cleaned.into_inner().unwrap_or_default();
+        if let Err(err) = update_manifest(&home, &root.id(), |manifest| {
+            for file in &cleaned {
+                manifest.remove(file);
+            }
+        }) {
+            nb_errors.fetch_add(1, Ordering::Relaxed);
+            eprintln!("{err}");
+        }
+    }
+
     let mut files = Arc::try_unwrap(files)
         .expect("processing is over, we're back to a single thread.")
         .into_inner()
@@ -119,20 +175,96 @@ pub fn clean(
     // as per the docs.
     files.sort();
 
-    ui::print_files(&files);
+    if dry_run {
+        ui::print_operations(&files);
+    } else {
+        ui::print_files(&files.into_iter().map(|(file, _)| file).collect::<Vec<_>>());
+    }
+
+    if verbose
+        && backup.nb_files() > 0
+        && let Some(backup_dir) = backup.directory()
+    {
+        ui::print_backup_summary(backup_dir, backup.nb_files());
+    }
 
     nb_hooks_ran += run_hooks(|| hooks.post_clean())?;
 
     let nb_files_removed = nb_files_removed.into_inner();
     let nb_errors = nb_errors.into_inner();
 
-    ui::print_summary(
-        ui::Action::Clean,
-        &root,
-        nb_files_removed,
-        nb_errors,
-        nb_hooks_ran,
-    );
+    if dry_run {
+        ui::print_dry_run_summary(
+            ui::Action::Clean,
+            &root,
+            nb_files_removed,
+            0,
+            nb_errors,
+            nb_hooks_ran,
+        );
+    } else {
+        ui::print_summary(
+            ui::Action::Clean,
+            &root,
+            nb_files_removed,
+            0,
+            nb_errors,
+            nb_hooks_ran,
+        );
+    }
 
     if nb_errors > 0 { Err(1) } else { Ok(()) }
 }
+
+/// Determine what cleaning `destination` will do.
+///
+/// This mirrors the branches of [`clean_file()`], without touching the
+/// file system.
+fn plan_clean(destination: &Path) -> Result<ui::Operation, String> {
+    if destination.is_dir() && !destination.is_symlink() {
+        if !is_dir_empty(destination) {
+            return Err(format!(
+                "{error}: Could not remove exising directory '{}': Directory not empty",
+                destination.display(),
+                error = ui::Color::error("error"),
+            ));
+        }
+        return Ok(ui::Operation::RemoveDir);
+    }
+
+    // Matches both files and symlinks (even broken ones).
+    if destination.is_file() || destination.is_symlink() {
+        return Ok(ui::Operation::Delete);
+    }
+
+    Ok(ui::Operation::NoOp)
+}
+
+/// Remove a single file from home, and the directories it leaves empty.
+///
+/// Files are always backed up and journaled before being removed, even
+/// if the root has the same content: the root may be gone by the time
+/// they need restoring (see [`remove_file_with_backup()`]).
+fn clean_file(home: &Path, destination: &Path, backup: &state::Backup) -> Result<(), String> {
+    if destination.is_dir() && !destination.is_symlink() {
+        // If destination exists and is a directory, try to `rmdir`
+        // it. If it works, the directory was empty anyway. If it
+        // doesn't work, the directory is not empty so we abort
+        // because it is too risky to remove an entire tree.
+        if let Err(err) = fs::remove_dir(destination) {
+            return Err(format!(
+                "{error}: Could not remove exising directory '{}': {err}",
+                destination.display(),
+                error = ui::Color::error("error"),
+            ));
+        }
+        return Ok(());
+    }
+
+    // Matches both files and symlinks (even broken ones).
+    if destination.is_file() || destination.is_symlink() {
+        remove_file_with_backup(home, destination, backup)?;
+    }
+
+    Ok(())
+}
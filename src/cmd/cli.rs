@@ -1,11 +1,21 @@
+use super::common::OnConflict;
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum Command {
     Sync,
     RSync,
     Link,
+    Relink,
+    Unlink,
+    Deploy,
+    Add,
+    Forget,
     Status,
     Diff,
+    Apply,
     Clean,
+    Undo,
+    Restore,
     Run,
     Nuts,
 }
@@ -15,11 +25,26 @@ pub enum Command {
 pub struct Args {
     pub command: Option<Command>,
     pub pull_before_command: bool,
+    pub dry_run: bool,
+    pub prune: bool,
+    pub atomic: bool,
+    pub relative: bool,
+    pub link: bool,
+    pub on_conflict: OnConflict,
+    pub interactive: bool,
+    pub patch: bool,
     pub reversed_diff: bool,
     pub incoming_diff: bool,
+    pub rev: Option<String>,
+    pub run_id: Option<String>,
+    pub old_root: Option<String>,
     #[allow(clippy::struct_field_names)]
     pub run_args: Vec<String>,
     pub root: Option<String>,
+    /// Roots layered over `root`, by increasing precedence.
+    pub layers: Vec<String>,
+    pub home: Option<String>,
+    pub paths: Vec<String>,
     pub pathspecs: Vec<String>,
     pub short_help: bool,
     pub long_help: bool,
@@ -39,16 +64,76 @@ impl Args {
             let some_root = args.root.is_some();
 
             let is_diff = args.command == Some(Command::Diff);
+            let is_status_or_diff = matches!(args.command, Some(Command::Status | Command::Diff));
+            let is_apply = args.command == Some(Command::Apply);
+            let is_link = args.command == Some(Command::Link);
+            let is_sync_or_link = matches!(args.command, Some(Command::Sync | Command::Link));
+            let is_deploy = args.command == Some(Command::Deploy);
+            let is_sync_or_rsync = matches!(args.command, Some(Command::Sync | Command::RSync));
+            let is_layered = matches!(
+                args.command,
+                Some(Command::Sync | Command::RSync | Command::Status | Command::Diff)
+            );
+            let is_sync_rsync_or_clean = matches!(
+                args.command,
+                Some(Command::Sync | Command::RSync | Command::Clean)
+            );
+            let is_restore = args.command == Some(Command::Restore);
+            let is_relink = args.command == Some(Command::Relink);
+            let is_add = args.command == Some(Command::Add);
+            let is_forget = args.command == Some(Command::Forget);
 
             match arg.as_ref() {
                 "sync" | "s" if !some_command => args.command = Some(Command::Sync),
+                "--prune" if is_sync_or_link || is_deploy => args.prune = true,
+                "--atomic" if is_sync_or_link || is_deploy => args.atomic = true,
+                "--force" | "--ours" | "--theirs" if is_sync_or_rsync => {
+                    let on_conflict = match arg.as_ref() {
+                        "--force" => OnConflict::Force,
+                        "--ours" => OnConflict::Ours,
+                        _ => OnConflict::Theirs,
+                    };
+                    if args.on_conflict != OnConflict::Refuse && args.on_conflict != on_conflict {
+                        return Err(String::from(
+                            "Only one of '--force', '--ours' and '--theirs' can be used",
+                        ));
+                    }
+                    args.on_conflict = on_conflict;
+                }
+                "-i" | "--interactive" if is_sync_rsync_or_clean => args.interactive = true,
                 "rsync" | "rs" if !some_command => args.command = Some(Command::RSync),
                 "link" | "l" if !some_command => args.command = Some(Command::Link),
+                "--relative" if is_link => args.relative = true,
+                "relink" if !some_command => args.command = Some(Command::Relink),
+                "unlink" if !some_command => args.command = Some(Command::Unlink),
+                "deploy" if !some_command => args.command = Some(Command::Deploy),
+                "add" if !some_command => args.command = Some(Command::Add),
+                "--link" if is_add => args.link = true,
+                "forget" if !some_command => args.command = Some(Command::Forget),
                 "status" | "st" if !some_command => args.command = Some(Command::Status),
                 "diff" | "df" if !some_command => args.command = Some(Command::Diff),
-                "-r" | "--reversed" if is_diff => args.reversed_diff = !args.reversed_diff,
+                "-r" | "--reversed" if is_diff || is_apply => {
+                    args.reversed_diff = !args.reversed_diff;
+                }
                 "-i" | "--incoming" if is_diff => args.incoming_diff = !args.incoming_diff,
+                "--rev" if is_status_or_diff => {
+                    let Some(rev) = cli_args.next() else {
+                        return Err(String::from("Missing revision after '--rev'"));
+                    };
+                    args.rev = Some(rev.to_string());
+                }
+                "apply" if !some_command => args.command = Some(Command::Apply),
+                // Shadows `--pull`'s short form, like `git add -p`.
+                "-p" | "--patch" if is_apply => args.patch = true,
                 "clean" | "c" if !some_command => args.command = Some(Command::Clean),
+                "undo" if !some_command => args.command = Some(Command::Undo),
+                "restore" if !some_command => args.command = Some(Command::Restore),
+                "--run" if is_restore => {
+                    let Some(run_id) = cli_args.next() else {
+                        return Err(String::from("Missing run id after '--run'"));
+                    };
+                    args.run_id = Some(run_id.to_string());
+                }
                 "run" | "r" if !some_command => {
                     args.command = Some(Command::Run);
                     args.run_args
@@ -60,19 +145,49 @@ impl Args {
                 "-V" | "--version" => args.version = true,
                 "-v" | "--verbose" => args.verbose = true,
                 "-p" | "--pull" => args.pull_before_command = true,
+                "-n" | "--dry-run" => args.dry_run = true,
+                "--home" => {
+                    let Some(home) = cli_args.next() else {
+                        return Err(String::from("Missing directory after '--home'"));
+                    };
+                    args.home = Some(home.to_string());
+                }
                 "--" if some_command => {
                     // Everything after `--` is a pathspec (git-style).
                     // Root is positional and must come _before_ `--`.
                     args.pathspecs
                         .extend(cli_args.by_ref().map(|arg| arg.to_string()));
                 }
-                root if some_command && !some_root => args.root = Some(root.to_string()),
+                path if is_restore || is_add || is_forget => args.paths.push(path.to_string()),
+                old_root if is_relink && args.old_root.is_none() => {
+                    args.old_root = Some(old_root.to_string());
+                }
+                // `relink` is always run from the (current) root.
+                root if some_command && !some_root && !is_relink => {
+                    args.root = Some(root.to_string());
+                }
+                // Options are not layers, they'd better be reported.
+                layer if is_layered && some_root && !layer.starts_with('-') => {
+                    args.layers.push(layer.to_string());
+                }
                 unknown => {
                     return Err(format!("Unknown argument: '{unknown}'"));
                 }
             }
         }
 
+        if args.incoming_diff && !args.layers.is_empty() {
+            return Err(String::from("'--incoming' only works with a single root"));
+        }
+        if args.rev.is_some() && !args.layers.is_empty() {
+            return Err(String::from("'--rev' only works with a single root"));
+        }
+        if args.rev.is_some() && args.incoming_diff {
+            return Err(String::from(
+                "'--rev' and '--incoming' cannot be used together",
+            ));
+        }
+
         Ok(args)
     }
 }
@@ -117,6 +232,25 @@ mod tests {
         assert!(args.pull_before_command);
     }
 
+    #[test]
+    fn option_home_is_global() {
+        let args =
+            Args::build_from_args(["--home", "/tmp/staging", "sync", "~/configs"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Sync));
+        assert_eq!(args.home, Some(String::from("/tmp/staging")));
+        assert_eq!(args.root, Some(String::from("~/configs")));
+
+        let args = Args::build_from_args(["status", "--home", "/tmp/staging"].iter()).unwrap();
+        assert_eq!(args.home, Some(String::from("/tmp/staging")));
+        assert_eq!(args.root, None);
+    }
+
+    #[test]
+    fn option_home_missing_directory_is_error() {
+        let err = Args::build_from_args(["sync", "--home"].iter()).unwrap_err();
+        assert!(err.contains("'--home'"));
+    }
+
     #[test]
     fn option_pull_shortcut() {
         let args = Args::build_from_args(["-p", "sync"].iter()).unwrap();
@@ -152,6 +286,307 @@ mod tests {
         assert!(args.run_args.is_empty());
     }
 
+    #[test]
+    fn option_dry_run_regular() {
+        let args = Args::build_from_args(["sync", "--dry-run"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Sync));
+        assert!(args.dry_run);
+    }
+
+    #[test]
+    fn option_dry_run_shortcut() {
+        let args = Args::build_from_args(["-n", "clean"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Clean));
+        assert!(args.dry_run);
+    }
+
+    #[test]
+    fn option_dry_run_default_off() {
+        let args = Args::build_from_args(["sync"].iter()).unwrap();
+        assert!(!args.dry_run);
+    }
+
+    #[test]
+    fn option_prune_sync() {
+        let args = Args::build_from_args(["sync", "--prune"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Sync));
+        assert!(args.prune);
+    }
+
+    #[test]
+    fn option_prune_link() {
+        let args = Args::build_from_args(["link", "--prune"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Link));
+        assert!(args.prune);
+    }
+
+    #[test]
+    fn option_prune_is_sync_and_link_only() {
+        let err = Args::build_from_args(["clean", "~/configs", "--prune"].iter()).unwrap_err();
+        assert!(err.contains("'--prune'"));
+    }
+
+    #[test]
+    fn option_atomic_sync() {
+        let args = Args::build_from_args(["sync", "--atomic"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Sync));
+        assert!(args.atomic);
+    }
+
+    #[test]
+    fn option_atomic_link() {
+        let args = Args::build_from_args(["link", "--atomic"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Link));
+        assert!(args.atomic);
+    }
+
+    #[test]
+    fn option_atomic_is_sync_and_link_only() {
+        let err = Args::build_from_args(["rsync", "~/configs", "--atomic"].iter()).unwrap_err();
+        assert!(err.contains("'--atomic'"));
+    }
+
+    #[test]
+    fn option_relative_link() {
+        let args = Args::build_from_args(["link", "--relative"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Link));
+        assert!(args.relative);
+    }
+
+    #[test]
+    fn option_relative_is_link_only() {
+        let err = Args::build_from_args(["sync", "~/configs", "--relative"].iter()).unwrap_err();
+        assert!(err.contains("'--relative'"));
+    }
+
+    #[test]
+    fn option_on_conflict_default_refuse() {
+        let args = Args::build_from_args(["sync"].iter()).unwrap();
+        assert_eq!(args.on_conflict, OnConflict::Refuse);
+    }
+
+    #[test]
+    fn option_force_sync() {
+        let args = Args::build_from_args(["sync", "--force"].iter()).unwrap();
+        assert_eq!(args.on_conflict, OnConflict::Force);
+    }
+
+    #[test]
+    fn option_ours_rsync() {
+        let args = Args::build_from_args(["rsync", "--ours"].iter()).unwrap();
+        assert_eq!(args.on_conflict, OnConflict::Ours);
+    }
+
+    #[test]
+    fn option_theirs_sync() {
+        let args = Args::build_from_args(["sync", "--theirs"].iter()).unwrap();
+        assert_eq!(args.on_conflict, OnConflict::Theirs);
+    }
+
+    #[test]
+    fn option_on_conflict_repeated_is_noop() {
+        let args = Args::build_from_args(["sync", "--ours", "--ours"].iter()).unwrap();
+        assert_eq!(args.on_conflict, OnConflict::Ours);
+    }
+
+    #[test]
+    fn option_on_conflict_mutually_exclusive() {
+        let err = Args::build_from_args(["sync", "--ours", "--theirs"].iter()).unwrap_err();
+        assert!(err.contains("'--ours'"));
+    }
+
+    #[test]
+    fn option_on_conflict_is_sync_and_rsync_only() {
+        let err = Args::build_from_args(["link", "~/configs", "--force"].iter()).unwrap_err();
+        assert!(err.contains("'--force'"));
+    }
+
+    #[test]
+    fn option_interactive_short() {
+        let args = Args::build_from_args(["sync", "-i"].iter()).unwrap();
+        assert!(args.interactive);
+    }
+
+    #[test]
+    fn option_interactive_long() {
+        let args = Args::build_from_args(["clean", "--interactive"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Clean));
+        assert!(args.interactive);
+    }
+
+    #[test]
+    fn option_interactive_rsync() {
+        let args = Args::build_from_args(["rsync", "-i"].iter()).unwrap();
+        assert!(args.interactive);
+    }
+
+    #[test]
+    fn option_interactive_is_sync_rsync_and_clean_only() {
+        let err = Args::build_from_args(["link", "~/configs", "--interactive"].iter()).unwrap_err();
+        assert!(err.contains("'--interactive'"));
+    }
+
+    #[test]
+    fn option_i_is_incoming_for_diff() {
+        let args = Args::build_from_args(["diff", "-i"].iter()).unwrap();
+        assert!(args.incoming_diff);
+        assert!(!args.interactive);
+    }
+
+    #[test]
+    fn command_apply_regular() {
+        let args = Args::build_from_args(["apply"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Apply));
+        assert!(!args.patch);
+        assert!(!args.reversed_diff);
+    }
+
+    #[test]
+    fn option_patch_short() {
+        let args = Args::build_from_args(["apply", "-p"].iter()).unwrap();
+        assert!(args.patch);
+        assert!(!args.pull_before_command);
+    }
+
+    #[test]
+    fn option_patch_long() {
+        let args = Args::build_from_args(["apply", "--patch"].iter()).unwrap();
+        assert!(args.patch);
+    }
+
+    #[test]
+    fn option_pull_with_apply() {
+        let args = Args::build_from_args(["-p", "apply", "--pull"].iter()).unwrap();
+        assert!(args.pull_before_command);
+        assert!(!args.patch);
+    }
+
+    #[test]
+    fn option_reversed_apply() {
+        let args = Args::build_from_args(["apply", "-p", "--reversed"].iter()).unwrap();
+        assert!(args.patch);
+        assert!(args.reversed_diff);
+    }
+
+    #[test]
+    fn option_patch_is_apply_only() {
+        let args = Args::build_from_args(["sync", "-p"].iter()).unwrap();
+        assert!(args.pull_before_command);
+        let err = Args::build_from_args(["sync", "~/configs", "--patch"].iter()).unwrap_err();
+        assert!(err.contains("'--patch'"));
+    }
+
+    #[test]
+    fn command_undo_regular() {
+        let args = Args::build_from_args(["undo"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Undo));
+    }
+
+    #[test]
+    fn command_relink_regular() {
+        let args = Args::build_from_args(["relink"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Relink));
+        assert_eq!(args.old_root, None);
+    }
+
+    #[test]
+    fn command_relink_old_root() {
+        let args = Args::build_from_args(["relink", "~/old-configs"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Relink));
+        assert_eq!(args.old_root, Some(String::from("~/old-configs")));
+        assert_eq!(args.root, None);
+    }
+
+    #[test]
+    fn command_relink_single_old_root() {
+        let err = Args::build_from_args(["relink", "~/old", "~/older"].iter()).unwrap_err();
+        assert!(err.contains("'~/older'"));
+    }
+
+    #[test]
+    fn command_unlink_regular() {
+        let args =
+            Args::build_from_args(["unlink", "~/configs", "--", ".config/fish"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Unlink));
+        assert_eq!(args.root, Some(String::from("~/configs")));
+        assert_eq!(args.pathspecs, vec![".config/fish"]);
+    }
+
+    #[test]
+    fn command_deploy_regular() {
+        let args = Args::build_from_args(["deploy", "~/configs", "--prune"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Deploy));
+        assert_eq!(args.root, Some(String::from("~/configs")));
+        assert!(args.prune);
+    }
+
+    #[test]
+    fn option_atomic_for_deploy() {
+        let args = Args::build_from_args(["deploy", "~/configs", "--atomic"].iter()).unwrap();
+        assert!(args.atomic);
+    }
+
+    #[test]
+    fn command_add_collects_paths() {
+        let args = Args::build_from_args(["add", "~/.gitconfig", "~/.config/fish"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Add));
+        assert_eq!(args.paths, vec!["~/.gitconfig", "~/.config/fish"]);
+        assert_eq!(args.root, None);
+        assert!(!args.link);
+    }
+
+    #[test]
+    fn option_link_add() {
+        let args = Args::build_from_args(["add", "--link", "~/.gitconfig"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Add));
+        assert_eq!(args.paths, vec!["~/.gitconfig"]);
+        assert!(args.link);
+    }
+
+    #[test]
+    fn option_link_is_add_only() {
+        let err = Args::build_from_args(["sync", "~/configs", "--link"].iter()).unwrap_err();
+        assert!(err.contains("'--link'"));
+    }
+
+    #[test]
+    fn command_forget_collects_paths_and_pathspecs() {
+        let args =
+            Args::build_from_args(["forget", "~/.gitconfig", "--", ".config/nvim"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Forget));
+        assert_eq!(args.paths, vec!["~/.gitconfig"]);
+        assert_eq!(args.pathspecs, vec![".config/nvim"]);
+        assert_eq!(args.root, None);
+    }
+
+    #[test]
+    fn command_restore_collects_paths() {
+        let args = Args::build_from_args(["restore", "~/.gitconfig", "~/.bashrc"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Restore));
+        assert_eq!(args.paths, ["~/.gitconfig", "~/.bashrc"]);
+        assert!(args.root.is_none());
+    }
+
+    #[test]
+    fn command_restore_run_id() {
+        let args = Args::build_from_args(["restore", "--run", "20250131-235959-999", "foo"].iter())
+            .unwrap();
+        assert_eq!(args.run_id.as_deref(), Some("20250131-235959-999"));
+        assert_eq!(args.paths, ["foo"]);
+    }
+
+    #[test]
+    fn command_restore_run_id_missing_is_error() {
+        let err = Args::build_from_args(["restore", "--run"].iter()).unwrap_err();
+        assert!(err.contains("'--run'"));
+    }
+
+    #[test]
+    fn option_run_is_restore_only() {
+        let err = Args::build_from_args(["sync", "~/configs", "--run", "foo"].iter()).unwrap_err();
+        assert!(err.contains("'--run'"));
+    }
+
     #[test]
     fn command_rsync_regular() {
         let args = Args::build_from_args(["rsync"].iter()).unwrap();
@@ -402,10 +837,59 @@ mod tests {
 
     #[test]
     fn root_with_previous_root_is_error() {
-        let err = Args::build_from_args(["sync", "~/other-root", "~/configs"].iter()).unwrap_err();
+        let err = Args::build_from_args(["link", "~/other-root", "~/configs"].iter()).unwrap_err();
         assert!(err.contains("'~/configs'"));
     }
 
+    #[test]
+    fn roots_after_root_are_layers() {
+        let args = Args::build_from_args(["sync", "base", "work", "laptop"].iter()).unwrap();
+        assert_eq!(args.root, Some(String::from("base")));
+        assert_eq!(args.layers, ["work", "laptop"]);
+    }
+
+    #[test]
+    fn unknown_option_after_root_is_not_a_layer() {
+        let err = Args::build_from_args(["status", "base", "--foo"].iter()).unwrap_err();
+        assert!(err.contains("'--foo'"));
+    }
+
+    #[test]
+    fn option_incoming_is_single_root_only() {
+        let err = Args::build_from_args(["diff", "-i", "base", "work"].iter()).unwrap_err();
+        assert!(err.contains("'--incoming'"));
+    }
+
+    #[test]
+    fn option_rev_for_status_and_diff() {
+        let args = Args::build_from_args(["status", "--rev", "v1.0"].iter()).unwrap();
+        assert_eq!(args.rev, Some(String::from("v1.0")));
+
+        let args =
+            Args::build_from_args(["diff", "~/configs", "--rev", "origin/main"].iter()).unwrap();
+        assert_eq!(args.root, Some(String::from("~/configs")));
+        assert_eq!(args.rev, Some(String::from("origin/main")));
+    }
+
+    #[test]
+    fn option_rev_missing_revision_is_error() {
+        let err = Args::build_from_args(["diff", "--rev"].iter()).unwrap_err();
+        assert!(err.contains("'--rev'"));
+    }
+
+    #[test]
+    fn option_rev_is_single_root_only() {
+        let err =
+            Args::build_from_args(["status", "--rev", "v1.0", "base", "work"].iter()).unwrap_err();
+        assert!(err.contains("'--rev'"));
+    }
+
+    #[test]
+    fn option_rev_and_incoming_are_exclusive() {
+        let err = Args::build_from_args(["diff", "-i", "--rev", "v1.0"].iter()).unwrap_err();
+        assert!(err.contains("'--rev' and '--incoming'"));
+    }
+
     #[test]
     fn root_before_double_dash_coexists_with_pathspecs() {
         // Root is positional (before `--`); pathspecs follow.
@@ -1,10 +1,19 @@
 use std::env;
 use std::fs;
-use std::path::{Component, Path, PathBuf};
+use std::path::{self, Component, Path, PathBuf};
 use std::process;
+use std::sync::OnceLock;
 
+use deezconfigs::attributes::Attributes;
 use deezconfigs::hooks::Hooks;
-use deezconfigs::ui;
+use deezconfigs::pathspec::PathSpec;
+use deezconfigs::state::manifest::{self, Divergence};
+use deezconfigs::state::{self, Backup, Change, Manifest};
+use deezconfigs::targets::Targets;
+use deezconfigs::templates::Templates;
+use deezconfigs::{ui, utils};
+
+use super::undo::roll_back;
 
 /// A resolved config root: a local path, or a temporary clone.
 pub enum ConfigRoot {
@@ -12,12 +21,17 @@ pub enum ConfigRoot {
     Temporary(TempClone),
 }
 
-/// A config root cloned from a remote; its clone is deleted on drop.
+/// A config root cloned from a remote (or extracted from a Git revision
+/// of a local root); its clone is deleted on drop.
+///
+/// The temp directory holds either a clone of a remote, or a checkout of
+/// a revision. `clone_dir` and `path` differ when the remote URL asks
+/// for a sub-root (e.g. `.../configs[nvim]`), and for revisions, which
+/// are checked out into a sub-directory.
 ///
-/// `clone_dir` and `path` differ only when the remote URL asks for a
-/// sub-root (e.g. `.../configs[nvim]`). `Drop` always removes
-/// `clone_dir`, the whole clone, so the temp directory is fully
-/// cleaned up even when commands run against a sub-directory of it.
+/// `Drop` always removes `clone_dir`, the whole clone, so the temp
+/// directory is fully cleaned up even when commands run against a
+/// sub-directory of it.
 ///
 /// The fields are private, so only this module can build a `TempClone`,
 /// which is the safety boundary: `Drop` can only ever delete a clone we
@@ -28,6 +42,11 @@ pub struct TempClone {
     clone_dir: PathBuf,
     /// What commands operate on: `clone_dir`, or a sub-root inside it.
     path: PathBuf,
+    /// Where the clone comes from (including the sub-root, if any).
+    uri: String,
+    /// The file the sub-root points to, if it is a file (relative to
+    /// `path`).
+    file: Option<PathBuf>,
 }
 
 impl From<PathBuf> for ConfigRoot {
@@ -45,6 +64,56 @@ impl AsRef<Path> for ConfigRoot {
     }
 }
 
+impl ConfigRoot {
+    /// Stable identifier of the root, to key its state (e.g., manifest).
+    ///
+    /// Local roots are identified by their canonical path. Temporary
+    /// clones live in a different directory every time, so they are
+    /// identified by their URI instead.
+    pub fn id(&self) -> String {
+        match self {
+            Self::Local(path) => local_root_id(path),
+            Self::Temporary(clone) => clone.uri.clone(),
+        }
+    }
+
+    /// Narrow `pathspec` down to the file the root points to, if any.
+    ///
+    /// Local roots that point to a file are split before the command
+    /// runs (see [`split_file_root()`]), but remote ones are only known
+    /// to be files once cloned.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the root points to a file, and `pathspec` is not
+    /// empty.
+    pub fn narrow_pathspec(&self, pathspec: &PathSpec) -> Result<Option<PathSpec>, i32> {
+        let Self::Temporary(TempClone {
+            file: Some(file), ..
+        }) = self
+        else {
+            return Ok(None);
+        };
+        if !pathspec.is_empty() {
+            eprintln!(
+                "{fatal}: Cannot use pathspecs with a root that is a file.",
+                fatal = ui::Color::error("fatal")
+            );
+            return Err(2);
+        }
+        Ok(Some(PathSpec::file(file)))
+    }
+}
+
+/// Stable identifier of a local root: its canonical path.
+pub fn local_root_id(root: &Path) -> String {
+    fs::canonicalize(root)
+        .or_else(|_| std::path::absolute(root))
+        .unwrap_or_else(|_| root.to_path_buf())
+        .display()
+        .to_string()
+}
+
 impl Drop for TempClone {
     fn drop(&mut self) {
         _ = fs::remove_dir_all(&self.clone_dir);
@@ -73,25 +142,7 @@ impl Drop for TempClone {
 /// _not_ a user-facing option. It is used internally by non-fs-altering
 /// commands that don't need it, such as `status` for instance.
 pub fn resolve_config_root(root: Option<&String>, do_check: bool) -> Result<PathBuf, i32> {
-    // Given.
-    let root = if let Some(root) = get_config_root_from_args(root) {
-        root
-    // Not given.
-    } else {
-        // Try current dir.
-        let mut default = get_default_config_root()?;
-        // If not, look inside parents.
-        if !is_a_config_root(&default) {
-            if let Some(parent) = find_config_root_in_parents(&default) {
-                default = parent.to_path_buf();
-            // If not, try `DEEZ_ROOT`.
-            } else if let Some(root) = get_config_root_from_config() {
-                default = root;
-            }
-            // Else, let current dir fail.
-        }
-        default
-    };
+    let root = find_config_root(root)?;
     ensure_root_exists(&root)?;
     if do_check {
         ensure_root_is_a_config_root(&root)?;
@@ -99,6 +150,31 @@ pub fn resolve_config_root(root: Option<&String>, do_check: bool) -> Result<Path
     Ok(root)
 }
 
+/// Find the config root to use, without checking it.
+///
+/// See [`resolve_config_root()`] for the heuristic.
+pub fn find_config_root(root: Option<&String>) -> Result<PathBuf, i32> {
+    // Given.
+    if let Some(root) = get_config_root_from_args(root) {
+        return Ok(root);
+    }
+    // Not given.
+
+    // Try current dir.
+    let mut default = get_default_config_root()?;
+    // If not, look inside parents.
+    if !is_a_config_root(&default) {
+        if let Some(parent) = find_config_root_in_parents(&default) {
+            default = parent.to_path_buf();
+        // If not, try `DEEZ_ROOT`.
+        } else if let Some(root) = get_config_root_from_config() {
+            default = root;
+        }
+        // Else, let current dir fail.
+    }
+    Ok(default)
+}
+
 fn get_config_root_from_args(root: Option<&String>) -> Option<PathBuf> {
     if let Some(root) = root
         && !root.is_empty()
@@ -147,6 +223,53 @@ fn find_config_root_in_parents(root: &Path) -> Option<&Path> {
     None
 }
 
+/// Split a root that points to a file into the config root that
+/// contains it, and the path of the file inside of it.
+///
+/// This lets commands run on a single file, as if given the enclosing
+/// root, and the file as pathspec. Other roots are returned as is.
+///
+/// # Errors
+///
+/// Errors if the file is not inside a config root, or if there are
+/// pathspecs too (they would make little sense on a single file).
+pub fn split_file_root(
+    root: Option<&String>,
+    has_pathspecs: bool,
+) -> Result<(Option<String>, Option<PathBuf>), i32> {
+    let Some(file) = root
+        .filter(|root| !is_git_remote_uri(Some(root)))
+        .map(PathBuf::from)
+        .filter(|root| root.is_file())
+    else {
+        return Ok((root.cloned(), None));
+    };
+
+    let file = path::absolute(&file).unwrap_or(file);
+    let Some(root) = find_config_root_in_parents(&file) else {
+        eprintln!(
+            "{fatal}: '{}' is not inside a config root.",
+            file.display(),
+            fatal = ui::Color::error("fatal")
+        );
+        return Err(1);
+    };
+
+    if has_pathspecs {
+        eprintln!(
+            "{fatal}: Cannot use pathspecs with a root that is a file.",
+            fatal = ui::Color::error("fatal")
+        );
+        return Err(2);
+    }
+
+    let path = file
+        .strip_prefix(root)
+        .expect("the root is a parent of the file")
+        .to_path_buf();
+    Ok((Some(root.display().to_string()), Some(path)))
+}
+
 pub fn ensure_root_exists(root: &Path) -> Result<(), i32> {
     if root.is_dir() {
         return Ok(());
@@ -206,7 +329,7 @@ Selected root: '{}'.
     Err(2)
 }
 
-fn is_a_config_root(root: &Path) -> bool {
+pub fn is_a_config_root(root: &Path) -> bool {
     root.join(".deez").is_file()
 }
 
@@ -243,6 +366,7 @@ pub fn get_config_root_from_git(uri: &str, verbose: bool) -> Result<ConfigRoot,
 
     // Extract potential sub root.
     // git@github.com/qrichert/configs[sub/root]
+    let full_uri = uri.clone();
     let (uri, sub_root) = extract_sub_root(&uri);
 
     // Yes, I know. Not a solid UUID, I should use a crate, etc.
@@ -282,6 +406,8 @@ The target directory already exists and could not be deleted.
     let mut root = ConfigRoot::Temporary(TempClone {
         clone_dir: clone_dir.clone(),
         path: clone_dir,
+        uri: full_uri,
+        file: None,
     });
 
     let status = if verbose {
@@ -326,12 +452,36 @@ installed on your machine.
         }
 
         // We just created it, can't be anything else.
-        let ConfigRoot::Temporary(TempClone { clone_dir, path }) = &mut root else {
+        let ConfigRoot::Temporary(TempClone {
+            clone_dir,
+            path,
+            file,
+            ..
+        }) = &mut root
+        else {
             unreachable!("`root` was just constructed as `Temporary`");
         };
 
         *path = clone_dir.join(sub_root);
 
+        // A single file: the root is the closest directory that
+        // contains it and a `.deez` file, or else the whole clone.
+        if path.is_file() {
+            let sub_root = path
+                .ancestors()
+                .skip(1)
+                .take_while(|dir| dir.starts_with(&*clone_dir))
+                .find(|dir| is_a_config_root(dir))
+                .unwrap_or(clone_dir)
+                .to_path_buf();
+            *file = Some(
+                path.strip_prefix(&sub_root)
+                    .expect("the sub-root contains the file")
+                    .to_path_buf(),
+            );
+            *path = sub_root;
+        }
+
         if !path.is_dir() {
             eprintln!(
                 "{fatal}: Cannot find sub-root inside Git repository: '{sub_root}'.",
@@ -344,6 +494,104 @@ installed on your machine.
     Ok(root)
 }
 
+/// Extract the files of a local root, as of Git revision `rev`.
+///
+/// The tree of the revision is checked out through a temporary index
+/// into the system's temporary directory (under the name
+/// `deez-<pid>-<uuid>`), so neither the working tree nor the index of
+/// the root are touched. If the root is a sub-directory of its
+/// repository, only that sub-directory is extracted.
+///
+/// # Errors
+///
+/// Errors if the root is a remote one, if the temporary directory
+/// cannot be written to, or with Git's own exit code if Git fails
+/// (e.g., the root is not a Git repository, or `rev` does not exist).
+pub fn get_config_root_at_revision(root: &ConfigRoot, rev: &str) -> Result<ConfigRoot, i32> {
+    let ConfigRoot::Local(root) = root else {
+        eprintln!(
+            "{fatal}: '--rev' only works with local config roots.",
+            fatal = ui::Color::error("fatal")
+        );
+        return Err(2);
+    };
+
+    // Where the repository is, and where the root is inside of it.
+    let output = process::Command::new("git")
+        .current_dir(root)
+        .args(["rev-parse", "--show-toplevel", "--show-prefix"])
+        .stderr(process::Stdio::inherit())
+        .output();
+    let (toplevel, prefix) = match output {
+        Ok(output) if output.status.success() => {
+            let output = String::from_utf8_lossy(&output.stdout);
+            let mut lines = output.lines();
+            (
+                PathBuf::from(lines.next().unwrap_or_default()),
+                lines.next().unwrap_or_default().to_string(),
+            )
+        }
+        // Git explains itself, on failure.
+        output => {
+            let status = output.map(|output| output.status);
+            return Err(propagate_git_status(status, "rev-parse").err().unwrap_or(1));
+        }
+    };
+
+    // Yes, same as remote clones. Not a solid UUID.
+    let pid = std::process::id();
+    let uuid = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("current time > Unix epoch")
+        .as_millis();
+    let clone_dir = env::temp_dir().join(format!("deez-{pid}-{uuid}"));
+
+    // Own the directory before running Git, so any early return below
+    // removes it on drop.
+    let revision = ConfigRoot::Temporary(TempClone {
+        clone_dir: clone_dir.clone(),
+        path: clone_dir.join("root"),
+        uri: format!("{}@{rev}", local_root_id(root)),
+        file: None,
+    });
+
+    if let Err(err) = fs::create_dir_all(clone_dir.join("root")) {
+        eprintln!(
+            "{fatal}: Could not extract revision '{rev}': {err}",
+            fatal = ui::Color::error("fatal")
+        );
+        return Err(1);
+    }
+
+    // A throwaway index, to leave the root's own alone. From the top
+    // level, or `checkout-index` would only extract the files under
+    // the current directory.
+    let git = || {
+        let mut command = process::Command::new("git");
+        command
+            .current_dir(&toplevel)
+            .env("GIT_INDEX_FILE", clone_dir.join("index"));
+        command
+    };
+
+    propagate_git_status(
+        git()
+            .arg("read-tree")
+            .arg(format!("{rev}:{prefix}"))
+            .status(),
+        "read-tree",
+    )?;
+    propagate_git_status(
+        git()
+            .args(["checkout-index", "--all"])
+            .arg(format!("--prefix={}/", clone_dir.join("root").display()))
+            .status(),
+        "checkout-index",
+    )?;
+
+    Ok(revision)
+}
+
 /// Resolve a local config root, ask for confirmation if it has no
 /// `.deez` marker, then run `git pull`. Remote roots are rejected.
 pub fn resolve_and_pull_config_root(root: Option<&String>) -> Result<PathBuf, i32> {
@@ -521,11 +769,60 @@ fn extract_sub_root(uri: &str) -> (&str, Option<&str>) {
     }
 }
 
+/// Home directory given with `--home`, if any.
+static HOME_DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use `home` as home directory, instead of the user's (see `--home`).
+///
+/// It takes precedence over `DEEZ_HOME`. Only the first call counts.
+pub fn set_home_directory(home: &str) {
+    _ = HOME_DIRECTORY.set(PathBuf::from(home));
+}
+
+/// Get the directory configs are deployed into.
+///
+/// This is the directory given with `--home`, or else `DEEZ_HOME`, or
+/// else the user's home directory. A home that is given must exist.
+pub fn get_home_directory() -> Result<PathBuf, i32> {
+    let Some(home) = HOME_DIRECTORY
+        .get()
+        .cloned()
+        .or_else(get_home_directory_from_config)
+    else {
+        return get_user_home_directory();
+    };
+
+    // Files get linked to, and recorded with, their absolute path.
+    let home = match path::absolute(&home) {
+        Ok(home) if home.is_dir() => home,
+        _ => {
+            eprintln!(
+                "{fatal}: Home directory '{}' does not exist.",
+                home.display(),
+                fatal = ui::Color::error("fatal")
+            );
+            return Err(1);
+        }
+    };
+
+    Ok(home)
+}
+
+fn get_home_directory_from_config() -> Option<PathBuf> {
+    if let Some(home) = env::var("DEEZ_HOME").ok()
+        && !home.is_empty()
+    {
+        Some(PathBuf::from(home))
+    } else {
+        None
+    }
+}
+
 /// Get the user's home directory.
 ///
 /// The home directory is read from `HOME` environment variable.
-pub fn get_home_directory() -> Result<PathBuf, i32> {
-    if let Some(home_directory) = std::env::home_dir() {
+fn get_user_home_directory() -> Result<PathBuf, i32> {
+    if let Some(home_directory) = env::home_dir() {
         Ok(home_directory)
     } else {
         eprintln!(
@@ -536,6 +833,597 @@ pub fn get_home_directory() -> Result<PathBuf, i32> {
     }
 }
 
+/// Get the state directory of `deez` (see [`state::state_directory()`]).
+///
+/// State belongs to the user, not to the home configs are deployed
+/// into: it stays in the user's home directory, even with `--home`.
+pub fn get_state_directory(home: &Path) -> PathBuf {
+    state::state_directory(&env::home_dir().unwrap_or_else(|| home.to_path_buf()))
+}
+
+/// Back up `destination` before it gets replaced by `source`.
+///
+/// Only files whose content or permissions differ from `source` are
+/// backed up; the others can be restored from the root anyway. Symlinks
+/// are compared by the content (and permissions) of their targets, and
+/// broken ones always differ.
+///
+/// Returns the path of the backup, if one was made.
+pub fn backup_if_different(
+    backup: &Backup,
+    source: &Path,
+    destination: &Path,
+) -> Result<Option<PathBuf>, String> {
+    if let (Ok(true), Ok(true)) = (
+        utils::are_files_equal(source, destination),
+        utils::are_permissions_equal(source, destination),
+    ) {
+        return Ok(None);
+    }
+    backup_file(backup, destination).map(Some)
+}
+
+/// Give `destination` the permissions of `source`.
+///
+/// `p` is the path of the file relative to the root; it is only used
+/// in error messages.
+pub fn copy_permissions(p: &Path, source: &Path, destination: &Path) -> Result<(), String> {
+    utils::copy_permissions(source, destination).map_err(|err| {
+        format!(
+            "{error}: Could not set the permissions of '{}': {err}",
+            p.display(),
+            error = ui::Color::error("error"),
+        )
+    })
+}
+
+/// Back up `file` before it gets replaced or removed.
+pub fn backup_file(backup: &Backup, file: &Path) -> Result<PathBuf, String> {
+    backup.save(file).map_err(|err| {
+        format!(
+            "{error}: Could not back up '{}': {err}",
+            file.display(),
+            error = ui::Color::error("error"),
+        )
+    })
+}
+
+/// Check that a change to `file` can be recorded in the run's journal,
+/// before making it.
+pub fn check_change(backup: &Backup, file: &Path) -> Result<(), String> {
+    backup.check(file).map_err(|err| {
+        format!(
+            "{error}: Could not record change to '{}' in journal: {err}",
+            file.display(),
+            error = ui::Color::error("error"),
+        )
+    })
+}
+
+/// Record a change to `file` in the run's journal.
+pub fn record_change(
+    backup: &Backup,
+    change: Change,
+    file: &Path,
+    backup_path: Option<&Path>,
+) -> Result<(), String> {
+    backup.record(change, file, backup_path).map_err(|err| {
+        format!(
+            "{error}: Could not record change to '{}' in journal: {err}",
+            file.display(),
+            error = ui::Color::error("error"),
+        )
+    })
+}
+
+/// What to do with files that changed on the receiving end.
+///
+/// `sync` receives in the home, `rsync` in the root.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum OnConflict {
+    /// Keep files changed on the receiving end only, refuse conflicts.
+    #[default]
+    Refuse,
+    /// Same, but resolve conflicts by keeping the receiving end's file.
+    Ours,
+    /// Same, but resolve conflicts by overwriting the receiving end.
+    Theirs,
+    /// Overwrite everything, whatever changed where.
+    Force,
+}
+
+/// Decide how to overwrite a file, given how it diverged.
+///
+/// `receiving` is the end being written to: [`Divergence::Home`] for
+/// `sync`, [`Divergence::Root`] for `rsync`. Changes to the sending end
+/// (or changes we can't place, for lack of a base) go through; changes
+/// to the receiving end are kept, and conflicts are left to
+/// `on_conflict`.
+pub fn plan_overwrite(
+    divergence: Divergence,
+    receiving: Divergence,
+    on_conflict: OnConflict,
+) -> ui::Operation {
+    match divergence {
+        Divergence::None => ui::Operation::NoOp,
+        _ if on_conflict == OnConflict::Force => ui::Operation::Overwrite,
+        Divergence::Both => match on_conflict {
+            OnConflict::Refuse => ui::Operation::Conflict,
+            OnConflict::Ours => ui::Operation::Skip,
+            OnConflict::Theirs | OnConflict::Force => ui::Operation::Overwrite,
+        },
+        divergence if divergence == receiving => ui::Operation::Skip,
+        _ => ui::Operation::Overwrite,
+    }
+}
+
+/// Load the attributes of `root`, or exit.
+pub fn load_attributes(root: &Path) -> Result<Attributes, i32> {
+    Attributes::load(root).map_err(|err| {
+        eprintln!("{fatal}: {err}", fatal = ui::Color::error("fatal"));
+        1
+    })
+}
+
+/// Load the targets of `root`, or exit.
+pub fn load_targets(root: &Path) -> Result<Targets, i32> {
+    Targets::load(root).map_err(|err| {
+        eprintln!("{fatal}: {err}", fatal = ui::Color::error("fatal"));
+        1
+    })
+}
+
+/// Load the templates of `root`, or exit.
+pub fn load_templates(root: &Path) -> Result<Templates, i32> {
+    Templates::load(root).map_err(|err| {
+        eprintln!("{fatal}: {err}", fatal = ui::Color::error("fatal"));
+        1
+    })
+}
+
+/// Whether `symlink` points to `target`.
+///
+/// Both are compared canonicalized, so it doesn't matter whether the
+/// link is relative or absolute. Broken links are compared as is.
+pub fn is_link_to(symlink: &Path, target: &Path) -> bool {
+    let Ok(link) = utils::read_link_absolute(symlink) else {
+        return false;
+    };
+    match (link.canonicalize(), target.canonicalize()) {
+        (Ok(link), Ok(target)) => link == target,
+        _ => link == target,
+    }
+}
+
+/// The folded directory `p` is linked through in the home, if any.
+///
+/// That is, the outermost directory of `p` (relative to the root) that
+/// is a symlink in the home, pointing to the same directory in the root
+/// (see `link` and [`Attribute::Fold`]). Like files, directories are
+/// linked where `targets` maps them.
+///
+/// [`Attribute::Fold`]: deezconfigs::attributes::Attribute::Fold
+pub fn find_folded_dir(root: &Path, home: &Path, targets: &Targets, p: &Path) -> Option<PathBuf> {
+    let mut dir = PathBuf::new();
+    // All components but the last: the file itself.
+    let mut components = p.components().peekable();
+    while let Some(component) = components.next() {
+        if components.peek().is_none() {
+            break;
+        }
+        dir.push(component);
+        let link = targets.destination(home, &dir);
+        if link.is_symlink() {
+            return is_link_to(&link, &root.join(&dir)).then_some(dir);
+        }
+    }
+    None
+}
+
+/// Load the manifest of `root_id`, or exit.
+pub fn load_manifest(home: &Path, root_id: &str) -> Result<Manifest, i32> {
+    Manifest::load(&get_state_directory(home), root_id, home).map_err(|err| {
+        eprintln!(
+            "{fatal}: Could not read manifest: {err}",
+            fatal = ui::Color::error("fatal")
+        );
+        1
+    })
+}
+
+/// Load the manifest of `root_id`, update it, and save it back.
+pub fn update_manifest(
+    home: &Path,
+    root_id: &str,
+    update: impl FnOnce(&mut Manifest),
+) -> Result<(), String> {
+    let state_dir = get_state_directory(home);
+    Manifest::load(&state_dir, root_id, home)
+        .and_then(|mut manifest| {
+            update(&mut manifest);
+            manifest.save()
+        })
+        .map_err(|err| {
+            format!(
+                "{error}: Could not update manifest: {err}",
+                error = ui::Color::error("error"),
+            )
+        })
+}
+
+/// Remove `file` from home, and the directories it leaves empty.
+///
+/// The file is always backed up and journaled before being removed. If
+/// the backup fails, the file is left as is.
+pub fn remove_file_with_backup(home: &Path, file: &Path, backup: &Backup) -> Result<(), String> {
+    let backup_path = backup_file(backup, file)?;
+
+    if let Err(err) = fs::remove_file(file) {
+        return Err(format!(
+            "{error}: Could not remove file '{}': {err}",
+            file.display(),
+            error = ui::Color::error("error"),
+        ));
+    }
+
+    remove_empty_parent_dirs(home, file);
+
+    record_change(backup, Change::Removed, file, Some(&backup_path))
+}
+
+/// Replace `destination`, a link to `source`, by a copy of it.
+///
+/// The link is backed up and journaled first, so `undo` can put it
+/// back. Like `sync`, the copy is renamed over the link, so the file
+/// never goes missing.
+pub fn materialize_link(source: &Path, destination: &Path, backup: &Backup) -> Result<(), String> {
+    let backup_path = backup_file(backup, destination)?;
+
+    if let Err(err) = utils::copy_atomically(source, destination) {
+        return Err(format!(
+            "{error}: Could not copy '{}' over its link: {err}",
+            source.display(),
+            error = ui::Color::error("error"),
+        ));
+    }
+
+    record_change(backup, Change::Replaced, destination, Some(&backup_path))
+}
+
+/// Replace the link of a folded directory (relative to the root) by a
+/// real directory, with copies of its files.
+///
+/// The link is backed up and journaled first, so `undo` can fold the
+/// directory back.
+pub fn materialize_folded_dir(
+    root: &Path,
+    home: &Path,
+    targets: &Targets,
+    dir: &Path,
+    files: &[PathBuf],
+    backup: &Backup,
+) -> Result<(), String> {
+    let link = targets.destination(home, dir);
+
+    for p in files {
+        check_change(backup, &targets.destination(home, p))?;
+    }
+    let backup_path = backup_file(backup, &link)?;
+
+    if let Err(err) = fs::remove_file(&link).and_then(|()| fs::create_dir(&link)) {
+        return Err(format!(
+            "{error}: Could not unfold directory '{}': {err}",
+            link.display(),
+            error = ui::Color::error("error"),
+        ));
+    }
+
+    record_change(backup, Change::Removed, &link, Some(&backup_path))?;
+
+    for p in files {
+        let destination = targets.destination(home, p);
+        if let Err(err) = fs::create_dir_all(
+            destination
+                .parent()
+                .expect("at the bare minimum, `parent` is the directory"),
+        )
+        .and_then(|()| utils::copy_atomically(&root.join(p), &destination))
+        {
+            return Err(format!(
+                "{error}: Could not copy '{}' to home: {err}",
+                p.display(),
+                error = ui::Color::error("error"),
+            ));
+        }
+        record_change(backup, Change::Created, &destination, None)?;
+    }
+
+    Ok(())
+}
+
+/// Home files deployed from a root, but since deleted from it.
+#[derive(Debug, Default)]
+pub struct Pruned {
+    /// Files removed from home (or that would be, in dry-run), relative
+    /// to the root.
+    pub files: Vec<PathBuf>,
+    /// Entries to remove from the manifest (given relative to the root).
+    pub stale_entries: Vec<PathBuf>,
+    pub nb_errors: usize,
+}
+
+/// Remove home files that were deployed from `root`, but that no longer
+/// exist in it.
+///
+/// The manifest tells what was deployed. Only files that are still
+/// exactly as deployed get removed (and backed up first); files edited
+/// since are left in place, with a warning.
+///
+/// This does not update the manifest itself, because the caller usually
+/// has entries of its own to record. Instead, the entries to remove are
+/// returned in [`Pruned::stale_entries`].
+pub fn prune_stale_files(
+    root: &Path,
+    home: &Path,
+    targets: &Targets,
+    root_id: &str,
+    pathspec: &PathSpec,
+    dry_run: bool,
+    backup: &Backup,
+) -> Pruned {
+    let mut pruned = Pruned::default();
+
+    let manifest = match Manifest::load(&get_state_directory(home), root_id, home) {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            pruned.nb_errors += 1;
+            eprintln!(
+                "{error}: Could not read manifest: {err}",
+                error = ui::Color::error("error"),
+            );
+            return pruned;
+        }
+    };
+
+    for entry in manifest.entries() {
+        let source = root.join(&entry.root_path);
+        // Matches both files and symlinks (even broken ones).
+        let is_in_root = source.exists() || source.is_symlink();
+        if is_in_root || !pathspec.matches(&entry.root_path) {
+            continue;
+        }
+
+        let destination = &entry.home_path;
+
+        // Already gone, only the manifest is left to update.
+        if !destination.exists() && !destination.is_symlink() {
+            pruned.stale_entries.push(entry.root_path.clone());
+            // Files of folded directories go away with the directory,
+            // which leaves the directory's link dangling.
+            if let Some(dir) = find_folded_dir(root, home, targets, &entry.root_path)
+                && !targets.destination(home, &dir).exists()
+                && !pruned.files.contains(&dir)
+            {
+                if !dry_run
+                    && let Err(err) =
+                        remove_file_with_backup(home, &targets.destination(home, &dir), backup)
+                {
+                    pruned.nb_errors += 1;
+                    eprintln!("{err}");
+                    continue;
+                }
+                pruned.files.push(dir);
+            }
+            continue;
+        }
+
+        // Links may have been made from the root as given, or from its
+        // canonical path (the root id), depending on how it was passed.
+        let sources = [source, Path::new(root_id).join(&entry.root_path)];
+        if !entry.is_unchanged(&sources) {
+            eprintln!(
+                "{warning}: Not pruning '{}': It has changed since it was deployed.",
+                destination.display(),
+                warning = ui::Color::warning("warning"),
+            );
+            continue;
+        }
+
+        if !dry_run && let Err(err) = remove_file_with_backup(home, destination, backup) {
+            pruned.nb_errors += 1;
+            eprintln!("{err}");
+            continue;
+        }
+
+        pruned.files.push(entry.root_path.clone());
+        pruned.stale_entries.push(entry.root_path.clone());
+    }
+
+    pruned
+}
+
+/// What deploying files into the home did (see `sync`, and `link`).
+///
+/// The walks only deploy the files. Everything that comes after (e.g.,
+/// pruning, recording, reporting) is left to [`finish_deployment()`],
+/// so that `deploy` can do it once for both.
+#[derive(Debug, Default)]
+pub struct Deployment {
+    /// Files, and what was done to them (only in verbose or dry-run).
+    pub files: Vec<(String, ui::Operation)>,
+    /// Files that changed in both the root and the home.
+    pub conflicts: Vec<String>,
+    /// What to record in the manifest.
+    pub entries: Vec<manifest::Entry>,
+    pub nb_files: usize,
+    /// Files left as is on purpose (e.g., edited in the home).
+    pub nb_skipped: usize,
+    pub nb_errors: usize,
+}
+
+impl Deployment {
+    /// Add what `other` did to what `self` did.
+    pub fn merge(&mut self, other: Self) {
+        self.files.extend(other.files);
+        self.conflicts.extend(other.conflicts);
+        self.entries.extend(other.entries);
+        self.nb_files += other.nb_files;
+        self.nb_skipped += other.nb_skipped;
+        self.nb_errors += other.nb_errors;
+    }
+}
+
+/// Wrap up a [`Deployment`].
+///
+/// 1. Prune stale files, if `prune`.
+/// 2. Record what was deployed (and pruned) in the manifest.
+/// 3. Report the files, and conflicts.
+/// 4. Run the `post_hooks`.
+/// 5. Print the summary.
+///
+/// With a `snapshot` (the manifest as it was before the run), the run
+/// is atomic: if anything failed (the post hooks included), everything
+/// `backup` recorded is rolled back instead.
+#[allow(clippy::too_many_arguments)]
+pub fn finish_deployment(
+    action: ui::Action,
+    root: &Path,
+    home: &Path,
+    targets: &Targets,
+    root_id: &str,
+    pathspec: &PathSpec,
+    deployment: Deployment,
+    backup: &Backup,
+    snapshot: Option<&Manifest>,
+    prune: bool,
+    verbose: bool,
+    dry_run: bool,
+    mut nb_hooks_ran: usize,
+    post_hooks: impl FnOnce() -> Result<usize, i32>,
+) -> Result<(), i32> {
+    let Deployment {
+        mut files,
+        mut conflicts,
+        entries,
+        nb_files,
+        nb_skipped,
+        mut nb_errors,
+    } = deployment;
+
+    // Pruning happens after the walk, so that the files it removes are
+    // really gone from the root, and not just renamed.
+    let pruned = if prune {
+        prune_stale_files(root, home, targets, root_id, pathspec, dry_run, backup)
+    } else {
+        Pruned::default()
+    };
+    nb_errors += pruned.nb_errors;
+
+    conflicts.sort();
+    nb_errors += conflicts.len();
+
+    // Record what was deployed, so later runs know what `deez` put in
+    // the home (and what it didn't). A failed atomic run gets rolled
+    // back instead.
+    let is_rolling_back = snapshot.is_some() && nb_errors > 0;
+    if !dry_run
+        && !is_rolling_back
+        && let Err(err) = update_manifest(home, root_id, |manifest| {
+            for entry in entries {
+                manifest.insert(entry);
+            }
+            for file in &pruned.stale_entries {
+                manifest.remove(file);
+            }
+        })
+    {
+        nb_errors += 1;
+        eprintln!("{err}");
+    }
+
+    if verbose || dry_run {
+        files.extend(
+            pruned
+                .files
+                .iter()
+                .map(|file| (file.to_string_lossy().to_string(), ui::Operation::Delete)),
+        );
+    }
+    // Do not use `sort_unstable()` because the files are likely
+    // _partially_ sorted, in which case stable sort is faster,
+    // as per the docs.
+    files.sort();
+
+    if dry_run {
+        ui::print_operations(&files);
+    } else {
+        ui::print_files(&files.into_iter().map(|(file, _)| file).collect::<Vec<_>>());
+    }
+
+    ui::print_conflicts(&conflicts);
+
+    if verbose
+        && backup.nb_files() > 0
+        && let Some(backup_dir) = backup.directory()
+    {
+        ui::print_backup_summary(backup_dir, backup.nb_files());
+    }
+
+    if let Some(snapshot) = snapshot
+        && nb_errors > 0
+    {
+        roll_back(home, backup, snapshot);
+        return Err(1);
+    }
+
+    nb_hooks_ran += post_hooks().inspect_err(|_| {
+        if let Some(snapshot) = snapshot {
+            roll_back(home, backup, snapshot);
+        }
+    })?;
+
+    if prune {
+        ui::print_prune_summary(pruned.files.len(), dry_run);
+    }
+
+    if dry_run {
+        ui::print_dry_run_summary(action, root, nb_files, nb_skipped, nb_errors, nb_hooks_ran);
+    } else {
+        ui::print_summary(action, root, nb_files, nb_skipped, nb_errors, nb_hooks_ran);
+    }
+
+    if nb_errors > 0 { Err(1) } else { Ok(()) }
+}
+
+/// Whether `dir` is empty, i.e., whether `fs::remove_dir()` can work.
+///
+/// Used by dry-runs, to report the errors a real run would hit.
+pub fn is_dir_empty(dir: &Path) -> bool {
+    fs::read_dir(dir).is_ok_and(|mut entries| entries.next().is_none())
+}
+
+/// Remove all parent dirs of `file` until not empty, or `home`.
+///
+/// Neither `home` nor anything above it is ever removed.
+pub fn remove_empty_parent_dirs(home: &Path, file: &Path) {
+    const DEPTH_LIMIT: usize = 20;
+    // `skip()` self (file).
+    for (i, dir) in file.ancestors().skip(1).enumerate() {
+        // Don't remove home or above.
+        if dir == home || !dir.starts_with(home) {
+            break;
+        }
+        // Basically, remove until it fails, since it fails if
+        // `dir` is not empty.
+        if fs::remove_dir(dir).is_err() {
+            break;
+        }
+        if i == DEPTH_LIMIT {
+            break;
+        }
+    }
+}
+
 /// Helper function to instantiate [`Hooks`] from a command, or error.
 pub fn get_hooks_for_command<'a>(
     root: &'a impl AsRef<Path>,
@@ -580,6 +1468,25 @@ mod tests {
         assert!(is_git_uri("gh:qrichert/configs.git"));
     }
 
+    #[test]
+    fn test_plan_overwrite() {
+        use ui::Operation::{Conflict, NoOp, Overwrite, Skip};
+
+        let plan =
+            |divergence, on_conflict| plan_overwrite(divergence, Divergence::Home, on_conflict);
+
+        assert_eq!(plan(Divergence::None, OnConflict::Force), NoOp);
+        assert_eq!(plan(Divergence::Root, OnConflict::Refuse), Overwrite);
+        assert_eq!(plan(Divergence::Unknown, OnConflict::Refuse), Overwrite);
+        assert_eq!(plan(Divergence::Home, OnConflict::Refuse), Skip);
+        assert_eq!(plan(Divergence::Home, OnConflict::Theirs), Skip);
+        assert_eq!(plan(Divergence::Home, OnConflict::Force), Overwrite);
+        assert_eq!(plan(Divergence::Both, OnConflict::Refuse), Conflict);
+        assert_eq!(plan(Divergence::Both, OnConflict::Ours), Skip);
+        assert_eq!(plan(Divergence::Both, OnConflict::Theirs), Overwrite);
+        assert_eq!(plan(Divergence::Both, OnConflict::Force), Overwrite);
+    }
+
     #[test]
     fn test_is_sub_root_safe() {
         // Safe.
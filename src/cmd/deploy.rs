@@ -0,0 +1,121 @@
+use deezconfigs::pathspec::PathSpec;
+use deezconfigs::{state, ui};
+
+use super::common::{
+    OnConflict, finish_deployment, get_home_directory, get_hooks_for_command, get_state_directory,
+    is_git_remote_uri, load_attributes, load_manifest, load_targets, load_templates, local_root_id,
+    resolve_and_pull_config_root, resolve_config_root, run_hooks,
+};
+use super::interactive::Prompter;
+use super::link::link_files;
+use super::sync::sync_files;
+
+/// Deploy config from root into home, each file its own way.
+///
+/// 1. Sync the files, except those with the `link` attribute.
+/// 2. Link the files with the `link` attribute.
+///
+/// Files without either attribute are synced, like with the `copy`
+/// attribute.
+///
+/// Both steps run, even if the first one fails, for the links not to
+/// lag behind the copies. They make a single run: the `deploy` hooks
+/// run once around both, and one summary covers them.
+///
+/// In atomic mode, the run is all or nothing: if any file fails (or
+/// the `post-deploy` hook does), every file touched, copied or linked,
+/// is rolled back.
+///
+/// In dry-run mode, both steps only report what would be done.
+#[allow(clippy::too_many_arguments)]
+pub fn deploy(
+    root: Option<&String>,
+    verbose: bool,
+    pull_before_command: bool,
+    dry_run: bool,
+    prune: bool,
+    atomic: bool,
+    pathspec: &PathSpec,
+) -> Result<(), i32> {
+    // Links into a temporary clone would be dangling once it's gone.
+    if is_git_remote_uri(root) {
+        eprintln!(
+            "{fatal}: Cannot deploy from a Git remote, links need a local root.",
+            fatal = ui::Color::error("fatal")
+        );
+        return Err(1);
+    }
+
+    let root = if pull_before_command {
+        resolve_and_pull_config_root(root)?
+    } else {
+        // Even a dry-run runs hooks, which must come from a root the
+        // user trusts.
+        resolve_config_root(root, true)?
+    };
+    let root_id = local_root_id(&root);
+    let home = get_home_directory()?;
+    let attributes = load_attributes(&root)?;
+    let targets = load_targets(&root)?;
+    let templates = load_templates(&root)?;
+    let mut hooks = get_hooks_for_command(&root, &home, verbose)?;
+    if dry_run {
+        hooks.set_env_var("DEEZ_DRY_RUN", "true");
+    }
+
+    // The last synced version of each file, to tell which end changed.
+    let manifest = load_manifest(&home, &root_id)?;
+
+    let backup = state::Backup::new(&get_state_directory(&home), "deploy", Some(root.as_ref()));
+
+    let mut nb_hooks_ran = 0;
+
+    nb_hooks_ran += run_hooks(|| hooks.pre_deploy())?;
+
+    let mut deployment = sync_files(
+        &root,
+        &home,
+        &attributes,
+        &targets,
+        &templates,
+        &manifest,
+        &backup,
+        &Prompter::new(false),
+        OnConflict::default(),
+        verbose,
+        dry_run,
+        pathspec,
+    );
+    deployment.merge(link_files(
+        &root,
+        &home,
+        &attributes,
+        &targets,
+        &backup,
+        false,
+        true,
+        verbose,
+        dry_run,
+        pathspec,
+    ));
+
+    // Nothing to roll back in a dry-run, nothing gets done.
+    let atomic = atomic && !dry_run;
+
+    finish_deployment(
+        ui::Action::Deploy,
+        &root,
+        &home,
+        &targets,
+        &root_id,
+        pathspec,
+        deployment,
+        &backup,
+        atomic.then_some(&manifest),
+        prune,
+        verbose,
+        dry_run,
+        nb_hooks_ran,
+        || run_hooks(|| hooks.post_deploy()),
+    )
+}
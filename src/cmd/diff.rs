@@ -10,7 +10,8 @@ use deezconfigs::pathspec::PathSpec;
 use deezconfigs::{ui, utils, walk};
 
 use super::common::{
-    get_config_root_from_git, get_home_directory, get_hooks_for_command, is_git_remote_uri,
+    get_config_root_at_revision, get_config_root_from_git, get_home_directory,
+    get_hooks_for_command, is_git_remote_uri, load_targets, load_templates,
     resolve_and_pull_config_root, resolve_config_root, run_git_fetch_in_root, run_hooks,
     show_git_diff_against_upstream,
 };
@@ -37,11 +38,15 @@ impl Ord for Diff {
 ///
 /// 1. Collect all files in `configs`.
 /// 2. Diff with files in `$HOME`.
+///
+/// With `rev`, step 1. collects the files of the root as of that Git
+/// revision instead.
 pub fn diff(
     root: Option<&String>,
     verbose: bool,
     pull_before_command: bool,
     reversed: bool,
+    rev: Option<&String>,
     pathspec: &PathSpec,
 ) -> Result<(), i32> {
     let root = if pull_before_command {
@@ -51,8 +56,18 @@ pub fn diff(
     } else {
         resolve_config_root(root, false)?.into()
     };
+    // Files as of a Git revision, instead of the working tree.
+    let root = match rev {
+        Some(rev) => get_config_root_at_revision(&root, rev)?,
+        None => root,
+    };
+    // Remote roots can point to a single file too.
+    let narrowed = root.narrow_pathspec(pathspec)?;
+    let pathspec = narrowed.as_ref().unwrap_or(pathspec);
     let root: &Path = root.as_ref();
     let home = get_home_directory()?;
+    let targets = load_targets(root)?;
+    let templates = load_templates(root)?;
     let hooks = get_hooks_for_command(&root, &home, verbose)?;
 
     let mut nb_hooks_ran = 0;
@@ -71,8 +86,15 @@ pub fn diff(
 
         nb_processed.fetch_add(1, Ordering::Relaxed);
 
-        let source = root.join(p);
-        let destination = home.join(p);
+        let source = match templates.source(root, p) {
+            Ok(source) => source,
+            Err(err) => {
+                nb_errors.fetch_add(1, Ordering::Relaxed);
+                eprintln!("{error}: {err}.", error = ui::Color::error("error"));
+                return;
+            }
+        };
+        let destination = targets.destination(&home, p);
 
         let diff = if destination.is_file() {
             let diff = if reversed {
@@ -222,8 +244,6 @@ pub fn diff_incoming(
 }
 
 fn diff_files(before: &Path, after: &Path) -> Result<Option<String>, std::io::Error> {
-    use imara_diff::{Algorithm, BasicLineDiffPrinter, Diff, InternedInput, UnifiedDiffConfig};
-
     thread_local! {
         static BUFFERS: RefCell<(String, String)> = RefCell::new(
             // 64 Kb should be plenty for the majority of config files.
@@ -235,49 +255,237 @@ fn diff_files(before: &Path, after: &Path) -> Result<Option<String>, std::io::Er
         utils::read_to_string_buffer(before_buf, before)?;
         utils::read_to_string_buffer(after_buf, after)?;
 
-        let input = InternedInput::new(before_buf.as_str(), after_buf.as_str());
-        let mut diff = Diff::compute(Algorithm::Histogram, &input);
-        diff.postprocess_lines(&input);
+        Ok(diff_texts(before_buf, after_buf))
+    })
+}
 
-        if diff.hunks().next().is_none() {
-            return Ok(None);
-        }
+/// Unified diff between two texts, or `None` if they are the same.
+pub fn diff_texts(before: &str, after: &str) -> Option<String> {
+    use imara_diff::{Algorithm, BasicLineDiffPrinter, Diff, InternedInput, UnifiedDiffConfig};
 
-        let diff = diff
-            .unified_diff(
-                &BasicLineDiffPrinter(&input.interner),
-                UnifiedDiffConfig::default(),
-                &input,
-            )
-            .to_string();
+    let input = InternedInput::new(before, after);
+    let mut diff = Diff::compute(Algorithm::Histogram, &input);
+    diff.postprocess_lines(&input);
 
-        Ok(Some(diff))
-    })
+    // No hunks, no changes.
+    diff.hunks().next()?;
+
+    let diff = diff
+        .unified_diff(
+            &BasicLineDiffPrinter(&input.interner),
+            UnifiedDiffConfig::default(),
+            &input,
+        )
+        .to_string();
+
+    Some(diff)
 }
 
 fn print_file_diffs(diffs: &[Diff]) {
     let diffs = diffs
         .iter()
-        .map(|d| {
-            format!(
-                "{}\n{}\n",
-                ui::Color::file_name(&d.file),
-                d.diff
-                    .lines()
-                    .map(|l| {
-                        match l.chars().next() {
-                            Some('+') => ui::Color::in_sync(l),
-                            Some('-' | '!') => ui::Color::missing(l),
-                            Some('@') => ui::Color::line_range(l),
-                            _ => Cow::Borrowed(l),
-                        }
-                    })
-                    .collect::<Vec<Cow<str>>>()
-                    .join("\n")
-            )
-        })
+        .map(|d| format_file_diff(&d.file, &d.diff))
         .collect::<Vec<String>>()
         .join("\n");
 
     Pager::page_or_print(&diffs);
 }
+
+/// Color the diff of a file, under its name.
+pub fn format_file_diff(file: &str, diff: &str) -> String {
+    format!("{}\n{}\n", ui::Color::file_name(file), color_diff(diff))
+}
+
+/// Color the lines of a diff, according to what they are.
+pub fn color_diff(diff: &str) -> String {
+    diff.lines()
+        .map(|l| match l.chars().next() {
+            Some('+') => ui::Color::in_sync(l),
+            Some('-' | '!') => ui::Color::missing(l),
+            Some('@') => ui::Color::line_range(l),
+            _ => Cow::Borrowed(l),
+        })
+        .collect::<Vec<Cow<str>>>()
+        .join("\n")
+}
+
+/// Lines of context shown around a hunk.
+const HUNK_CONTEXT_LINES: usize = 3;
+
+/// The line-diff between two texts, split into hunks.
+///
+/// Contrary to [`diff_texts()`], hunks are kept separate, so they can be
+/// applied selectively (see [`Hunks::apply()`]).
+pub struct Hunks<'a> {
+    before: Vec<&'a str>,
+    after: Vec<&'a str>,
+    hunks: Vec<imara_diff::Hunk>,
+}
+
+impl<'a> Hunks<'a> {
+    #[must_use]
+    pub fn new(before: &'a str, after: &'a str) -> Self {
+        use imara_diff::{Algorithm, Diff, InternedInput, sources};
+
+        let input = InternedInput::new(before, after);
+        let mut diff = Diff::compute(Algorithm::Histogram, &input);
+        diff.postprocess_lines(&input);
+
+        Self {
+            // Tokenized the same way as `input`, so hunks index into them.
+            before: sources::lines(before).collect(),
+            after: sources::lines(after).collect(),
+            hunks: diff.hunks().collect(),
+        }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.hunks.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.hunks.is_empty()
+    }
+
+    /// Render hunk `i` as a unified diff, with a few lines of context.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no hunk `i`.
+    #[must_use]
+    pub fn format(&self, i: usize) -> String {
+        let hunk = &self.hunks[i];
+        let (before_start, before_end) = (hunk.before.start as usize, hunk.before.end as usize);
+        let (after_start, after_end) = (hunk.after.start as usize, hunk.after.end as usize);
+
+        // Context is the same on both ends, read it from `before`.
+        let context_start = before_start.saturating_sub(HUNK_CONTEXT_LINES);
+        let context_end = (before_end + HUNK_CONTEXT_LINES).min(self.before.len());
+        let nb_context = (before_start - context_start) + (context_end - before_end);
+
+        // Like Git, empty ranges start at the line _before_ them.
+        let range = |start: usize, len: usize| {
+            let start = if len == 0 { start } else { start + 1 };
+            format!("{start},{len}")
+        };
+
+        let mut diff = format!(
+            "@@ -{} +{} @@\n",
+            range(context_start, before_end - before_start + nb_context),
+            range(
+                after_start - (before_start - context_start),
+                after_end - after_start + nb_context
+            ),
+        );
+        let mut push_lines = |prefix: char, lines: &[&str]| {
+            for line in lines {
+                diff.push(prefix);
+                diff.push_str(line.strip_suffix('\n').unwrap_or(line));
+                diff.push('\n');
+            }
+        };
+        push_lines(' ', &self.before[context_start..before_start]);
+        push_lines('-', &self.before[before_start..before_end]);
+        push_lines('+', &self.after[after_start..after_end]);
+        push_lines(' ', &self.before[before_end..context_end]);
+
+        diff
+    }
+
+    /// Rebuild `before`, with the hunks marked as `accepted` applied.
+    ///
+    /// Accepting every hunk gives `after`, and accepting none gives
+    /// `before`.
+    #[must_use]
+    pub fn apply(&self, accepted: &[bool]) -> String {
+        debug_assert_eq!(accepted.len(), self.hunks.len());
+
+        let mut text = String::new();
+        let mut pos = 0;
+
+        for (hunk, &is_accepted) in self.hunks.iter().zip(accepted) {
+            let (before_start, before_end) = (hunk.before.start as usize, hunk.before.end as usize);
+
+            text.extend(self.before[pos..before_start].iter().copied());
+            if is_accepted {
+                text.extend(
+                    self.after[hunk.after.start as usize..hunk.after.end as usize]
+                        .iter()
+                        .copied(),
+                );
+            } else {
+                text.extend(self.before[before_start..before_end].iter().copied());
+            }
+            pos = before_end;
+        }
+        text.extend(self.before[pos..].iter().copied());
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BEFORE: &str = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+    const AFTER: &str = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\n";
+
+    #[test]
+    fn hunks_are_kept_separate() {
+        let hunks = Hunks::new(BEFORE, AFTER);
+        assert_eq!(hunks.len(), 2);
+    }
+
+    #[test]
+    fn hunks_of_same_texts_are_empty() {
+        let hunks = Hunks::new(BEFORE, BEFORE);
+        assert!(hunks.is_empty());
+    }
+
+    #[test]
+    fn hunks_apply_all_gives_after() {
+        let hunks = Hunks::new(BEFORE, AFTER);
+        assert_eq!(hunks.apply(&[true, true]), AFTER);
+    }
+
+    #[test]
+    fn hunks_apply_none_gives_before() {
+        let hunks = Hunks::new(BEFORE, AFTER);
+        assert_eq!(hunks.apply(&[false, false]), BEFORE);
+    }
+
+    #[test]
+    fn hunks_apply_some() {
+        let hunks = Hunks::new(BEFORE, AFTER);
+        assert_eq!(
+            hunks.apply(&[false, true]),
+            "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\n"
+        );
+        assert_eq!(
+            hunks.apply(&[true, false]),
+            "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\n"
+        );
+    }
+
+    #[test]
+    fn hunks_apply_to_empty_text() {
+        let hunks = Hunks::new("", "a\nb\n");
+        assert_eq!(hunks.apply(&[true]), "a\nb\n");
+    }
+
+    #[test]
+    fn hunks_format_with_context() {
+        let hunks = Hunks::new(BEFORE, AFTER);
+        assert_eq!(hunks.format(0), "@@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n");
+        assert_eq!(hunks.format(1), "@@ -8,3 +8,4 @@\n h\n i\n j\n+k\n");
+    }
+
+    #[test]
+    fn hunks_format_insertion_into_empty_text() {
+        let hunks = Hunks::new("", "a\n");
+        assert_eq!(hunks.format(0), "@@ -0,0 +1,1 @@\n+a\n");
+    }
+}
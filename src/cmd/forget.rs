@@ -0,0 +1,199 @@
+use std::collections::BTreeSet;
+use std::path::{self, Path, PathBuf};
+
+use deezconfigs::pathspec::PathSpec;
+use deezconfigs::{state, ui, walk};
+
+use super::common::{
+    find_folded_dir, get_home_directory, get_state_directory, is_link_to, load_targets,
+    local_root_id, materialize_folded_dir, materialize_link, remove_file_with_backup,
+    resolve_config_root, update_manifest,
+};
+use super::undo::format_errors;
+
+/// Stop managing files, but keep them in home.
+///
+/// 1. Collect the files of the root matching the given paths (in the
+///    home, or in the root) and pathspecs.
+/// 2. Replace their links in `$HOME` by real files, with the same
+///    contents. Folded directories are replaced by real directories.
+/// 3. Remove the files from the root, and from the manifest.
+///
+/// Files that are not in `$HOME` are kept in the root: removing them
+/// would lose them.
+///
+/// Everything is backed up first, so `undo` can revert it.
+///
+/// In dry-run mode, steps 2. and 3. only report what would be done.
+#[allow(clippy::too_many_lines)] // More a procedure than a function.
+pub fn forget(
+    paths: &[String],
+    pathspecs: &[String],
+    verbose: bool,
+    dry_run: bool,
+) -> Result<(), i32> {
+    // Without anything to go by, the pathspec would match everything.
+    if paths.is_empty() && pathspecs.is_empty() {
+        eprintln!(
+            "{error}: Forget what? Please provide the path of a file.",
+            error = ui::Color::error("error")
+        );
+        return Err(2);
+    }
+
+    // A dry-run doesn't alter the file system, no need to check.
+    let root = resolve_config_root(None, !dry_run)?;
+    let root_id = local_root_id(&root);
+    let root = PathBuf::from(&root_id);
+    let home = get_home_directory()?;
+    let targets = load_targets(&root)?;
+
+    // Paths are turned into pathspecs, relative to the root.
+    let mut all_pathspecs = Vec::with_capacity(paths.len() + pathspecs.len());
+    for path in paths {
+        match to_root_path(&root, &home, path) {
+            Ok(p) => all_pathspecs.push(p.display().to_string()),
+            Err(err) => {
+                eprintln!(
+                    "{fatal}: Not forgetting '{path}': {err}.",
+                    fatal = ui::Color::error("fatal")
+                );
+                return Err(1);
+            }
+        }
+    }
+    all_pathspecs.extend(pathspecs.iter().cloned());
+
+    let pathspec = match PathSpec::parse(&all_pathspecs) {
+        Ok(pathspec) => pathspec,
+        Err(err) => {
+            eprintln!("{fatal}: {err}.", fatal = ui::Color::error("fatal"));
+            return Err(2);
+        }
+    };
+
+    let backup = state::Backup::new(&get_state_directory(&home), "forget", Some(&root));
+
+    let files = walk::collect_files_recursively(&root, &pathspec);
+
+    let mut forgotten = Vec::with_capacity(files.len());
+    let mut unfolded = BTreeSet::new();
+    let mut nb_errors = 0;
+
+    for p in &files {
+        let source = root.join(p);
+        let destination = targets.destination(&home, p);
+
+        // Folded directories can only be forgotten as a whole, or
+        // their other files would go with them.
+        if let Some(dir) = find_folded_dir(&root, &home, &targets, p) {
+            if !unfolded.contains(&dir) {
+                let dir_files = walk::collect_files_recursively(
+                    &root,
+                    &PathSpec::parse(&[dir.display().to_string()]).unwrap_or_default(),
+                );
+                if !dir_files.iter().all(|file| files.contains(file)) {
+                    nb_errors += 1;
+                    eprintln!(
+                        "{error}: Could not forget '{}': It is in folded directory '{}', forget the whole directory instead.",
+                        destination.display(),
+                        targets.destination(&home, &dir).display(),
+                        error = ui::Color::error("error"),
+                    );
+                    continue;
+                }
+                if !dry_run
+                    && let Err(err) =
+                        materialize_folded_dir(&root, &home, &targets, &dir, &dir_files, &backup)
+                {
+                    nb_errors += 1;
+                    eprintln!("{err}");
+                    continue;
+                }
+                unfolded.insert(dir);
+            }
+        } else if destination.is_symlink() && is_link_to(&destination, &source) {
+            if !dry_run && let Err(err) = materialize_link(&source, &destination, &backup) {
+                nb_errors += 1;
+                eprintln!("{err}");
+                continue;
+            }
+        } else if !destination.exists() && !destination.is_symlink() {
+            eprintln!(
+                "{warning}: Not forgetting '{}': It is not in the home.",
+                destination.display(),
+                warning = ui::Color::warning("warning"),
+            );
+            continue;
+        }
+        // Else, the home has its own copy already (e.g., synced).
+
+        if !dry_run && let Err(err) = remove_file_with_backup(&root, &source, &backup) {
+            nb_errors += 1;
+            eprintln!("{err}");
+            continue;
+        }
+
+        forgotten.push(p.clone());
+    }
+
+    if !dry_run
+        && let Err(err) = update_manifest(&home, &root_id, |manifest| {
+            for p in &forgotten {
+                manifest.remove(p);
+            }
+        })
+    {
+        nb_errors += 1;
+        eprintln!("{err}");
+    }
+
+    ui::print_files(
+        &forgotten
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>(),
+    );
+
+    if verbose
+        && backup.nb_files() > 0
+        && let Some(backup_dir) = backup.directory()
+    {
+        ui::print_backup_summary(backup_dir, backup.nb_files());
+    }
+
+    let nb_files = forgotten.len();
+    let plural = if nb_files == 1 { "" } else { "s" };
+    let errors = format_errors(nb_errors);
+    if dry_run {
+        println!(
+            "Would forget {nb_files} file{plural} from '{}'{errors} (dry run).",
+            root.display()
+        );
+    } else {
+        println!(
+            "Forgot {nb_files} file{plural} from '{}'{errors}.",
+            root.display()
+        );
+    }
+
+    if nb_errors > 0 { Err(1) } else { Ok(()) }
+}
+
+/// Turn a path in the home, or in the root, into a path relative to the
+/// root.
+fn to_root_path(root: &Path, home: &Path, path: &str) -> Result<PathBuf, String> {
+    let path = path::absolute(path).map_err(|err| err.to_string())?;
+
+    // The root is checked first, it may well be in the home.
+    let p = path
+        .strip_prefix(root)
+        .or_else(|_| path.strip_prefix(home))
+        .map_err(|_| String::from("It is neither in the home nor in the root"))?;
+
+    if p.as_os_str().is_empty() {
+        return Err(String::from("It is the home or the root itself"));
+    }
+
+    Ok(p.to_path_buf())
+}
@@ -0,0 +1,254 @@
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use lessify::Pager;
+
+use deezconfigs::pathspec::PathSpec;
+use deezconfigs::{ui, utils, walk};
+
+use super::diff::{diff_texts, format_file_diff};
+
+/// Lines of diff shown before asking; `d` shows the rest.
+const DIFF_PREVIEW_LINES: usize = 20;
+
+/// What to do with a file, as decided by the user.
+pub enum Decision {
+    /// Apply the change, from the user's edited copy if there is one.
+    Apply(Option<EditedFile>),
+    /// Leave the file as is.
+    Skip,
+}
+
+/// Copy of an incoming file, edited by the user; removed on drop.
+///
+/// The copy lives in a private temp dir of its own, so no one else can
+/// read it, or swap it for something else while it is being edited.
+pub struct EditedFile {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl EditedFile {
+    /// Copy `incoming` to a new private temp dir, as `name`.
+    fn new(incoming: &Path, name: &OsStr) -> io::Result<Self> {
+        let dir = utils::create_private_temp_dir("deez-edit")?;
+        let edited = Self {
+            path: dir.join(name),
+            dir,
+        };
+
+        let mut source = fs::File::open(incoming)?;
+        let mut copy = utils::create_new_file(edited.path(), source.metadata()?.permissions())?;
+        io::copy(&mut source, &mut copy)?;
+
+        Ok(edited)
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for EditedFile {
+    fn drop(&mut self) {
+        _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Ask the user about each change, one file at a time.
+///
+/// When disabled, every change is applied without asking. The answers
+/// "apply all" and "quit" stick, and settle every file that follows.
+///
+/// Prompting only makes sense if files come one at a time: callers
+/// must walk the root with [`Prompter::walk()`].
+pub struct Prompter {
+    is_enabled: bool,
+    is_apply_all: AtomicBool,
+    is_quit: AtomicBool,
+}
+
+impl Prompter {
+    #[must_use]
+    pub fn new(is_enabled: bool) -> Self {
+        Self {
+            is_enabled,
+            is_apply_all: AtomicBool::new(false),
+            is_quit: AtomicBool::new(false),
+        }
+    }
+
+    /// Call `f` on each file of `root`, like [`walk::find_files_recursively()`].
+    ///
+    /// If prompting, the files are processed one at a time, in order.
+    /// Otherwise, they are processed in parallel.
+    pub fn walk(&self, root: impl AsRef<Path>, pathspec: &PathSpec, f: impl Fn(&Path) + Sync) {
+        if self.is_enabled {
+            for p in walk::collect_files_recursively(root, pathspec) {
+                f(&p);
+            }
+        } else {
+            walk::find_files_recursively(root, pathspec, f);
+        }
+    }
+
+    /// Ask whether to apply `operation` to `p`.
+    ///
+    /// `before` is the file as it is now, and `after` the file it will
+    /// be replaced with, or `None` if it is getting removed. Either may
+    /// not exist, in which case it is shown as empty. Only regular files
+    /// can be edited before being applied.
+    pub fn ask(
+        &self,
+        p: &Path,
+        operation: ui::Operation,
+        before: &Path,
+        after: Option<&Path>,
+    ) -> Decision {
+        if !self.is_enabled || self.is_apply_all.load(Ordering::Relaxed) {
+            return Decision::Apply(None);
+        }
+        if self.is_quit.load(Ordering::Relaxed) {
+            return Decision::Skip;
+        }
+
+        let file = p.to_string_lossy();
+        let can_edit = after.is_some_and(|after| after.is_file() && !after.is_symlink());
+        let choices: &[char] = if can_edit {
+            &['y', 'n', 'd', 'e', 'a', 'q', '?']
+        } else {
+            &['y', 'n', 'd', 'a', 'q', '?']
+        };
+
+        let mut edited: Option<EditedFile> = None;
+        let mut diff = render_diff(&file, before, after);
+        print_diff_preview(&diff);
+
+        loop {
+            let prompt = format!("{operation} '{file}'?");
+            match ui::ask_choice_with_prompt(&prompt, choices) {
+                Some('y') => return Decision::Apply(edited),
+                Some('n') => return Decision::Skip,
+                Some('d') => Pager::page_or_print(&diff),
+                Some('e') => {
+                    let Some(after) = after else { continue };
+                    match edit_file(p, after, edited.take()) {
+                        Ok(file) => edited = Some(file),
+                        Err(err) => {
+                            eprintln!(
+                                "{error}: Could not edit '{}': {err}",
+                                p.display(),
+                                error = ui::Color::error("error"),
+                            );
+                            continue;
+                        }
+                    }
+                    diff = render_diff(&file, before, edited.as_ref().map(EditedFile::path));
+                    print_diff_preview(&diff);
+                }
+                Some('a') => {
+                    self.is_apply_all.store(true, Ordering::Relaxed);
+                    return Decision::Apply(edited);
+                }
+                Some('?') => print_help(can_edit),
+                // `q`, or no more input.
+                _ => {
+                    self.is_quit.store(true, Ordering::Relaxed);
+                    return Decision::Skip;
+                }
+            }
+        }
+    }
+}
+
+/// Render the diff of a change, the way `diff` does.
+fn render_diff(file: &str, before: &Path, after: Option<&Path>) -> String {
+    let read = |path: &Path| -> io::Result<String> {
+        // Missing files are shown as empty: created or removed.
+        if path.is_file() {
+            fs::read_to_string(path)
+        } else {
+            Ok(String::new())
+        }
+    };
+
+    let texts = read(before).and_then(|before| {
+        let after = after.map(read).transpose()?.unwrap_or_default();
+        Ok((before, after))
+    });
+
+    let diff = match texts {
+        Ok((before, after)) => {
+            diff_texts(&before, &after).unwrap_or_else(|| String::from("! No changes in content."))
+        }
+        Err(err) => format!("! Cannot show diff: {err}."),
+    };
+
+    format_file_diff(file, &diff)
+}
+
+fn print_diff_preview(diff: &str) {
+    let nb_lines = diff.lines().count();
+    for line in diff.lines().take(DIFF_PREVIEW_LINES) {
+        println!("{line}");
+    }
+    if nb_lines > DIFF_PREVIEW_LINES {
+        println!(
+            "{}",
+            ui::Color::line_range(&format!(
+                "... {} more lines, press `d` to see the full diff.",
+                nb_lines - DIFF_PREVIEW_LINES
+            ))
+        );
+    }
+}
+
+fn print_help(can_edit: bool) {
+    println!("y - apply this change");
+    println!("n - skip this file");
+    println!("d - show the full diff");
+    if can_edit {
+        println!("e - edit the incoming file in $EDITOR before applying it");
+    }
+    println!("a - apply this change, and all the remaining ones");
+    println!("q - quit; skip this file, and all the remaining ones");
+    println!("? - print help");
+}
+
+/// Open a copy of `incoming` in the user's editor.
+///
+/// If the file was already edited, `previous` is edited again instead.
+/// The editor is `$EDITOR` (or `vi`), run through `sh`, so it can have
+/// arguments (e.g., `code --wait`).
+fn edit_file(p: &Path, incoming: &Path, previous: Option<EditedFile>) -> io::Result<EditedFile> {
+    let edited = if let Some(previous) = previous {
+        previous
+    } else {
+        // Keep the file name, so editors can pick the right syntax.
+        EditedFile::new(incoming, p.file_name().unwrap_or(p.as_os_str()))?
+    };
+
+    let editor = env::var("EDITOR")
+        .ok()
+        .filter(|editor| !editor.is_empty())
+        .unwrap_or_else(|| String::from("vi"));
+
+    let status = process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$1\""))
+        .arg("sh")
+        .arg(edited.path())
+        .status()?;
+
+    if !status.success() {
+        return Err(io::Error::other(format!("'{editor}' failed ({status})")));
+    }
+
+    Ok(edited)
+}
@@ -0,0 +1,263 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use deezconfigs::pathspec::PathSpec;
+use deezconfigs::state::journal;
+use deezconfigs::state::manifest::Manifest;
+use deezconfigs::templates::Templates;
+use deezconfigs::{alternates, ui, walk};
+
+use super::common::{
+    find_config_root, get_home_directory, get_state_directory, is_a_config_root, is_git_remote_uri,
+    load_manifest, local_root_id,
+};
+use super::undo::roll_back_run;
+
+/// Run `command` on each layer of a layered root, or on `root` alone.
+///
+/// A root is layered if it's given along with other `layers` on the
+/// CLI, or if its `.deez` file lists them. Files from later layers
+/// override files at the same path from earlier ones: `command` runs on
+/// each layer in turn, with `pathspec` narrowed down to the files the
+/// layer provides.
+///
+/// Each layer is announced, along with the files it provides that
+/// later layers override.
+///
+/// If `atomic`, a layer that fails also rolls back the layers before
+/// it, so the layered run is all or nothing.
+///
+/// # Errors
+///
+/// Errors if a layer is not a local directory, and stops at the first
+/// layer `command` fails for.
+pub fn layered(
+    root: Option<&String>,
+    layers: &[String],
+    pathspec: &PathSpec,
+    atomic: bool,
+    mut command: impl FnMut(Option<&String>, &PathSpec) -> Result<(), i32>,
+) -> Result<(), i32> {
+    let layers = if layers.is_empty() {
+        find_layers_in_root(root)?
+    } else {
+        root.into_iter().chain(layers).cloned().collect()
+    };
+
+    if layers.is_empty() {
+        return command(root, pathspec);
+    }
+
+    // Files must be listed beforehand, so remote layers would have to
+    // be cloned twice.
+    if let Some(layer) = layers.iter().find(|layer| is_git_remote_uri(Some(layer))) {
+        eprintln!(
+            "{fatal}: Layer '{layer}' is not local, layers must be local roots.",
+            fatal = ui::Color::error("fatal")
+        );
+        return Err(1);
+    }
+    if let Some(layer) = layers.iter().find(|layer| !Path::new(layer).is_dir()) {
+        eprintln!(
+            "{fatal}: Layer '{layer}' is not a directory.",
+            fatal = ui::Color::error("fatal")
+        );
+        return Err(1);
+    }
+
+    // Later layers win.
+    let files = layers
+        .iter()
+        .map(|layer| walk::collect_files_recursively(layer, pathspec))
+        .collect::<Vec<_>>();
+    // Alternates override the plain file, and the other way around.
+    // Same for templates.
+    let deployed_as = |file: &Path| -> PathBuf {
+        Templates::rendered_path(&alternates::base_path(file)).into_owned()
+    };
+    let mut providers = BTreeMap::new();
+    for (i, files) in files.iter().enumerate() {
+        for file in files {
+            providers.insert(deployed_as(file), i);
+        }
+    }
+
+    // The runs of the layers done so far, and their manifest before.
+    let home = get_home_directory()?;
+    let mut runs = atomic.then(Vec::<(Vec<PathBuf>, Manifest)>::new);
+
+    for (i, (layer, files)) in layers.iter().zip(&files).enumerate() {
+        let overridden = files
+            .iter()
+            .filter_map(|file| match providers.get(&deployed_as(file)) {
+                Some(&provider) if provider != i => Some((file, &layers[provider])),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        println!("{}", ui::Color::layer(&format!("Layer '{layer}'")));
+        for (file, provider) in &overridden {
+            println!(
+                "  {}{}",
+                file.display(),
+                ui::Color::strategy(&format!("  (overridden by '{provider}')"))
+            );
+        }
+
+        let pathspec = pathspec.excluding(overridden.into_iter().map(|(file, _)| file.clone()));
+
+        let Some(runs) = runs.as_mut() else {
+            command(Some(layer), &pathspec)?;
+            continue;
+        };
+
+        // The layer rolls itself back, the ones before it are on us.
+        let manifest = load_manifest(&home, &local_root_id(Path::new(layer)))?;
+        let before = list_runs(&home)?;
+        if let Err(code) = command(Some(layer), &pathspec) {
+            for (run_dirs, manifest) in runs.iter().rev() {
+                // Nothing was touched, but the manifest may have moved.
+                if run_dirs.is_empty() {
+                    roll_back_run(&home, None, manifest);
+                }
+                for run_dir in run_dirs.iter().rev() {
+                    roll_back_run(&home, Some(run_dir), manifest);
+                }
+            }
+            return Err(code);
+        }
+        let run_dirs = list_runs(&home)?
+            .into_iter()
+            .filter(|run_dir| !before.contains(run_dir))
+            .collect();
+        runs.push((run_dirs, manifest));
+    }
+
+    Ok(())
+}
+
+/// The directories of the runs recorded so far, oldest first.
+fn list_runs(home: &Path) -> Result<Vec<PathBuf>, i32> {
+    match journal::list(&get_state_directory(home)) {
+        Ok(journals) => Ok(journals.into_iter().map(|journal| journal.dir).collect()),
+        Err(err) => {
+            eprintln!(
+                "{fatal}: Could not read backups: {err}",
+                fatal = ui::Color::error("fatal")
+            );
+            Err(1)
+        }
+    }
+}
+
+/// Refuse layered roots, for commands that work on a single root.
+///
+/// # Errors
+///
+/// Errors if the `.deez` file of `root` lists layers.
+pub fn ensure_not_layered(root: Option<&String>, command: &str) -> Result<(), i32> {
+    if find_layers_in_root(root)?.is_empty() {
+        return Ok(());
+    }
+    eprintln!(
+        "{fatal}: The root is made of layers, `{command}` works on a single root.",
+        fatal = ui::Color::error("fatal")
+    );
+    Err(2)
+}
+
+/// The layers listed in the `.deez` file of `root`, if any.
+///
+/// Layers are listed one per line, as `layer <dir>`, relative to the
+/// root, from lowest to highest precedence. Other lines are left alone:
+/// `.deez` is a marker first, and may hold anything.
+fn find_layers_in_root(root: Option<&String>) -> Result<Vec<String>, i32> {
+    if is_git_remote_uri(root) {
+        return Ok(Vec::new());
+    }
+    // Let the command complain about missing roots.
+    let Ok(root) = find_config_root(root) else {
+        return Ok(Vec::new());
+    };
+    if !is_a_config_root(&root) {
+        return Ok(Vec::new());
+    }
+
+    let Ok(content) = fs::read_to_string(root.join(".deez")) else {
+        return Ok(Vec::new());
+    };
+    let layers = parse_layers(&content).map_err(|err| {
+        eprintln!(
+            "{fatal}: {}: {err}",
+            root.join(".deez").display(),
+            fatal = ui::Color::error("fatal")
+        );
+        1
+    })?;
+
+    Ok(layers
+        .into_iter()
+        .map(|layer| root.join(layer).display().to_string())
+        .collect())
+}
+
+/// Parse the layers listed in a `.deez` file.
+fn parse_layers(content: &str) -> Result<Vec<PathBuf>, String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (keyword, layer) = line.trim().split_once(char::is_whitespace)?;
+            (keyword == "layer").then(|| layer.trim())
+        })
+        .map(|line| {
+            let layer = PathBuf::from(line);
+            let is_inside_root = layer
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+            if is_inside_root {
+                Ok(layer)
+            } else {
+                Err(format!(
+                    "Invalid layer '{line}': It must be a directory inside the root"
+                ))
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_layers_in_order() {
+        let layers = parse_layers("# Lowest first.\nlayer base\n\nlayer  work/laptop\n").unwrap();
+        assert_eq!(
+            layers,
+            [PathBuf::from("base"), PathBuf::from("work/laptop")]
+        );
+    }
+
+    #[test]
+    fn empty_file_has_no_layers() {
+        assert!(parse_layers("").unwrap().is_empty());
+        assert!(parse_layers("# Just a marker.\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn other_lines_are_not_layers() {
+        assert!(parse_layers("my dotfiles marker\n").unwrap().is_empty());
+        assert!(parse_layers("layer\nlayers base\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn layers_must_be_inside_the_root() {
+        assert_eq!(
+            parse_layers("layer ../base\n").unwrap_err(),
+            "Invalid layer '../base': It must be a directory inside the root"
+        );
+        assert!(parse_layers("layer /etc\n").is_err());
+        assert!(parse_layers("layer .\n").is_err());
+    }
+}
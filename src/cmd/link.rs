@@ -1,115 +1,243 @@
+use std::collections::BTreeMap;
 use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, PoisonError};
 
+use deezconfigs::attributes::Attributes;
 use deezconfigs::pathspec::PathSpec;
-use deezconfigs::{ui, walk};
+use deezconfigs::state::{self, manifest};
+use deezconfigs::targets::Targets;
+use deezconfigs::templates::Templates;
+use deezconfigs::{ui, utils, walk};
 
 use super::common::{
-    get_home_directory, get_hooks_for_command, resolve_and_pull_config_root, resolve_config_root,
-    run_hooks,
+    Deployment, backup_file, backup_if_different, check_change, find_folded_dir, finish_deployment,
+    get_home_directory, get_hooks_for_command, get_state_directory, is_dir_empty, load_attributes,
+    load_manifest, load_targets, local_root_id, record_change, resolve_and_pull_config_root,
+    resolve_config_root, run_hooks,
 };
 
 /// Link config from root into home.
 ///
 /// 1. Collect all files in `configs`.
 /// 2. Create matching symlinks to the files in `$HOME`.
+///
+/// Files with the `copy` attribute are left alone (see `deploy`). If
+/// `only_declared`, so are files without the `link` attribute. So are
+/// templates, which can only be copied.
+///
+/// Directories with the `fold` attribute are linked as a whole, instead
+/// of file by file, unless the home already has a directory in their
+/// place. Folded directories that lose the attribute are unfolded.
+///
+/// Home files that get replaced are backed up first, if their content
+/// differs from the root's.
+///
+/// In relative mode, symlinks point to the root with a path relative
+/// to their own directory, instead of an absolute one. They survive the
+/// root and the home moving together (e.g., a home mounted elsewhere).
+///
+/// In atomic mode, the run is all or nothing: if any file fails (or
+/// the `post-link` hook does), every file touched is rolled back.
+///
+/// In dry-run mode, step 2. only reports what would be done.
+#[allow(clippy::too_many_arguments)]
 pub fn link(
     root: Option<&String>,
     verbose: bool,
     pull_before_command: bool,
+    dry_run: bool,
+    prune: bool,
+    relative: bool,
+    atomic: bool,
+    only_declared: bool,
     pathspec: &PathSpec,
 ) -> Result<(), i32> {
     let root = if pull_before_command {
         resolve_and_pull_config_root(root)?
     } else {
+        // Even a dry-run runs hooks, which must come from a root the
+        // user trusts.
         resolve_config_root(root, true)?
     };
+    let root_id = local_root_id(&root);
     let home = get_home_directory()?;
-    let hooks = get_hooks_for_command(&root, &home, verbose)?;
+    let attributes = load_attributes(&root)?;
+    let targets = load_targets(&root)?;
+    let mut hooks = get_hooks_for_command(&root, &home, verbose)?;
+    if dry_run {
+        hooks.set_env_var("DEEZ_DRY_RUN", "true");
+    }
+
+    // Nothing to roll back in a dry-run, nothing gets done.
+    let atomic = atomic && !dry_run;
+
+    // The manifest as it was before the run, in case it is rolled back.
+    let manifest = if atomic {
+        Some(load_manifest(&home, &root_id)?)
+    } else {
+        None
+    };
+
+    let backup = state::Backup::new(&get_state_directory(&home), "link", Some(root.as_ref()));
 
     let mut nb_hooks_ran = 0;
 
     nb_hooks_ran += run_hooks(|| hooks.pre_link())?;
 
+    let deployment = link_files(
+        &root,
+        &home,
+        &attributes,
+        &targets,
+        &backup,
+        relative,
+        only_declared,
+        verbose,
+        dry_run,
+        pathspec,
+    );
+
+    finish_deployment(
+        ui::Action::Link,
+        &root,
+        &home,
+        &targets,
+        &root_id,
+        pathspec,
+        deployment,
+        &backup,
+        manifest.as_ref(),
+        prune,
+        verbose,
+        dry_run,
+        nb_hooks_ran,
+        || run_hooks(|| hooks.post_link()),
+    )
+}
+
+/// Link the files of `root` into `home` (steps 1. and 2. of `link()`).
+///
+/// Files with the `copy` attribute are left alone, and so are files
+/// without the `link` attribute if `only_declared`. The files are only
+/// deployed: it's up to the caller to record and report them (see
+/// [`finish_deployment()`]).
+#[allow(clippy::too_many_lines)] // More a procedure than a function.
+#[allow(clippy::too_many_arguments)]
+pub fn link_files(
+    root: &Path,
+    home: &Path,
+    attributes: &Attributes,
+    targets: &Targets,
+    backup: &state::Backup,
+    relative: bool,
+    only_declared: bool,
+    verbose: bool,
+    dry_run: bool,
+    pathspec: &PathSpec,
+) -> Deployment {
     // There will be high contention, but it likely won't matter much
     // given there are rarely _that_ many config files (and the syscalls
     // we issue are a bigger bottleneck anyway).
     let files = Arc::new(Mutex::new(Vec::with_capacity(20)));
+    let deployed = Mutex::new(Vec::with_capacity(20));
+    // Folded directories, and their files, linked once the walk is over.
+    let folded = Mutex::new(BTreeMap::<PathBuf, Vec<PathBuf>>::new());
+    let unfolding = Mutex::new(());
     let nb_files_linked = AtomicUsize::new(0);
     let nb_errors = AtomicUsize::new(0);
 
-    walk::find_files_recursively(&root, pathspec, |p| {
+    walk::find_files_recursively(root, pathspec, |p| {
         debug_assert!(!p.is_dir());
 
-        let source = root.join(p);
-        let destination = home.join(p);
-
-        if destination.is_dir() {
-            // If destination exists and is a directory, try to `rmdir`
-            // it. If it works, the directory was empty anyway. If it
-            // doesn't work, the directory is not empty so we abort
-            // because it is too risky to remove an entire tree.
-            if let Err(err) = fs::remove_dir(&destination) {
-                nb_errors.fetch_add(1, Ordering::Relaxed);
+        // Declared as a copy, it's for `sync` to deploy.
+        match attributes.strategy(p) {
+            Some(manifest::Kind::Copy) => return,
+            None if only_declared => return,
+            _ => {}
+        }
+
+        // There's nothing to link to, it's for `sync` to render.
+        if Templates::is_template(p) {
+            if !only_declared {
                 eprintln!(
-                    "{error}: Could not remove exising directory '{}': {err}",
-                    destination.display(),
-                    error = ui::Color::error("error"),
+                    "{warning}: Skipping '{}': It is a template, sync it instead.",
+                    p.display(),
+                    warning = ui::Color::warning("warning"),
                 );
-                return;
             }
+            return;
         }
 
-        if let Err(err) = fs::create_dir_all(
-            destination
-                .parent()
-                .expect("at the bare minimum, `parent` is `$HOME`"),
-        ) {
-            nb_errors.fetch_add(1, Ordering::Relaxed);
-            eprintln!(
-                "{error}: Could not link '{}' to home: {err}",
-                p.display(),
-                error = ui::Color::error("error"),
-            );
+        // A directory in the way is never replaced: the folded
+        // directory gets unfolded into it instead, file by file.
+        if let Some(dir) = attributes.folded_dir(p)
+            && can_fold(&targets.destination(home, dir))
+            && let Ok(mut folded) = folded.lock()
+        {
+            folded
+                .entry(dir.to_path_buf())
+                .or_default()
+                .push(p.to_path_buf());
             return;
         }
 
-        // If destination exists, remove it.
-        if destination.is_file() || destination.is_symlink() {
-            // TODO: We put `is_symlink()` to handle the case when the
-            //  link is broken (and so `is_file()` presumably wouldn't
-            //  match?). Test it out.
-            if let Err(err) = fs::remove_file(&destination) {
+        let source = root.join(p);
+        let destination = targets.destination(home, p);
+
+        // Linking files through a folded directory would replace them
+        // in the root. The directory must be unfolded first.
+        let unfolded =
+            find_folded_dir(root, home, targets, p).map(|dir| targets.destination(home, &dir));
+
+        let operation = if unfolded.is_some() {
+            Ok(ui::Operation::Create)
+        } else {
+            plan_link(&source, &destination, relative)
+        };
+        let operation = match operation {
+            Ok(operation) => operation,
+            Err(err) => {
                 nb_errors.fetch_add(1, Ordering::Relaxed);
-                eprintln!(
-                    "{error}: Could not remove exising file '{}': {err}",
-                    destination.display(),
-                    error = ui::Color::error("error"),
-                );
+                eprintln!("{err}");
                 return;
             }
-        }
+        };
 
-        #[cfg(unix)]
-        let res = std::os::unix::fs::symlink(&source, &destination);
-        #[cfg(windows)]
-        let res = std::os::windows::fs::symlink_file(&source, &destination);
+        if !dry_run
+            && let Some(dir) = &unfolded
+            && let Err(err) = unfold_dir(dir, &unfolding, backup)
+        {
+            nb_errors.fetch_add(1, Ordering::Relaxed);
+            eprintln!("{err}");
+            return;
+        }
 
-        if let Err(err) = res {
+        if !dry_run
+            && operation != ui::Operation::NoOp
+            && let Err(err) =
+                link_file_with_backup(p, &source, &destination, operation, relative, backup)
+        {
             nb_errors.fetch_add(1, Ordering::Relaxed);
-            eprintln!(
-                "{error}: Could not create link to '{}': {err}",
-                source.display(),
-                error = ui::Color::error("error"),
-            );
+            eprintln!("{err}");
             return;
         }
 
-        if verbose {
+        // Files that can't be hashed (e.g., broken symlinks) are left out
+        // of the manifest; they can't be checked for changes anyway.
+        if !dry_run
+            && let Ok(entry) = manifest::Entry::new(manifest::Kind::Link, &source, p, &destination)
+            && let Ok(mut deployed) = deployed.lock()
+        {
+            deployed.push(entry);
+        }
+
+        if verbose || dry_run {
             let file = p.to_string_lossy().to_string();
             if let Ok(mut files) = files.lock() {
-                files.push(file);
+                files.push((file, operation));
                 // Release the lock ASAP.
                 drop(files);
             } else {
@@ -122,29 +250,256 @@ pub fn link(
         nb_files_linked.fetch_add(1, Ordering::Relaxed);
     });
 
-    let mut files = Arc::try_unwrap(files)
-        .expect("processing is over, we're back to a single thread.")
-        .into_inner()
-        .unwrap();
-    // Do not use `sort_unstable()` because the files are likely
-    // _partially_ sorted, in which case stable sort is faster,
-    // as per the docs.
-    files.sort();
+    for (dir, dir_files) in folded.into_inner().unwrap_or_default() {
+        let source = root.join(&dir);
+        let destination = targets.destination(home, &dir);
 
-    ui::print_files(&files);
+        let operation = plan_fold(&source, &destination, relative);
 
-    nb_hooks_ran += run_hooks(|| hooks.post_link())?;
+        if !dry_run
+            && operation != ui::Operation::NoOp
+            && let Err(err) =
+                link_file_with_backup(&dir, &source, &destination, operation, relative, backup)
+        {
+            nb_errors.fetch_add(1, Ordering::Relaxed);
+            eprintln!("{err}");
+            continue;
+        }
 
-    let nb_files_linked = nb_files_linked.into_inner();
-    let nb_errors = nb_errors.into_inner();
+        // The files are linked through their directory, but they are
+        // still recorded one by one, like any other.
+        if !dry_run && let Ok(mut deployed) = deployed.lock() {
+            deployed.extend(dir_files.iter().filter_map(|p| {
+                let destination = targets.destination(home, p);
+                manifest::Entry::new(manifest::Kind::Link, &root.join(p), p, &destination).ok()
+            }));
+        }
 
-    ui::print_summary(
-        ui::Action::Link,
-        &root,
-        nb_files_linked,
-        nb_errors,
-        nb_hooks_ran,
+        if (verbose || dry_run)
+            && let Ok(mut files) = files.lock()
+        {
+            files.push((format!("{}/", dir.display()), operation));
+        }
+
+        nb_files_linked.fetch_add(dir_files.len(), Ordering::Relaxed);
+    }
+
+    Deployment {
+        files: Arc::try_unwrap(files)
+            .expect("processing is over, we're back to a single thread.")
+            .into_inner()
+            .unwrap(),
+        entries: deployed.into_inner().unwrap_or_default(),
+        nb_files: nb_files_linked.into_inner(),
+        nb_errors: nb_errors.into_inner(),
+        ..Deployment::default()
+    }
+}
+
+/// Determine what linking `source` to `destination` will do.
+///
+/// This mirrors the branches of [`link_file()`], without touching the
+/// file system.
+fn plan_link(source: &Path, destination: &Path, relative: bool) -> Result<ui::Operation, String> {
+    if destination.is_dir() && !destination.is_symlink() {
+        if !is_dir_empty(destination) {
+            return Err(format!(
+                "{error}: Could not remove exising directory '{}': Directory not empty",
+                destination.display(),
+                error = ui::Color::error("error"),
+            ));
+        }
+        return Ok(ui::Operation::RemoveDir);
+    }
+
+    if destination.is_symlink() {
+        // Already linked (and the same way, absolute or relative).
+        if is_linked(source, destination, relative) {
+            return Ok(ui::Operation::NoOp);
+        }
+        return Ok(ui::Operation::ReplaceSymlink);
+    }
+
+    if destination.is_file() {
+        return Ok(ui::Operation::Overwrite);
+    }
+
+    Ok(ui::Operation::Create)
+}
+
+/// The target of the symlink to `source`, created at `destination`.
+///
+/// Relative targets go from the directory of `destination` (which must
+/// exist) to `source`. Both are canonicalized first, but not `source`
+/// itself: links to symlinks in the root point to the symlinks.
+fn link_target(source: &Path, destination: &Path, relative: bool) -> io::Result<PathBuf> {
+    if !relative {
+        return Ok(source.to_path_buf());
+    }
+
+    let canonicalize_parent = |path: &Path| -> io::Result<PathBuf> {
+        let parent = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let file_name = path.file_name().unwrap_or_default();
+        Ok(fs::canonicalize(parent)?.join(file_name))
+    };
+
+    let source = canonicalize_parent(source)?;
+    let destination = canonicalize_parent(destination)?;
+
+    Ok(utils::relative_path(
+        destination.parent().expect("canonical paths have parents"),
+        &source,
+    ))
+}
+
+/// Whether `destination` is a symlink to `source`, as [`link_file()`]
+/// would have created it.
+fn is_linked(source: &Path, destination: &Path, relative: bool) -> bool {
+    match (
+        fs::read_link(destination),
+        link_target(source, destination, relative),
+    ) {
+        (Ok(target), Ok(expected)) => target == expected,
+        _ => false,
+    }
+}
+
+/// Whether the folded directory can be linked to `destination`.
+///
+/// Only symlinks get replaced (including one to the directory itself).
+fn can_fold(destination: &Path) -> bool {
+    destination.is_symlink() || !destination.exists()
+}
+
+/// Determine what linking the folded directory `source` will do.
+///
+/// See [`can_fold()`] for the directories that can be.
+fn plan_fold(source: &Path, destination: &Path, relative: bool) -> ui::Operation {
+    if destination.is_symlink() {
+        // Already folded.
+        if is_linked(source, destination, relative) {
+            return ui::Operation::NoOp;
+        }
+        return ui::Operation::ReplaceSymlink;
+    }
+    ui::Operation::Create
+}
+
+/// Replace the link of a folded directory with a real directory.
+///
+/// The link is backed up and journaled first, so `undo` can fold the
+/// directory back.
+fn unfold_dir(dir: &Path, unfolding: &Mutex<()>, backup: &state::Backup) -> Result<(), String> {
+    // Every file of the directory wants it unfolded, only the first one
+    // to get here does it.
+    let _lock = unfolding.lock().unwrap_or_else(PoisonError::into_inner);
+    if !dir.is_symlink() {
+        return Ok(());
+    }
+
+    let backup_path = backup_file(backup, dir)?;
+
+    if let Err(err) = fs::remove_file(dir).and_then(|()| fs::create_dir(dir)) {
+        return Err(format!(
+            "{error}: Could not unfold directory '{}': {err}",
+            dir.display(),
+            error = ui::Color::error("error"),
+        ));
+    }
+
+    record_change(backup, state::Change::Removed, dir, Some(&backup_path))
+}
+
+/// Link a single file, backing up and journaling what it replaces.
+///
+/// If the backup fails, the file is left untouched: better not link it
+/// than lose it.
+fn link_file_with_backup(
+    p: &Path,
+    source: &Path,
+    destination: &Path,
+    operation: ui::Operation,
+    relative: bool,
+    backup: &state::Backup,
+) -> Result<(), String> {
+    let is_replacing = matches!(
+        operation,
+        ui::Operation::Overwrite | ui::Operation::ReplaceSymlink
     );
 
-    if nb_errors > 0 { Err(1) } else { Ok(()) }
+    check_change(backup, destination)?;
+    let backup_path = if is_replacing {
+        backup_if_different(backup, source, destination)?
+    } else {
+        None
+    };
+
+    link_file(p, source, destination, relative)?;
+
+    let change = if is_replacing {
+        state::Change::Replaced
+    } else {
+        state::Change::Created
+    };
+    record_change(backup, change, destination, backup_path.as_deref())
+}
+
+/// Link a single file from root into home.
+///
+/// `p` is the path of the file relative to the root; it is only used
+/// in error messages.
+fn link_file(p: &Path, source: &Path, destination: &Path, relative: bool) -> Result<(), String> {
+    if destination.is_dir() && !destination.is_symlink() {
+        // If destination exists and is a directory, try to `rmdir`
+        // it. If it works, the directory was empty anyway. If it
+        // doesn't work, the directory is not empty so we abort
+        // because it is too risky to remove an entire tree.
+        if let Err(err) = fs::remove_dir(destination) {
+            return Err(format!(
+                "{error}: Could not remove exising directory '{}': {err}",
+                destination.display(),
+                error = ui::Color::error("error"),
+            ));
+        }
+    }
+
+    if let Err(err) = fs::create_dir_all(
+        destination
+            .parent()
+            .expect("at the bare minimum, `parent` is `$HOME`"),
+    ) {
+        return Err(format!(
+            "{error}: Could not link '{}' to home: {err}",
+            p.display(),
+            error = ui::Color::error("error"),
+        ));
+    }
+
+    // Relative targets need the destination's directory to exist.
+    let target = match link_target(source, destination, relative) {
+        Ok(target) => target,
+        Err(err) => {
+            return Err(format!(
+                "{error}: Could not link '{}' to home: {err}",
+                p.display(),
+                error = ui::Color::error("error"),
+            ));
+        }
+    };
+
+    // The link is created next to the destination, then renamed over
+    // it. This replaces files and symlinks alike (even broken ones), and
+    // there is never a moment without either of them.
+    if let Err(err) = utils::symlink_atomically(&target, destination) {
+        return Err(format!(
+            "{error}: Could not create link to '{}': {err}",
+            source.display(),
+            error = ui::Color::error("error"),
+        ));
+    }
+
+    Ok(())
 }
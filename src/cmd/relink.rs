@@ -0,0 +1,273 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use deezconfigs::pathspec::PathSpec;
+use deezconfigs::state::{self, Change, Manifest};
+use deezconfigs::{ui, utils, walk};
+
+use super::common::{
+    backup_file, get_home_directory, get_state_directory, load_manifest, local_root_id,
+    record_change, resolve_config_root, update_manifest,
+};
+use super::undo::format_errors;
+
+/// Repoint the links into a root that moved to the current root.
+///
+/// 1. Find the previous location of the root (given, or the one root
+///    deployed into `$HOME` that doesn't exist anymore).
+/// 2. Collect the files deployed from there, and the files in the
+///    current root.
+/// 3. Repoint the symlinks in `$HOME` to these files (or to their
+///    folded directories) that point into the previous root.
+///
+/// Links keep their style: relative links stay relative. Links to files
+/// that are not in the current root are reported, and left as is.
+///
+/// Replaced links are backed up first, so `undo` can revert them.
+///
+/// In dry-run mode, step 3. only reports what would be done.
+#[allow(clippy::too_many_lines)] // More a procedure than a function.
+pub fn relink(old_root: Option<&String>, verbose: bool, dry_run: bool) -> Result<(), i32> {
+    // A dry-run doesn't alter the file system, no need to check.
+    let root = resolve_config_root(None, !dry_run)?;
+    let root_id = local_root_id(&root);
+    let home = get_home_directory()?;
+
+    let old_manifest = match old_root {
+        Some(old_root) => load_manifest(&home, &local_root_id(Path::new(old_root)))?,
+        None => find_previous_root(&home, &root_id)?,
+    };
+    let old_root = PathBuf::from(old_manifest.root_id());
+    // Links are made absolute, from the canonical root.
+    let root = PathBuf::from(&root_id);
+
+    if old_root == root {
+        eprintln!(
+            "{fatal}: '{}' is the current root, nothing to relink.",
+            old_root.display(),
+            fatal = ui::Color::error("fatal")
+        );
+        return Err(1);
+    }
+
+    // Files deployed from the previous root, and files of the current
+    // one (in case they were deployed before manifests were a thing).
+    let candidates = Mutex::new(
+        old_manifest
+            .entries()
+            .map(|entry| entry.root_path.clone())
+            .collect::<BTreeSet<_>>(),
+    );
+    walk::find_files_recursively(&root, &PathSpec::default(), |p| {
+        if let Ok(mut candidates) = candidates.lock() {
+            candidates.insert(p.to_path_buf());
+        }
+    });
+    let candidates = candidates.into_inner().unwrap_or_default();
+
+    let backup = state::Backup::new(&get_state_directory(&home), "relink", Some(&root));
+
+    // Files of folded directories share the same link.
+    let mut links = BTreeMap::<PathBuf, usize>::new();
+    for p in &candidates {
+        if let Some(link) = find_link(&home, p) {
+            *links.entry(link).or_default() += 1;
+        }
+    }
+
+    let mut files = Vec::new();
+    let mut nb_files_relinked = 0;
+    let mut nb_missing = 0;
+    let mut nb_errors = 0;
+
+    for (link, nb_files) in links {
+        let destination = home.join(&link);
+
+        let Some(source) = utils::read_link_absolute(&destination)
+            .ok()
+            .and_then(|target| Some(root.join(target.strip_prefix(&old_root).ok()?)))
+        else {
+            // Not linked into the previous root.
+            continue;
+        };
+
+        // Matches both files and symlinks (even broken ones).
+        if !source.exists() && !source.is_symlink() {
+            nb_missing += 1;
+            eprintln!(
+                "{warning}: Not relinking '{}': It is not in the root anymore.",
+                destination.display(),
+                warning = ui::Color::warning("warning"),
+            );
+            continue;
+        }
+
+        if !dry_run && let Err(err) = relink_file(&source, &destination, &backup) {
+            nb_errors += 1;
+            eprintln!("{err}");
+            continue;
+        }
+
+        let file = if source.is_dir() {
+            format!("{}/", link.display())
+        } else {
+            link.display().to_string()
+        };
+        files.push((file, ui::Operation::ReplaceSymlink));
+
+        nb_files_relinked += nb_files;
+    }
+
+    // The files moved with the root, and so does the record of them
+    // having been deployed. Missing ones stay behind, with their links.
+    if !dry_run {
+        let mut old_manifest = old_manifest;
+        let moved = old_manifest
+            .entries()
+            .filter(|entry| {
+                let source = root.join(&entry.root_path);
+                source.exists() || source.is_symlink()
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        if let Err(err) = update_manifest(&home, &root_id, |manifest| {
+            for entry in &moved {
+                manifest.insert(entry.clone());
+            }
+        }) {
+            nb_errors += 1;
+            eprintln!("{err}");
+        } else {
+            for entry in &moved {
+                old_manifest.remove(&entry.root_path);
+            }
+            if let Err(err) = old_manifest.save() {
+                nb_errors += 1;
+                eprintln!(
+                    "{error}: Could not update manifest of '{}': {err}",
+                    old_root.display(),
+                    error = ui::Color::error("error"),
+                );
+            }
+        }
+    }
+
+    if dry_run {
+        ui::print_operations(&files);
+    } else if verbose {
+        ui::print_files(&files.into_iter().map(|(file, _)| file).collect::<Vec<_>>());
+    }
+
+    if verbose
+        && backup.nb_files() > 0
+        && let Some(backup_dir) = backup.directory()
+    {
+        ui::print_backup_summary(backup_dir, backup.nb_files());
+    }
+
+    let plural = if nb_files_relinked == 1 { "" } else { "s" };
+    let missing = if nb_missing > 0 {
+        format!(", {nb_missing} not in root")
+    } else {
+        String::new()
+    };
+    let errors = format_errors(nb_errors);
+    if dry_run {
+        println!(
+            "Would relink {nb_files_relinked} file{plural} from '{}'{missing}{errors} (dry run).",
+            old_root.display(),
+        );
+    } else {
+        println!(
+            "Relinked {nb_files_relinked} file{plural} from '{}'{missing}{errors}.",
+            old_root.display(),
+        );
+    }
+
+    if nb_errors > 0 { Err(1) } else { Ok(()) }
+}
+
+/// The root that moved, i.e., the one root deployed into `home` that
+/// doesn't exist anymore.
+fn find_previous_root(home: &Path, root_id: &str) -> Result<Manifest, i32> {
+    let manifests = match Manifest::list(&get_state_directory(home), home) {
+        Ok(manifests) => manifests,
+        Err(err) => {
+            eprintln!(
+                "{fatal}: Could not read manifests: {err}",
+                fatal = ui::Color::error("fatal")
+            );
+            return Err(1);
+        }
+    };
+
+    let mut previous_roots = manifests
+        .into_iter()
+        .filter(|manifest| manifest.root_id() != root_id && !Path::new(manifest.root_id()).exists())
+        .collect::<Vec<_>>();
+
+    match previous_roots.len() {
+        0 => {
+            eprintln!(
+                "{fatal}: Could not find where the root was. Please provide its previous path.",
+                fatal = ui::Color::error("fatal")
+            );
+            Err(1)
+        }
+        1 => Ok(previous_roots.remove(0)),
+        _ => {
+            eprintln!(
+                "{fatal}: Several roots are gone, which one was it?",
+                fatal = ui::Color::error("fatal")
+            );
+            for manifest in previous_roots {
+                eprintln!("  {}", manifest.root_id());
+            }
+            Err(1)
+        }
+    }
+}
+
+/// The symlink `p` (relative to the root) is deployed through in the
+/// home, if any.
+///
+/// This is `p` itself, or the folded directory it is in.
+fn find_link(home: &Path, p: &Path) -> Option<PathBuf> {
+    let mut link = PathBuf::new();
+    for component in p.components() {
+        link.push(component);
+        if home.join(&link).is_symlink() {
+            return Some(link);
+        }
+    }
+    None
+}
+
+/// Point the symlink `destination` to `source`, backing up and
+/// journaling the previous link.
+fn relink_file(source: &Path, destination: &Path, backup: &state::Backup) -> Result<(), String> {
+    let target = match fs::read_link(destination) {
+        Ok(target) if target.is_relative() => destination
+            .parent()
+            .and_then(|parent| fs::canonicalize(parent).ok())
+            .map_or_else(
+                || source.to_path_buf(),
+                |dir| utils::relative_path(&dir, source),
+            ),
+        _ => source.to_path_buf(),
+    };
+
+    let backup_path = backup_file(backup, destination)?;
+
+    if let Err(err) = utils::symlink_atomically(&target, destination) {
+        return Err(format!(
+            "{error}: Could not create link to '{}': {err}",
+            source.display(),
+            error = ui::Color::error("error"),
+        ));
+    }
+
+    record_change(backup, Change::Replaced, destination, Some(&backup_path))
+}
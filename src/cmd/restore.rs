@@ -0,0 +1,167 @@
+use std::path::{self, Path, PathBuf};
+
+use deezconfigs::state::{self, Backup, Journal};
+use deezconfigs::ui;
+
+use super::common::{get_home_directory, get_state_directory};
+use super::undo::{format_errors, plan_restore, restore_file};
+
+/// Restore files from the backups of earlier runs.
+///
+/// 1. Find the most recent backup of each file (or the backup from a
+///    given run).
+/// 2. Put it back in place.
+///
+/// The current versions of the files are backed up first, so `restore`
+/// itself can be undone.
+///
+/// In dry-run mode, the files are only printed, along with what would
+/// be done to them.
+pub fn restore(
+    paths: &[String],
+    run_id: Option<&String>,
+    verbose: bool,
+    dry_run: bool,
+) -> Result<(), i32> {
+    if paths.is_empty() {
+        eprintln!(
+            "{error}: Restore what? Please provide the path of a file.",
+            error = ui::Color::error("error")
+        );
+        return Err(2);
+    }
+
+    let home = get_home_directory()?;
+    let state_dir = get_state_directory(&home);
+
+    let journals = match state::journal::list(&state_dir) {
+        Ok(journals) => journals,
+        Err(err) => {
+            eprintln!(
+                "{fatal}: Could not read backups: {err}",
+                fatal = ui::Color::error("fatal")
+            );
+            return Err(1);
+        }
+    };
+
+    if let Some(run_id) = run_id
+        && !journals.iter().any(|journal| &journal.id == run_id)
+    {
+        eprintln!(
+            "{fatal}: No run with id '{run_id}'.",
+            fatal = ui::Color::error("fatal")
+        );
+        return Err(1);
+    }
+
+    let backup = Backup::new(&state_dir, "restore", None);
+
+    let mut files = Vec::with_capacity(paths.len());
+    let mut operations = Vec::new();
+    let mut nb_files = 0;
+    let mut nb_errors = 0;
+
+    for path in paths {
+        let path = match path::absolute(path) {
+            Ok(path) => path,
+            Err(err) => {
+                nb_errors += 1;
+                eprintln!(
+                    "{error}: Invalid path '{path}': {err}",
+                    error = ui::Color::error("error")
+                );
+                continue;
+            }
+        };
+
+        let Some((previous, journal)) = find_backup(&journals, &path, run_id) else {
+            nb_errors += 1;
+            eprintln!(
+                "{error}: No backup of '{}' found.",
+                path.display(),
+                error = ui::Color::error("error")
+            );
+            continue;
+        };
+
+        if dry_run {
+            match plan_restore(&previous, &path) {
+                Ok(ui::Operation::NoOp) => (),
+                Ok(operation) => {
+                    nb_files += 1;
+                    operations.push((path.display().to_string(), operation));
+                }
+                Err(err) => {
+                    nb_errors += 1;
+                    eprintln!("{err}");
+                }
+            }
+            continue;
+        }
+
+        match restore_file(&previous, &path, &backup) {
+            // Already the same as its backup, nothing was written.
+            Ok(false) => continue,
+            Ok(true) => nb_files += 1,
+            Err(err) => {
+                nb_errors += 1;
+                eprintln!("{err}");
+                continue;
+            }
+        }
+
+        if verbose {
+            files.push(format!("{} (from run '{}')", path.display(), journal.id));
+        }
+    }
+
+    if dry_run {
+        ui::print_operations(&operations);
+        println!(
+            "Would restore {nb_files} file{}{} (dry run).",
+            if nb_files == 1 { "" } else { "s" },
+            format_errors(nb_errors),
+        );
+        return if nb_errors > 0 { Err(1) } else { Ok(()) };
+    }
+
+    ui::print_files(&files);
+
+    if verbose
+        && backup.nb_files() > 0
+        && let Some(backup_dir) = backup.directory()
+    {
+        ui::print_backup_summary(backup_dir, backup.nb_files());
+    }
+
+    println!(
+        "Restored {nb_files} file{}{}.",
+        if nb_files == 1 { "" } else { "s" },
+        format_errors(nb_errors),
+    );
+
+    if nb_errors > 0 { Err(1) } else { Ok(()) }
+}
+
+/// Find the most recent backup of `path`.
+///
+/// If `run_id` is given, only that run is considered.
+fn find_backup<'a>(
+    journals: &'a [Journal],
+    path: &Path,
+    run_id: Option<&String>,
+) -> Option<(PathBuf, &'a Journal)> {
+    journals
+        .iter()
+        .rev()
+        .filter(|journal| run_id.is_none_or(|run_id| &journal.id == run_id))
+        .find_map(|journal| {
+            journal
+                .entries
+                .iter()
+                .filter(|entry| entry.path == path)
+                .find_map(|entry| entry.backup.clone())
+                .map(|backup| (backup, journal))
+        })
+}
@@ -1,34 +1,69 @@
 use std::fs;
+use std::io;
 use std::path::Path;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 
 use deezconfigs::pathspec::PathSpec;
-use deezconfigs::{ui, walk};
+use deezconfigs::state::manifest::Divergence;
+use deezconfigs::state::{self, manifest};
+use deezconfigs::templates::Templates;
+use deezconfigs::{ui, utils};
 
 use super::common::{
-    get_home_directory, get_hooks_for_command, resolve_and_pull_config_root, resolve_config_root,
-    run_hooks,
+    OnConflict, backup_file, copy_permissions, find_folded_dir, get_home_directory,
+    get_hooks_for_command, get_state_directory, load_manifest, load_targets, load_templates,
+    local_root_id, plan_overwrite, record_change, resolve_and_pull_config_root,
+    resolve_config_root, run_hooks, update_manifest,
 };
+use super::interactive::{Decision, EditedFile, Prompter};
 
 /// Sync config from home back into root.
 ///
 /// 1. Collect all files in `configs`.
 /// 2. Find matching files in `$HOME`.
 /// 3. Replace files in `configs` with files in `$HOME`.
+///
+/// Files in `configs` that get replaced are backed up first.
+///
+/// Files that changed in the root since the last sync (e.g., after a
+/// `git pull`) are left alone, unless `on_conflict` says otherwise. If
+/// the home changed too, that is a conflict, and it is reported as an
+/// error. The manifest is updated with the new last synced versions.
+///
+/// In interactive mode, the user is asked before each file in step 3.
+///
+/// In dry-run mode, step 3. only reports what would be done.
+#[allow(clippy::too_many_lines)] // More a procedure than a function.
 pub fn rsync(
     root: Option<&String>,
     verbose: bool,
     pull_before_command: bool,
+    dry_run: bool,
+    on_conflict: OnConflict,
+    interactive: bool,
     pathspec: &PathSpec,
 ) -> Result<(), i32> {
     let root = if pull_before_command {
         resolve_and_pull_config_root(root)?
     } else {
+        // Even a dry-run runs hooks, which must come from a root the
+        // user trusts.
         resolve_config_root(root, true)?
     };
+    let root_id = local_root_id(&root);
     let home = get_home_directory()?;
-    let hooks = get_hooks_for_command(&root, &home, verbose)?;
+    let targets = load_targets(&root)?;
+    let templates = load_templates(&root)?;
+    let mut hooks = get_hooks_for_command(&root, &home, verbose)?;
+    if dry_run {
+        hooks.set_env_var("DEEZ_DRY_RUN", "true");
+    }
+
+    // The last synced version of each file, to tell which end changed.
+    let manifest = load_manifest(&home, &root_id)?;
+
+    let backup = state::Backup::new(&get_state_directory(&home), "rsync", Some(&root));
 
     let mut nb_hooks_ran = 0;
 
@@ -38,59 +73,126 @@ pub fn rsync(
     // given there are rarely _that_ many config files (and the syscalls
     // we issue are a bigger bottleneck anyway).
     let files = Arc::new(Mutex::new(Vec::with_capacity(20)));
+    let synced = Mutex::new(Vec::with_capacity(20));
+    let conflicts = Mutex::new(Vec::new());
     let nb_files_rsynced = AtomicUsize::new(0);
+    let nb_files_skipped = AtomicUsize::new(0);
     let nb_errors = AtomicUsize::new(0);
 
-    walk::find_files_recursively(&root, pathspec, |p| {
+    // Nothing to ask in a dry-run, nothing gets done.
+    let prompter = Prompter::new(interactive && !dry_run);
+
+    prompter.walk(&root, pathspec, |p| {
         debug_assert!(!p.is_dir());
 
         // Despite `rsync` working in reverse, we keep the same
         // terminology as everywhere else for consistency.
-        let source = root.join(p);
-        let destination = home.join(p);
-
-        // Note: Here won't don't worry about `source` being a directory
-        // because it can't be. If it was, `find_files_recursively()`
-        // would not yield it.
-
-        if destination.is_symlink()
-            && match does_symlink_point_to_file(&home, &destination, &source) {
-                Ok(points_to_source) => points_to_source,
-                Err(err) => {
-                    nb_errors.fetch_add(1, Ordering::Relaxed);
-                    eprintln!("{err}");
-                    return;
-                }
+        let source = match templates.source(&root, p) {
+            Ok(source) => source,
+            Err(err) => {
+                nb_errors.fetch_add(1, Ordering::Relaxed);
+                eprintln!("{error}: {err}.", error = ui::Color::error("error"));
+                return;
             }
-        {
-            // No-op: The config file is `link`ed, and so is up-to-date.
-            //
-            // If a symlink in home links to a file in configs, copying
-            // it back to configs (i.e, `cp B A` where `B@ -> A`) would
-            // (likely) truncate the file. This behaviour is documented
-            // in `std::fs::copy()` (Rust 1.86) and observed at least on
-            // macOS. This is a no-op for us since a symlink is always
-            // up-to-date.
-        } else if destination.is_file() {
-            // Follows symlinks.
-            // `fs::copy()` follows symlinks. It will create files with
-            // the contents of the symlink's target; it will not create
-            // a link.
-            if let Err(err) = fs::copy(destination, source) {
+        };
+        let destination = targets.destination(&home, p);
+
+        let entry = manifest.get(p);
+
+        // Like linked files, files of folded directories are always
+        // up-to-date: home and root share them.
+        let operation = if find_folded_dir(&root, &home, &targets, p).is_some() {
+            Ok(ui::Operation::NoOp)
+        } else {
+            plan_rsync(&source, &destination, entry, on_conflict)
+        };
+        let operation = match operation {
+            Ok(operation) => operation,
+            Err(err) => {
                 nb_errors.fetch_add(1, Ordering::Relaxed);
-                eprintln!(
-                    "{error}: Could not copy '{}' from home: {err}",
-                    p.display(),
-                    error = ui::Color::error("error"),
-                );
+                eprintln!("{err}");
                 return;
             }
+        };
+
+        // Conflicts are reported all together, once the walk is over.
+        // The manifest keeps the old base, so they stay conflicts until
+        // they are resolved.
+        if operation == ui::Operation::Conflict {
+            if let Ok(mut conflicts) = conflicts.lock() {
+                conflicts.push(p.to_string_lossy().to_string());
+            }
+            return;
+        }
+
+        // Nothing gets written, so the base stays where it is.
+        if operation == ui::Operation::Skip {
+            eprintln!(
+                "{warning}: Skipping '{}': It changed in the root since the last sync.",
+                p.display(),
+                warning = ui::Color::warning("warning"),
+            );
+            nb_files_skipped.fetch_add(1, Ordering::Relaxed);
+            if dry_run && let Ok(mut files) = files.lock() {
+                files.push((p.to_string_lossy().to_string(), operation));
+            }
+            return;
+        }
+
+        let is_overwriting = matches!(
+            operation,
+            ui::Operation::Overwrite | ui::Operation::UpdateMode
+        );
+
+        // The rendered file is thrown away, the template is what to edit.
+        if is_overwriting && Templates::is_template(p) {
+            eprintln!(
+                "{warning}: Skipping '{}': It is a template, edit it in the root.",
+                p.display(),
+                warning = ui::Color::warning("warning"),
+            );
+            return;
+        }
+
+        let mut edited = None;
+        if is_overwriting {
+            match prompter.ask(p, operation, &source, Some(&destination)) {
+                Decision::Apply(file) => edited = file,
+                Decision::Skip => return,
+            }
         }
+        // The user may have edited the file before it is rsynced. The
+        // manifest still records the home's version, so the edits show
+        // up as changes made in the root.
+        let origin = edited
+            .as_ref()
+            .map_or(destination.as_path(), EditedFile::path);
 
-        if verbose {
+        if !dry_run
+            && is_overwriting
+            && let Err(err) = rsync_file_with_backup(p, &source, origin, &backup)
+        {
+            nb_errors.fetch_add(1, Ordering::Relaxed);
+            eprintln!("{err}");
+            return;
+        }
+
+        // The home's version is now the last synced one: both ends have
+        // it. Only files `deez` copied itself are tracked.
+        if !dry_run
+            && entry.is_some_and(|entry| entry.kind == manifest::Kind::Copy)
+            && !destination.is_symlink()
+            && let Ok(entry) =
+                manifest::Entry::new(manifest::Kind::Copy, &destination, p, &destination)
+            && let Ok(mut synced) = synced.lock()
+        {
+            synced.push(entry);
+        }
+
+        if verbose || dry_run {
             let file = p.to_string_lossy().to_string();
             if let Ok(mut files) = files.lock() {
-                files.push(file);
+                files.push((file, operation));
                 // Release the lock ASAP.
                 drop(files);
             } else {
@@ -103,6 +205,20 @@ pub fn rsync(
         nb_files_rsynced.fetch_add(1, Ordering::Relaxed);
     });
 
+    if !dry_run {
+        let synced = synced.into_inner().unwrap_or_default();
+        if !synced.is_empty()
+            && let Err(err) = update_manifest(&home, &root_id, |manifest| {
+                for entry in synced {
+                    manifest.insert(entry);
+                }
+            })
+        {
+            nb_errors.fetch_add(1, Ordering::Relaxed);
+            eprintln!("{err}");
+        }
+    }
+
     let mut files = Arc::try_unwrap(files)
         .expect("processing is over, we're back to a single thread.")
         .into_inner()
@@ -112,40 +228,138 @@ pub fn rsync(
     // as per the docs.
     files.sort();
 
-    ui::print_files(&files);
+    let mut conflicts = conflicts.into_inner().unwrap_or_default();
+    conflicts.sort();
+    nb_errors.fetch_add(conflicts.len(), Ordering::Relaxed);
+
+    if dry_run {
+        ui::print_operations(&files);
+    } else {
+        ui::print_files(&files.into_iter().map(|(file, _)| file).collect::<Vec<_>>());
+    }
+
+    ui::print_conflicts(&conflicts);
+
+    if verbose
+        && backup.nb_files() > 0
+        && let Some(backup_dir) = backup.directory()
+    {
+        ui::print_backup_summary(backup_dir, backup.nb_files());
+    }
 
     nb_hooks_ran += run_hooks(|| hooks.post_rsync())?;
 
     let nb_files_rsynced = nb_files_rsynced.into_inner();
+    let nb_files_skipped = nb_files_skipped.into_inner();
     let nb_errors = nb_errors.into_inner();
 
-    ui::print_summary(
-        ui::Action::RSync,
-        &root,
-        nb_files_rsynced,
-        nb_errors,
-        nb_hooks_ran,
-    );
+    if dry_run {
+        ui::print_dry_run_summary(
+            ui::Action::RSync,
+            &root,
+            nb_files_rsynced,
+            nb_files_skipped,
+            nb_errors,
+            nb_hooks_ran,
+        );
+    } else {
+        ui::print_summary(
+            ui::Action::RSync,
+            &root,
+            nb_files_rsynced,
+            nb_files_skipped,
+            nb_errors,
+            nb_hooks_ran,
+        );
+    }
 
     if nb_errors > 0 { Err(1) } else { Ok(()) }
 }
 
+/// Determine what rsyncing `destination` back onto `source` will do.
+///
+/// Only [`Overwrite`](ui::Operation::Overwrite) and
+/// [`UpdateMode`](ui::Operation::UpdateMode) alter the root; every other
+/// case leaves it as is.
+///
+/// Files that differ are compared to their last synced version
+/// (`entry`), to find out which end changed (see [`plan_overwrite()`]).
+fn plan_rsync(
+    source: &Path,
+    destination: &Path,
+    entry: Option<&manifest::Entry>,
+    on_conflict: OnConflict,
+) -> Result<ui::Operation, String> {
+    // Note: Here won't don't worry about `source` being a directory
+    // because it can't be. If it was, `find_files_recursively()`
+    // would not yield it.
+
+    if destination.is_symlink() && does_symlink_point_to_file(destination, source)? {
+        // No-op: The config file is `link`ed, and so is up-to-date.
+        //
+        // If a symlink in home links to a file in configs, copying
+        // it back to configs (i.e, `cp B A` where `B@ -> A`) would
+        // (likely) truncate the file. This behaviour is documented
+        // in `std::fs::copy()` (Rust 1.86) and observed at least on
+        // macOS. This is a no-op for us since a symlink is always
+        // up-to-date.
+        return Ok(ui::Operation::NoOp);
+    }
+
+    // Follows symlinks.
+    if destination.is_file() {
+        let compare = || -> io::Result<ui::Operation> {
+            let divergence = Divergence::of(entry, source, destination)?;
+            let operation = plan_overwrite(divergence, Divergence::Root, on_conflict);
+            if operation == ui::Operation::NoOp
+                && !utils::are_permissions_equal(source, destination)?
+            {
+                return plan_mode_update(source, entry, on_conflict);
+            }
+            Ok(operation)
+        };
+        return compare().map_err(|err| {
+            format!(
+                "{error}: Could not compare '{}': {err}",
+                source.display(),
+                error = ui::Color::error("error"),
+            )
+        });
+    }
+
+    // Nothing to rsync back.
+    Ok(ui::Operation::NoOp)
+}
+
+/// Determine what to do with a file whose permissions only differ.
+///
+/// The home's permissions are carried back to the root, unless it is
+/// the root's that changed since the last sync (e.g., a `git pull` made
+/// a script executable), as told by the mode recorded in `entry`.
+fn plan_mode_update(
+    source: &Path,
+    entry: Option<&manifest::Entry>,
+    on_conflict: OnConflict,
+) -> io::Result<ui::Operation> {
+    if on_conflict != OnConflict::Force
+        && let Some(entry) = entry
+        && entry.kind == manifest::Kind::Copy
+        && entry.mode != manifest::SYMLINK_MODE
+        && entry.mode != manifest::file_mode(source)?
+    {
+        return Ok(ui::Operation::Skip);
+    }
+    Ok(ui::Operation::UpdateMode)
+}
+
 /// Determine if symlink in home points to file in Configs.
 ///
 /// I.e., check if a config file is `link`ed, and not `sync`ed.
-fn does_symlink_point_to_file(home: &Path, symlink: &Path, file: &Path) -> Result<bool, String> {
-    let symlink_target = match fs::read_link(symlink) {
-        Ok(target) => {
-            if target.is_relative() {
-                // If the symlink contains a _relative_ path to the
-                // target, we make it "canonicalizable" by making it
-                // absolute. Since the link lives in home, we know the
-                // path it contains is relative to home.
-                home.join(target)
-            } else {
-                target
-            }
-        }
+fn does_symlink_point_to_file(symlink: &Path, file: &Path) -> Result<bool, String> {
+    // Relative links are relative to the directory they live in (e.g.,
+    // made with `link --relative`), absolute ones are used as is.
+    let symlink_target = match utils::read_link_absolute(symlink) {
+        Ok(target) => target,
         Err(err) => {
             return Err(format!(
                 "{error}: Symbolic link is broken '{}': {err}",
@@ -178,3 +392,41 @@ fn does_symlink_point_to_file(home: &Path, symlink: &Path, file: &Path) -> Resul
 
     Ok(symlink_target == file)
 }
+
+/// Copy a single file from home back into the root.
+///
+/// The root's version is backed up and journaled first. If the backup
+/// fails, the file is left untouched.
+fn rsync_file_with_backup(
+    p: &Path,
+    source: &Path,
+    destination: &Path,
+    backup: &state::Backup,
+) -> Result<(), String> {
+    // Only called on `Overwrite` and `UpdateMode`, the content or the
+    // permissions always differ.
+    let backup_path = backup_file(backup, source)?;
+
+    // Symlinks in the root are kept, their target gets the changes.
+    let target = if source.is_symlink() {
+        fs::canonicalize(source).unwrap_or_else(|_| source.to_path_buf())
+    } else {
+        source.to_path_buf()
+    };
+
+    // Like `fs::copy()`, this follows symlinks. It will create files
+    // with the contents of the symlink's target; it will not create a
+    // link. The copy is renamed into place, so the root never holds a
+    // half-written file.
+    if let Err(err) = utils::copy_atomically(destination, &target) {
+        return Err(format!(
+            "{error}: Could not copy '{}' from home: {err}",
+            p.display(),
+            error = ui::Color::error("error"),
+        ));
+    }
+    // Carry permission changes back too (e.g., `chmod +x`).
+    copy_permissions(p, destination, source)?;
+
+    record_change(backup, state::Change::Replaced, source, Some(&backup_path))
+}
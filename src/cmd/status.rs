@@ -1,15 +1,16 @@
 use std::borrow::Cow;
-use std::cell::RefCell;
-use std::fs;
+use std::io;
 use std::path::Path;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 
 use deezconfigs::pathspec::PathSpec;
+use deezconfigs::state::manifest::Kind;
 use deezconfigs::{ui, utils, walk};
 
 use super::common::{
-    get_config_root_from_git, get_home_directory, get_hooks_for_command, is_git_remote_uri,
+    find_folded_dir, get_config_root_at_revision, get_config_root_from_git, get_home_directory,
+    get_hooks_for_command, is_git_remote_uri, load_attributes, load_targets, load_templates,
     resolve_and_pull_config_root, resolve_config_root, run_hooks,
 };
 
@@ -17,6 +18,7 @@ use super::common::{
 enum State {
     InSync,
     Modified,
+    PermissionsChanged,
     Missing,
 }
 
@@ -25,6 +27,8 @@ struct Status {
     file: String,
     state: State,
     is_symlinked: bool,
+    /// How the root declares the file to be deployed (see `deploy`).
+    strategy: Option<Kind>,
 }
 
 impl PartialOrd for Status {
@@ -45,11 +49,18 @@ impl Ord for Status {
 /// 2. Compare with files in `$HOME` to get status:
 ///    - In Sync (equal).
 ///    - Modified (not equal).
+///    - Permissions Changed (equal, but with different permissions).
 ///    - Missing (not yet copied).
+///
+/// Files with a declared strategy (`link` or `copy` attribute) show it.
+///
+/// With `rev`, step 1. collects the files of the root as of that Git
+/// revision instead.
 pub fn status(
     root: Option<&String>,
     verbose: bool,
     pull_before_command: bool,
+    rev: Option<&String>,
     pathspec: &PathSpec,
 ) -> Result<(), i32> {
     let root = if pull_before_command {
@@ -59,8 +70,19 @@ pub fn status(
     } else {
         resolve_config_root(root, false)?.into()
     };
+    // Files as of a Git revision, instead of the working tree.
+    let root = match rev {
+        Some(rev) => get_config_root_at_revision(&root, rev)?,
+        None => root,
+    };
+    // Remote roots can point to a single file too.
+    let narrowed = root.narrow_pathspec(pathspec)?;
+    let pathspec = narrowed.as_ref().unwrap_or(pathspec);
     let root: &Path = root.as_ref();
     let home = get_home_directory()?;
+    let attributes = load_attributes(root)?;
+    let targets = load_targets(root)?;
+    let templates = load_templates(root)?;
     let hooks = get_hooks_for_command(&root, &home, verbose)?;
 
     let mut nb_hooks_ran = 0;
@@ -76,20 +98,30 @@ pub fn status(
     walk::find_files_recursively(root, pathspec, |p| {
         debug_assert!(!p.is_dir());
 
-        let source = root.join(p);
-        let destination = home.join(p);
+        let source = match templates.source(root, p) {
+            Ok(source) => source,
+            Err(err) => {
+                nb_errors.fetch_add(1, Ordering::Relaxed);
+                eprintln!("{error}: {err}.", error = ui::Color::error("error"));
+                return;
+            }
+        };
+        let destination = targets.destination(&home, p);
 
         let status = Status {
             file: p.to_string_lossy().to_string(),
             state: if destination.is_file() {
-                match are_files_equal(&source, &destination) {
-                    Ok(equal) => {
-                        if equal {
-                            State::InSync
-                        } else {
-                            State::Modified
-                        }
+                let compare = || -> io::Result<State> {
+                    if !utils::are_files_equal(&source, &destination)? {
+                        return Ok(State::Modified);
+                    }
+                    if !utils::are_permissions_equal(&source, &destination)? {
+                        return Ok(State::PermissionsChanged);
                     }
+                    Ok(State::InSync)
+                };
+                match compare() {
+                    Ok(state) => state,
                     Err(err) => {
                         eprintln!(
                             "{error}: Could not compare '{}': {err}.",
@@ -102,7 +134,10 @@ pub fn status(
             } else {
                 State::Missing
             },
-            is_symlinked: destination.is_symlink(),
+            // Files of folded directories are linked through them.
+            is_symlinked: destination.is_symlink()
+                || find_folded_dir(root, &home, &targets, p).is_some(),
+            strategy: attributes.strategy(p),
         };
 
         match statuses.lock() {
@@ -147,34 +182,6 @@ pub fn status(
     }
 }
 
-fn are_files_equal(a: &Path, b: &Path) -> Result<bool, std::io::Error> {
-    // Possible improvements if this is a bottleneck:
-    //  - Compare _streaming_ bytes, to cater for big files.
-    //  - Compare hashes (e.g., xxHashes) if we do the streaming.
-    //    Streaming is slower because you have to jump back-and-forth
-    //    between files.
-
-    // 1. Compare by file size (quick).
-    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
-        return Ok(false);
-    }
-
-    // 2. Compare contents (slow; as raw bytes to avoid UTF-8 overhead).
-    thread_local! {
-        static BUFFERS: RefCell<(Vec<u8>, Vec<u8>)> = RefCell::new(
-            // 64 Kb should be plenty for the majority of config files.
-            (Vec::with_capacity(65_536), Vec::with_capacity(65_536))
-        );
-    }
-
-    BUFFERS.with_borrow_mut(|(a_buf, b_buf)| {
-        utils::read_to_bytes_buffer(a_buf, a)?;
-        utils::read_to_bytes_buffer(b_buf, b)?;
-
-        Ok(a_buf == b_buf)
-    })
-}
-
 fn print_file_statuses(statuses: &[Status]) {
     // The `Files` section is always printed because it's the core of
     // the command (unlinke `Hook`).
@@ -187,10 +194,11 @@ fn print_file_statuses(statuses: &[Status]) {
         .iter()
         .map(|s| {
             format!(
-                "  {}  {}{}",
+                "  {}  {}{}{}",
                 match &s.state {
                     State::InSync => ui::Color::in_sync("S"),
                     State::Modified => ui::Color::modified("M"),
+                    State::PermissionsChanged => ui::Color::modified("P"),
                     State::Missing => ui::Color::missing("!"),
                 },
                 s.file,
@@ -199,6 +207,10 @@ fn print_file_statuses(statuses: &[Status]) {
                 } else {
                     ui::Color::none("")
                 },
+                match s.strategy {
+                    Some(strategy) => ui::Color::strategy(&format!("  ({strategy})")).into_owned(),
+                    None => String::new(),
+                },
             )
         })
         .collect::<Vec<String>>()
@@ -221,14 +233,25 @@ fn print_hooks(hooks: &[Cow<str>]) {
 }
 
 fn print_summary_line(statuses: &[Status]) {
-    let (mut nb_in_sync, mut nb_modified, mut nb_missing) = (0, 0, 0);
+    let (mut nb_in_sync, mut nb_modified, mut nb_permissions_changed, mut nb_missing) =
+        (0, 0, 0, 0);
     for s in statuses {
         match &s.state {
             State::InSync => nb_in_sync += 1,
             State::Modified => nb_modified += 1,
+            State::PermissionsChanged => nb_permissions_changed += 1,
             State::Missing => nb_missing += 1,
         }
     }
 
-    println!("{nb_in_sync} in sync, {nb_modified} modified, {nb_missing} missing.");
+    // Rare enough that it's only mentioned if it happens.
+    let permissions_changed = if nb_permissions_changed > 0 {
+        format!(", {nb_permissions_changed} permissions changed")
+    } else {
+        String::new()
+    };
+
+    println!(
+        "{nb_in_sync} in sync, {nb_modified} modified{permissions_changed}, {nb_missing} missing."
+    );
 }
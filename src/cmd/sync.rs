@@ -1,25 +1,55 @@
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 
+use deezconfigs::attributes::Attributes;
 use deezconfigs::pathspec::PathSpec;
-use deezconfigs::{ui, walk};
+use deezconfigs::state::manifest::{self, Divergence};
+use deezconfigs::state::{self, Manifest};
+use deezconfigs::targets::Targets;
+use deezconfigs::templates::Templates;
+use deezconfigs::{ui, utils};
 
 use super::common::{
-    get_config_root_from_git, get_home_directory, get_hooks_for_command, is_git_remote_uri,
-    resolve_and_pull_config_root, resolve_config_root, run_hooks,
+    Deployment, OnConflict, backup_if_different, check_change, copy_permissions, finish_deployment,
+    get_config_root_from_git, get_home_directory, get_hooks_for_command, get_state_directory,
+    is_dir_empty, is_git_remote_uri, load_attributes, load_manifest, load_targets, load_templates,
+    plan_overwrite, record_change, resolve_and_pull_config_root, resolve_config_root, run_hooks,
 };
+use super::interactive::{Decision, EditedFile, Prompter};
 
 /// Sync config from root into home.
 ///
 /// 1. Collect all files in `configs`.
 /// 2. Create or replace matching files in `$HOME`.
-#[allow(clippy::too_many_lines)] // More a procedure than a function.
+///
+/// Files with the `link` attribute are left alone (see `deploy`).
+///
+/// Home files that get replaced are backed up first, if their content
+/// differs from the root's.
+///
+/// Files edited in the home since the last sync are left alone, unless
+/// `on_conflict` says otherwise. If the root changed too, that is a
+/// conflict, and it is reported as an error.
+///
+/// In interactive mode, the user is asked before each file in step 2.
+///
+/// In atomic mode, the run is all or nothing: if any file fails (or
+/// the `post-sync` hook does), every file touched is rolled back.
+///
+/// In dry-run mode, step 2. only reports what would be done.
+#[allow(clippy::too_many_arguments)]
 pub fn sync(
     root: Option<&String>,
     verbose: bool,
     pull_before_command: bool,
+    dry_run: bool,
+    prune: bool,
+    on_conflict: OnConflict,
+    interactive: bool,
+    atomic: bool,
     pathspec: &PathSpec,
 ) -> Result<(), i32> {
     let root = if pull_before_command {
@@ -27,139 +57,191 @@ pub fn sync(
     } else if is_git_remote_uri(root) {
         get_config_root_from_git(root.expect("not empty, contains a `git:` prefix"), verbose)?
     } else {
+        // Even a dry-run runs hooks, which must come from a root the
+        // user trusts.
         resolve_config_root(root, true)?.into()
     };
+    // Remote roots can point to a single file too.
+    let narrowed = root.narrow_pathspec(pathspec)?;
+    let pathspec = narrowed.as_ref().unwrap_or(pathspec);
+    let root_id = root.id();
     let root: &Path = root.as_ref();
     let home = get_home_directory()?;
-    let hooks = get_hooks_for_command(&root, &home, verbose)?;
+    let attributes = load_attributes(root)?;
+    let targets = load_targets(root)?;
+    let templates = load_templates(root)?;
+    let mut hooks = get_hooks_for_command(&root, &home, verbose)?;
+    if dry_run {
+        hooks.set_env_var("DEEZ_DRY_RUN", "true");
+    }
+
+    // The last synced version of each file, to tell which end changed.
+    let manifest = load_manifest(&home, &root_id)?;
+
+    let backup = state::Backup::new(&get_state_directory(&home), "sync", Some(root));
 
     let mut nb_hooks_ran = 0;
 
     nb_hooks_ran += run_hooks(|| hooks.pre_sync())?;
 
+    // Nothing to ask in a dry-run, nothing gets done.
+    let prompter = Prompter::new(interactive && !dry_run);
+
+    let deployment = sync_files(
+        root,
+        &home,
+        &attributes,
+        &targets,
+        &templates,
+        &manifest,
+        &backup,
+        &prompter,
+        on_conflict,
+        verbose,
+        dry_run,
+        pathspec,
+    );
+
+    // Nothing to roll back in a dry-run, nothing gets done.
+    let atomic = atomic && !dry_run;
+
+    finish_deployment(
+        ui::Action::Sync,
+        root,
+        &home,
+        &targets,
+        &root_id,
+        pathspec,
+        deployment,
+        &backup,
+        atomic.then_some(&manifest),
+        prune,
+        verbose,
+        dry_run,
+        nb_hooks_ran,
+        || run_hooks(|| hooks.post_sync()),
+    )
+}
+
+/// Sync the files of `root` into `home` (steps 1. and 2. of `sync()`).
+///
+/// Files with the `link` attribute are left alone. The files are only
+/// deployed: it's up to the caller to record and report them (see
+/// [`finish_deployment()`]).
+#[allow(clippy::too_many_lines)] // More a procedure than a function.
+#[allow(clippy::too_many_arguments)]
+pub fn sync_files(
+    root: &Path,
+    home: &Path,
+    attributes: &Attributes,
+    targets: &Targets,
+    templates: &Templates,
+    manifest: &Manifest,
+    backup: &state::Backup,
+    prompter: &Prompter,
+    on_conflict: OnConflict,
+    verbose: bool,
+    dry_run: bool,
+    pathspec: &PathSpec,
+) -> Deployment {
     // There will be high contention, but it likely won't matter much
     // given there are rarely _that_ many config files (and the syscalls
     // we issue are a bigger bottleneck anyway).
     let files = Arc::new(Mutex::new(Vec::with_capacity(20)));
+    let deployed = Mutex::new(Vec::with_capacity(20));
+    let conflicts = Mutex::new(Vec::new());
     let nb_files_synced = AtomicUsize::new(0);
+    let nb_files_skipped = AtomicUsize::new(0);
     let nb_errors = AtomicUsize::new(0);
 
-    walk::find_files_recursively(root, pathspec, |p| {
+    prompter.walk(root, pathspec, |p| {
         debug_assert!(!p.is_dir());
 
-        let source = root.join(p);
-        let destination = home.join(p);
+        // Declared as a link, it's for `link` to deploy. Templates are
+        // always copied, there's nothing to link to.
+        if attributes.strategy(p) == Some(manifest::Kind::Link) && !Templates::is_template(p) {
+            return;
+        }
 
-        if destination.is_dir() {
-            // If destination exists and is a directory, try to `rmdir`
-            // it. If it works, the directory was empty anyway. If it
-            // doesn't work, the directory is not empty so we abort
-            // because it is too risky to remove an entire tree.
-            if let Err(err) = fs::remove_dir(&destination) {
+        let source = match templates.source(root, p) {
+            Ok(source) => source,
+            Err(err) => {
                 nb_errors.fetch_add(1, Ordering::Relaxed);
-                eprintln!(
-                    "{error}: Could not remove exising directory '{}': {err}",
-                    destination.display(),
-                    error = ui::Color::error("error"),
-                );
+                eprintln!("{error}: {err}.", error = ui::Color::error("error"));
                 return;
             }
+        };
+        let destination = targets.destination(home, p);
+
+        let operation = match plan_sync(&source, &destination, manifest.get(p), on_conflict) {
+            Ok(operation) => operation,
+            Err(err) => {
+                nb_errors.fetch_add(1, Ordering::Relaxed);
+                eprintln!("{err}");
+                return;
+            }
+        };
+
+        // Conflicts are reported all together, once the walk is over.
+        // The manifest keeps the old base, so they stay conflicts until
+        // they are resolved.
+        if operation == ui::Operation::Conflict {
+            if let Ok(mut conflicts) = conflicts.lock() {
+                conflicts.push(p.to_string_lossy().to_string());
+            }
+            return;
         }
 
-        if let Err(err) = fs::create_dir_all(
-            destination
-                .parent()
-                .expect("at the bare minimum, `parent` is `$HOME`"),
-        ) {
-            nb_errors.fetch_add(1, Ordering::Relaxed);
+        // Nothing gets written, so the base stays where it is.
+        if operation == ui::Operation::Skip {
             eprintln!(
-                "{error}: Could not copy '{}' to home: {err}",
+                "{warning}: Skipping '{}': It changed in the home since the last sync.",
                 p.display(),
-                error = ui::Color::error("error"),
+                warning = ui::Color::warning("warning"),
             );
+            nb_files_skipped.fetch_add(1, Ordering::Relaxed);
+            if dry_run && let Ok(mut files) = files.lock() {
+                files.push((p.to_string_lossy().to_string(), operation));
+            }
             return;
         }
 
-        // If _source_ is a symlink, copy the link, _not_ the contents.
-        // We want to _mirror_ what the user has, not interpret what he
-        // might have wanted to do.
-        //
-        // `fs::copy()` follows symlinks. It will create files with the
-        // contents of the symlink's target; it will not create a link.
-        if source.is_symlink() {
-            // If destination exists we must _delete_ it before the
-            // copy, because symlinks don't override existing files.
-            if destination.is_file() {
-                // Matches both files and symlinks.
-                if let Err(err) = fs::remove_file(&destination) {
-                    nb_errors.fetch_add(1, Ordering::Relaxed);
-                    eprintln!(
-                        "{error}: Could not remove exising file '{}': {err}",
-                        destination.display(),
-                        error = ui::Color::error("error"),
-                    );
-                    return;
-                }
-            }
+        let is_noop = operation == ui::Operation::NoOp;
 
-            let target: PathBuf = match fs::read_link(&source) {
-                Ok(target) => target,
-                Err(err) => {
-                    nb_errors.fetch_add(1, Ordering::Relaxed);
-                    eprintln!(
-                        "{error}: Could not read symlink '{}': {err}",
-                        p.display(),
-                        error = ui::Color::error("error"),
-                    );
-                    return;
-                }
-            };
-
-            #[cfg(unix)]
-            let res = std::os::unix::fs::symlink(&target, &destination);
-            #[cfg(windows)]
-            let res = std::os::windows::fs::symlink_file(&target, &destination);
-
-            if let Err(err) = res {
-                nb_errors.fetch_add(1, Ordering::Relaxed);
-                eprintln!(
-                    "{error}: Could not create symlink '{}': {err}",
-                    p.display(),
-                    error = ui::Color::error("error"),
-                );
-                return;
-            }
-        } else {
-            // If destination exists and is a symlink, we must _delete_
-            // it before the copy, or else it would override the link's
-            // target.
-            if destination.is_symlink()
-                && let Err(err) = fs::remove_file(&destination)
-            {
-                nb_errors.fetch_add(1, Ordering::Relaxed);
-                eprintln!(
-                    "{error}: Could not remove exising symlink '{}': {err}",
-                    destination.display(),
-                    error = ui::Color::error("error"),
-                );
-                return;
+        let mut edited = None;
+        if !is_noop {
+            match prompter.ask(p, operation, &destination, Some(&source)) {
+                Decision::Apply(file) => edited = file,
+                Decision::Skip => return,
             }
+        }
+        // The user may have edited the file before it is synced. The
+        // manifest still records the root's version, so the edits show
+        // up as changes made in the home.
+        let origin = edited.as_ref().map_or(source.as_path(), EditedFile::path);
 
-            if let Err(err) = fs::copy(source, destination) {
-                nb_errors.fetch_add(1, Ordering::Relaxed);
-                eprintln!(
-                    "{error}: Could not copy '{}' to home: {err}",
-                    p.display(),
-                    error = ui::Color::error("error"),
-                );
-                return;
-            }
+        if !dry_run
+            && !is_noop
+            && let Err(err) = sync_file_with_backup(p, origin, &destination, operation, backup)
+        {
+            nb_errors.fetch_add(1, Ordering::Relaxed);
+            eprintln!("{err}");
+            return;
         }
 
-        if verbose {
+        // Files that can't be hashed (e.g., broken symlinks) are left out
+        // of the manifest; they can't be checked for changes anyway.
+        if !dry_run
+            && let Ok(entry) = manifest::Entry::new(manifest::Kind::Copy, &source, p, &destination)
+            && let Ok(mut deployed) = deployed.lock()
+        {
+            deployed.push(entry);
+        }
+
+        if verbose || dry_run {
             let file = p.to_string_lossy().to_string();
             if let Ok(mut files) = files.lock() {
-                files.push(file);
+                files.push((file, operation));
                 // Release the lock ASAP.
                 drop(files);
             } else {
@@ -172,29 +254,191 @@ pub fn sync(
         nb_files_synced.fetch_add(1, Ordering::Relaxed);
     });
 
-    let mut files = Arc::try_unwrap(files)
-        .expect("processing is over, we're back to a single thread.")
-        .into_inner()
-        .unwrap();
-    // Do not use `sort_unstable()` because the files are likely
-    // _partially_ sorted, in which case stable sort is faster,
-    // as per the docs.
-    files.sort();
+    Deployment {
+        files: Arc::try_unwrap(files)
+            .expect("processing is over, we're back to a single thread.")
+            .into_inner()
+            .unwrap(),
+        conflicts: conflicts.into_inner().unwrap_or_default(),
+        entries: deployed.into_inner().unwrap_or_default(),
+        nb_files: nb_files_synced.into_inner(),
+        nb_skipped: nb_files_skipped.into_inner(),
+        nb_errors: nb_errors.into_inner(),
+    }
+}
 
-    ui::print_files(&files);
+/// Determine what syncing `source` onto `destination` will do.
+///
+/// This mirrors the branches of [`sync_file()`], without touching the
+/// file system. It errors in the same cases `sync_file()` would error
+/// before writing anything (e.g., a non-empty directory in the way).
+///
+/// Regular files that differ are compared to their last synced version
+/// (`entry`), to find out which end changed (see [`plan_overwrite()`]).
+fn plan_sync(
+    source: &Path,
+    destination: &Path,
+    entry: Option<&manifest::Entry>,
+    on_conflict: OnConflict,
+) -> Result<ui::Operation, String> {
+    if destination.is_dir() && !destination.is_symlink() {
+        if !is_dir_empty(destination) {
+            return Err(format!(
+                "{error}: Could not remove exising directory '{}': Directory not empty",
+                destination.display(),
+                error = ui::Color::error("error"),
+            ));
+        }
+        return Ok(ui::Operation::RemoveDir);
+    }
 
-    nb_hooks_ran += run_hooks(|| hooks.post_sync())?;
+    if source.is_symlink() {
+        if destination.is_symlink() {
+            // Same link, same target: nothing changes.
+            if fs::read_link(source).ok() == fs::read_link(destination).ok() {
+                return Ok(ui::Operation::NoOp);
+            }
+            return Ok(ui::Operation::ReplaceSymlink);
+        }
+        if destination.is_file() {
+            return Ok(ui::Operation::Overwrite);
+        }
+        return Ok(ui::Operation::Create);
+    }
 
-    let nb_files_synced = nb_files_synced.into_inner();
-    let nb_errors = nb_errors.into_inner();
+    if destination.is_symlink() {
+        return Ok(ui::Operation::ReplaceSymlink);
+    }
+    if destination.is_file() {
+        let compare = || -> io::Result<ui::Operation> {
+            let divergence = Divergence::of(entry, source, destination)?;
+            let operation = plan_overwrite(divergence, Divergence::Home, on_conflict);
+            // The root's permissions always win, even if only they differ.
+            if operation == ui::Operation::NoOp
+                && !utils::are_permissions_equal(source, destination)?
+            {
+                return Ok(ui::Operation::UpdateMode);
+            }
+            Ok(operation)
+        };
+        return compare().map_err(|err| {
+            format!(
+                "{error}: Could not compare '{}': {err}",
+                source.display(),
+                error = ui::Color::error("error"),
+            )
+        });
+    }
 
-    ui::print_summary(
-        ui::Action::Sync,
-        root,
-        nb_files_synced,
-        nb_errors,
-        nb_hooks_ran,
+    Ok(ui::Operation::Create)
+}
+
+/// Sync a single file, backing up and journaling what it replaces.
+///
+/// If the backup fails, the file is left untouched: better not sync it
+/// than lose it.
+fn sync_file_with_backup(
+    p: &Path,
+    source: &Path,
+    destination: &Path,
+    operation: ui::Operation,
+    backup: &state::Backup,
+) -> Result<(), String> {
+    let is_replacing = matches!(
+        operation,
+        ui::Operation::Overwrite | ui::Operation::UpdateMode | ui::Operation::ReplaceSymlink
     );
 
-    if nb_errors > 0 { Err(1) } else { Ok(()) }
+    check_change(backup, destination)?;
+    let backup_path = if is_replacing {
+        backup_if_different(backup, source, destination)?
+    } else {
+        None
+    };
+
+    sync_file(p, source, destination)?;
+
+    let change = if is_replacing {
+        state::Change::Replaced
+    } else {
+        state::Change::Created
+    };
+    record_change(backup, change, destination, backup_path.as_deref())
+}
+
+/// Sync a single file from root into home.
+///
+/// `p` is the path of the file relative to the root; it is only used
+/// in error messages.
+fn sync_file(p: &Path, source: &Path, destination: &Path) -> Result<(), String> {
+    if destination.is_dir() && !destination.is_symlink() {
+        // If destination exists and is a directory, try to `rmdir`
+        // it. If it works, the directory was empty anyway. If it
+        // doesn't work, the directory is not empty so we abort
+        // because it is too risky to remove an entire tree.
+        if let Err(err) = fs::remove_dir(destination) {
+            return Err(format!(
+                "{error}: Could not remove exising directory '{}': {err}",
+                destination.display(),
+                error = ui::Color::error("error"),
+            ));
+        }
+    }
+
+    if let Err(err) = fs::create_dir_all(
+        destination
+            .parent()
+            .expect("at the bare minimum, `parent` is `$HOME`"),
+    ) {
+        return Err(format!(
+            "{error}: Could not copy '{}' to home: {err}",
+            p.display(),
+            error = ui::Color::error("error"),
+        ));
+    }
+
+    // If _source_ is a symlink, copy the link, _not_ the contents.
+    // We want to _mirror_ what the user has, not interpret what he
+    // might have wanted to do.
+    //
+    // `fs::copy()` follows symlinks. It will create files with the
+    // contents of the symlink's target; it will not create a link.
+    //
+    // Either way, the new file is written next to the destination,
+    // then renamed over it. This replaces files and symlinks alike
+    // (even broken ones), and never leaves a half-written file behind.
+    if source.is_symlink() {
+        let target: PathBuf = match fs::read_link(source) {
+            Ok(target) => target,
+            Err(err) => {
+                return Err(format!(
+                    "{error}: Could not read symlink '{}': {err}",
+                    p.display(),
+                    error = ui::Color::error("error"),
+                ));
+            }
+        };
+
+        if let Err(err) = utils::symlink_atomically(&target, destination) {
+            return Err(format!(
+                "{error}: Could not create symlink '{}': {err}",
+                p.display(),
+                error = ui::Color::error("error"),
+            ));
+        }
+    } else {
+        if let Err(err) = utils::copy_atomically(source, destination) {
+            return Err(format!(
+                "{error}: Could not copy '{}' to home: {err}",
+                p.display(),
+                error = ui::Color::error("error"),
+            ));
+        }
+
+        // The copy happens to carry the permissions over too, but we rely
+        // on it (e.g., `0600` for secrets, `+x` for scripts): make sure.
+        copy_permissions(p, source, destination)?;
+    }
+
+    Ok(())
 }
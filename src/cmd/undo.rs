@@ -0,0 +1,364 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use deezconfigs::state::manifest::Manifest;
+use deezconfigs::state::{self, Backup, Change, Journal, backup};
+use deezconfigs::ui;
+
+use super::common::{
+    backup_file, check_change, get_home_directory, get_state_directory, record_change,
+    remove_file_with_backup,
+};
+
+/// Revert the most recent run.
+///
+/// 1. Find the journal of the latest run that hasn't been undone yet.
+/// 2. Walk its changes backwards, and revert them one by one:
+///    - Delete files that were created.
+///    - Restore files that were replaced or removed from their backup.
+/// 3. Forget what the run recorded in the manifests about these files.
+/// 4. Mark the run as undone, so the next `undo` reverts the one before.
+///
+/// Files that changed since the run are backed up before being reverted
+/// (in a run of their own), so `undo` never loses anything either.
+///
+/// In dry-run mode, the changes are only printed, and the run is not
+/// marked as undone.
+pub fn undo(verbose: bool, dry_run: bool) -> Result<(), i32> {
+    let home = get_home_directory()?;
+    let state_dir = get_state_directory(&home);
+
+    let journals = match state::journal::list(&state_dir) {
+        Ok(journals) => journals,
+        Err(err) => {
+            eprintln!(
+                "{fatal}: Could not read backups: {err}",
+                fatal = ui::Color::error("fatal")
+            );
+            return Err(1);
+        }
+    };
+
+    // Undoing an `undo` would be a redo, and redoing is confusing. So
+    // undos are skipped, and each `undo` goes one run further back.
+    let Some(mut journal) = journals
+        .into_iter()
+        .rev()
+        .find(|journal| !journal.is_undone && journal.command != "undo")
+    else {
+        println!("Nothing to undo.");
+        return Ok(());
+    };
+
+    let root = journal.root.as_deref().map(Path::new);
+    let backup = Backup::new(&state_dir, "undo", root);
+
+    let (mut files, mut nb_errors) = undo_changes(&home, &journal, &backup, dry_run);
+    files.sort();
+    let nb_files = files.len();
+
+    if dry_run {
+        ui::print_operations(&files);
+        println!(
+            "Would revert {} file{} from `{}` run '{}'{} (dry run).",
+            files.len(),
+            if files.len() == 1 { "" } else { "s" },
+            journal.command,
+            journal.id,
+            format_errors(nb_errors),
+        );
+        return if nb_errors > 0 { Err(1) } else { Ok(()) };
+    }
+
+    if verbose {
+        let files: Vec<String> = files.into_iter().map(|(file, _)| file).collect();
+        ui::print_files(&files);
+
+        if backup.nb_files() > 0
+            && let Some(backup_dir) = backup.directory()
+        {
+            ui::print_backup_summary(backup_dir, backup.nb_files());
+        }
+    }
+
+    if let Err(err) = forget_reverted_files(&home, &journal) {
+        nb_errors += 1;
+        eprintln!("{err}");
+    }
+
+    // If something went wrong, leave the run as is, so the user can fix
+    // the problem and try again.
+    if nb_errors == 0
+        && let Err(err) = journal.mark_undone()
+    {
+        nb_errors += 1;
+        eprintln!(
+            "{error}: Could not mark run '{}' as undone: {err}",
+            journal.id,
+            error = ui::Color::error("error"),
+        );
+    }
+
+    print_undo_summary(&journal, nb_files, nb_errors);
+
+    if nb_errors > 0 { Err(1) } else { Ok(()) }
+}
+
+/// Revert a run that failed midway, as if it never happened.
+///
+/// This is `undo`, on the spot, for `--atomic` runs: the files `run`
+/// touched so far are put back the way they were, and the manifest is
+/// reset to `manifest`, its state before the run.
+pub fn roll_back(home: &Path, run: &Backup, manifest: &Manifest) {
+    roll_back_run(home, run.directory(), manifest);
+}
+
+/// Revert a run that already completed, given its directory (if any).
+///
+/// Same as [`roll_back()`], for runs whose [`Backup`] is gone (e.g.,
+/// the earlier layers of a layered run).
+pub fn roll_back_run(home: &Path, run_dir: Option<&Path>, manifest: &Manifest) {
+    let mut nb_files = 0;
+    let mut nb_errors = 0;
+
+    if let Err(err) = manifest.save() {
+        nb_errors += 1;
+        eprintln!(
+            "{error}: Could not restore manifest: {err}",
+            error = ui::Color::error("error"),
+        );
+    }
+
+    // No run directory, nothing was touched.
+    if let Some(run_dir) = run_dir {
+        match Journal::read(run_dir) {
+            Ok(mut journal) => {
+                let root = journal.root.as_deref().map(Path::new);
+                let backup = Backup::new(&get_state_directory(home), "undo", root);
+
+                let (files, nb_undo_errors) = undo_changes(home, &journal, &backup, false);
+                nb_files = files.len();
+                nb_errors += nb_undo_errors;
+
+                // Like `undo`, leave the run as is if something went
+                // wrong, so the user can `undo` it later.
+                if nb_undo_errors == 0
+                    && let Err(err) = journal.mark_undone()
+                {
+                    nb_errors += 1;
+                    eprintln!(
+                        "{error}: Could not mark run '{}' as undone: {err}",
+                        journal.id,
+                        error = ui::Color::error("error"),
+                    );
+                }
+            }
+            Err(err) => {
+                nb_errors += 1;
+                eprintln!(
+                    "{error}: Could not read journal of run '{}': {err}",
+                    run_dir.display(),
+                    error = ui::Color::error("error"),
+                );
+            }
+        }
+    }
+
+    println!(
+        "Rolled back {nb_files} file{}{}.",
+        if nb_files == 1 { "" } else { "s" },
+        format_errors(nb_errors),
+    );
+}
+
+/// Revert the changes of `journal`, most recent first.
+///
+/// In dry-run mode, nothing is reverted, the changes are only planned.
+///
+/// Returns the files that were (or would be) altered, how, and the
+/// number of errors.
+fn undo_changes(
+    home: &Path,
+    journal: &Journal,
+    backup: &Backup,
+    dry_run: bool,
+) -> (Vec<(String, ui::Operation)>, usize) {
+    let mut files = Vec::with_capacity(journal.entries.len());
+    let mut nb_errors = 0;
+
+    for entry in journal.entries.iter().rev() {
+        let res = plan_undo_change(entry).and_then(|operation| match operation {
+            ui::Operation::NoOp => Ok(operation),
+            _ if dry_run => Ok(operation),
+            _ => undo_change(home, entry, operation, backup).map(|()| operation),
+        });
+        match res {
+            Ok(ui::Operation::NoOp) => (),
+            Ok(operation) => files.push((entry.path.display().to_string(), operation)),
+            Err(err) => {
+                nb_errors += 1;
+                eprintln!("{err}");
+            }
+        }
+    }
+
+    (files, nb_errors)
+}
+
+/// Remove the manifest entries of the files changed by `journal`.
+///
+/// The run recorded the versions it deployed as the files' base, but
+/// they are gone now. Left in place, the next `sync` would take the
+/// reverted files for edits made in the home, and skip them. Without a
+/// base, it deploys them again.
+fn forget_reverted_files(home: &Path, journal: &Journal) -> Result<(), String> {
+    let reverted: Vec<&Path> = journal
+        .entries
+        .iter()
+        .map(|entry| entry.path.as_path())
+        .collect();
+
+    let error = |err: io::Error| {
+        format!(
+            "{error}: Could not update manifest: {err}",
+            error = ui::Color::error("error"),
+        )
+    };
+
+    for mut manifest in Manifest::list(&get_state_directory(home), home).map_err(error)? {
+        // `rsync` changes the root files, the others the home files.
+        let root = Path::new(manifest.root_id());
+        let forgotten: Vec<PathBuf> = manifest
+            .entries()
+            .filter(|entry| {
+                reverted.contains(&entry.home_path.as_path())
+                    || reverted.contains(&root.join(&entry.root_path).as_path())
+            })
+            .map(|entry| entry.root_path.clone())
+            .collect();
+
+        if forgotten.is_empty() {
+            continue;
+        }
+        for root_path in &forgotten {
+            manifest.remove(root_path);
+        }
+        manifest.save().map_err(error)?;
+    }
+
+    Ok(())
+}
+
+/// What reverting a single change does to its file.
+fn plan_undo_change(entry: &state::journal::Entry) -> Result<ui::Operation, String> {
+    let path = &entry.path;
+
+    match (entry.change, &entry.backup) {
+        // Matches both files and symlinks (even broken ones).
+        (Change::Created, _) if path.is_file() || path.is_symlink() => Ok(ui::Operation::Delete),
+        (Change::Created, _) => Ok(ui::Operation::NoOp),
+        (Change::Replaced | Change::Removed, Some(previous)) => plan_restore(previous, path),
+        // Replaced by identical content, there is nothing to restore.
+        (Change::Replaced | Change::Removed, None) => Ok(ui::Operation::NoOp),
+    }
+}
+
+/// Revert a single change, as planned by [`plan_undo_change()`].
+fn undo_change(
+    home: &Path,
+    entry: &state::journal::Entry,
+    operation: ui::Operation,
+    backup: &Backup,
+) -> Result<(), String> {
+    match (operation, &entry.backup) {
+        (ui::Operation::Delete, _) => remove_file_with_backup(home, &entry.path, backup),
+        (_, Some(previous)) => restore_file(previous, &entry.path, backup).map(|_| ()),
+        (_, None) => Ok(()),
+    }
+}
+
+/// What putting `previous` back in place of `path` does to `path`.
+///
+/// # Errors
+///
+/// Errors if a directory stands in the way.
+pub fn plan_restore(previous: &Path, path: &Path) -> Result<ui::Operation, String> {
+    // Matches both files and symlinks (even broken ones).
+    if path.is_file() || path.is_symlink() {
+        return Ok(if backup::is_same_as_backup(path, previous) {
+            ui::Operation::NoOp
+        } else {
+            ui::Operation::Overwrite
+        });
+    }
+
+    if path.is_dir() {
+        return Err(format!(
+            "{error}: Could not restore '{}': A directory is in the way",
+            path.display(),
+            error = ui::Color::error("error"),
+        ));
+    }
+
+    Ok(ui::Operation::Create)
+}
+
+/// Put `previous` back in place of `path`.
+///
+/// Whatever `path` currently holds is backed up first, unless it is
+/// already the same as `previous`.
+///
+/// Returns whether `path` was altered.
+pub fn restore_file(previous: &Path, path: &Path, backup: &Backup) -> Result<bool, String> {
+    check_change(backup, path)?;
+
+    let operation = plan_restore(previous, path)?;
+    let exists = match operation {
+        ui::Operation::NoOp => return Ok(false),
+        ui::Operation::Create => false,
+        _ => true,
+    };
+    let backup_path = if exists {
+        Some(backup_file(backup, path)?)
+    } else {
+        None
+    };
+
+    if let Err(err) = backup::restore(previous, path) {
+        return Err(format!(
+            "{error}: Could not restore '{}': {err}",
+            path.display(),
+            error = ui::Color::error("error"),
+        ));
+    }
+
+    let change = if exists {
+        Change::Replaced
+    } else {
+        Change::Created
+    };
+    record_change(backup, change, path, backup_path.as_deref())?;
+
+    Ok(true)
+}
+
+fn print_undo_summary(journal: &Journal, nb_files: usize, nb_errors: usize) {
+    println!(
+        "Reverted {nb_files} file{} from `{}` run '{}'{}.",
+        if nb_files == 1 { "" } else { "s" },
+        journal.command,
+        journal.id,
+        format_errors(nb_errors),
+    );
+}
+
+/// Format the error count of a summary (e.g., ", 2 errors"), if any.
+pub fn format_errors(nb_errors: usize) -> String {
+    if nb_errors == 0 {
+        return String::new();
+    }
+    format!(
+        ", {nb_errors} error{}",
+        if nb_errors == 1 { "" } else { "s" }
+    )
+}
@@ -0,0 +1,152 @@
+use std::collections::BTreeSet;
+
+use deezconfigs::pathspec::PathSpec;
+use deezconfigs::state::{self, manifest};
+use deezconfigs::{ui, walk};
+
+use super::common::{
+    find_folded_dir, get_home_directory, get_state_directory, is_link_to, load_targets,
+    local_root_id, materialize_folded_dir, materialize_link, resolve_and_pull_config_root,
+    resolve_config_root, update_manifest,
+};
+use super::undo::format_errors;
+
+/// Turn linked config files into copies.
+///
+/// 1. Collect all files in `configs`.
+/// 2. Replace the symlinks in `$HOME` that point to them by copies of
+///    the files. Folded directories are replaced by real directories.
+///
+/// Symlinks that point elsewhere, and regular files, are left as is.
+///
+/// The symlinks are backed up first, so `undo` can link them back.
+///
+/// In dry-run mode, step 2. only reports what would be done.
+#[allow(clippy::too_many_lines)] // More a procedure than a function.
+pub fn unlink(
+    root: Option<&String>,
+    verbose: bool,
+    pull_before_command: bool,
+    dry_run: bool,
+    pathspec: &PathSpec,
+) -> Result<(), i32> {
+    let root = if pull_before_command {
+        resolve_and_pull_config_root(root)?
+    } else {
+        // A dry-run doesn't alter the file system, no need to check.
+        resolve_config_root(root, !dry_run)?
+    };
+    let root_id = local_root_id(&root);
+    let home = get_home_directory()?;
+    let targets = load_targets(&root)?;
+
+    let backup = state::Backup::new(&get_state_directory(&home), "unlink", Some(root.as_ref()));
+
+    let files = walk::collect_files_recursively(&root, pathspec);
+
+    let mut unlinked = Vec::with_capacity(files.len());
+    let mut deployed = Vec::with_capacity(files.len());
+    let mut unfolded = BTreeSet::new();
+    let mut nb_errors = 0;
+
+    for p in &files {
+        let source = root.join(p);
+        let destination = targets.destination(&home, p);
+
+        // Folded directories are unlinked as a whole, or their other
+        // files would lose their link.
+        if let Some(dir) = find_folded_dir(&root, &home, &targets, p) {
+            // Its files are all copied along with it, the first time.
+            if !unfolded.contains(&dir) {
+                let dir_files = walk::collect_files_recursively(
+                    &root,
+                    &PathSpec::parse(&[dir.display().to_string()]).unwrap_or_default(),
+                );
+                if !dir_files.iter().all(|file| files.contains(file)) {
+                    nb_errors += 1;
+                    eprintln!(
+                        "{error}: Could not unlink '{}': It is in folded directory '{}', unlink the whole directory instead.",
+                        destination.display(),
+                        targets.destination(&home, &dir).display(),
+                        error = ui::Color::error("error"),
+                    );
+                    continue;
+                }
+                if !dry_run
+                    && let Err(err) =
+                        materialize_folded_dir(&root, &home, &targets, &dir, &dir_files, &backup)
+                {
+                    nb_errors += 1;
+                    eprintln!("{err}");
+                    continue;
+                }
+                unfolded.insert(dir);
+            }
+        } else if destination.is_symlink() && is_link_to(&destination, &source) {
+            if !dry_run && let Err(err) = materialize_link(&source, &destination, &backup) {
+                nb_errors += 1;
+                eprintln!("{err}");
+                continue;
+            }
+        } else {
+            // Not linked from the root, nothing to do.
+            continue;
+        }
+
+        // The files are copies now, the manifest must know it (or it
+        // would take them for links that were replaced).
+        if !dry_run
+            && let Ok(entry) = manifest::Entry::new(manifest::Kind::Copy, &source, p, &destination)
+        {
+            deployed.push(entry);
+        }
+
+        unlinked.push(p.display().to_string());
+    }
+
+    if !dry_run
+        && let Err(err) = update_manifest(&home, &root_id, |manifest| {
+            for entry in deployed {
+                manifest.insert(entry);
+            }
+        })
+    {
+        nb_errors += 1;
+        eprintln!("{err}");
+    }
+
+    if dry_run {
+        ui::print_operations(
+            &unlinked
+                .iter()
+                .map(|file| (file.clone(), ui::Operation::ReplaceSymlink))
+                .collect::<Vec<_>>(),
+        );
+    } else if verbose {
+        ui::print_files(&unlinked);
+    }
+
+    if verbose
+        && backup.nb_files() > 0
+        && let Some(backup_dir) = backup.directory()
+    {
+        ui::print_backup_summary(backup_dir, backup.nb_files());
+    }
+
+    let nb_files = unlinked.len();
+    let plural = if nb_files == 1 { "" } else { "s" };
+    let errors = format_errors(nb_errors);
+    if dry_run {
+        println!(
+            "Would unlink {nb_files} file{plural} from '{}'{errors} (dry run).",
+            root.display()
+        );
+    } else {
+        println!(
+            "Unlinked {nb_files} file{plural} from '{}'{errors}.",
+            root.display()
+        );
+    }
+
+    if nb_errors > 0 { Err(1) } else { Ok(()) }
+}
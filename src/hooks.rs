@@ -6,13 +6,15 @@ use std::{env, process};
 
 use crate::ui;
 
-const HOOKS: [&str; 12] = [
+const HOOKS: [&str; 14] = [
     "pre-sync",
     "post-sync",
     "pre-rsync",
     "post-rsync",
     "pre-link",
     "post-link",
+    "pre-deploy",
+    "post-deploy",
     "pre-status",
     "post-status",
     "pre-diff",
@@ -36,6 +38,8 @@ struct Scripts {
     post_rsync: Vec<PathBuf>,
     pre_link: Vec<PathBuf>,
     post_link: Vec<PathBuf>,
+    pre_deploy: Vec<PathBuf>,
+    post_deploy: Vec<PathBuf>,
     pre_status: Vec<PathBuf>,
     post_status: Vec<PathBuf>,
     pre_diff: Vec<PathBuf>,
@@ -76,6 +80,8 @@ impl<'a> Hooks<'a> {
                 post_rsync: Vec::new(),
                 pre_link: Vec::new(),
                 post_link: Vec::new(),
+                pre_deploy: Vec::new(),
+                post_deploy: Vec::new(),
                 pre_status: Vec::new(),
                 post_status: Vec::new(),
                 pre_diff: Vec::new(),
@@ -124,6 +130,8 @@ impl<'a> Hooks<'a> {
                 Some("post-rsync") => hooks.scripts.post_rsync.push(entry.to_path_buf()),
                 Some("pre-link") => hooks.scripts.pre_link.push(entry.to_path_buf()),
                 Some("post-link") => hooks.scripts.post_link.push(entry.to_path_buf()),
+                Some("pre-deploy") => hooks.scripts.pre_deploy.push(entry.to_path_buf()),
+                Some("post-deploy") => hooks.scripts.post_deploy.push(entry.to_path_buf()),
                 Some("pre-status") => hooks.scripts.pre_status.push(entry.to_path_buf()),
                 Some("post-status") => hooks.scripts.post_status.push(entry.to_path_buf()),
                 Some("pre-diff") => hooks.scripts.pre_diff.push(entry.to_path_buf()),
@@ -149,6 +157,8 @@ impl<'a> Hooks<'a> {
         hooks.scripts.post_rsync.sort();
         hooks.scripts.pre_link.sort();
         hooks.scripts.post_link.sort();
+        hooks.scripts.pre_deploy.sort();
+        hooks.scripts.post_deploy.sort();
         hooks.scripts.pre_status.sort();
         hooks.scripts.post_status.sort();
         hooks.scripts.pre_diff.sort();
@@ -250,6 +260,28 @@ impl<'a> Hooks<'a> {
         self.run_hooks(&self.scripts.post_link)
     }
 
+    /// Run "pre-deploy" hooks.
+    ///
+    /// Returns the number of hooks that ran.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `sh` executable cannot be found.
+    pub fn pre_deploy(&self) -> Result<usize, String> {
+        self.run_hooks(&self.scripts.pre_deploy)
+    }
+
+    /// Run "post-deploy" hooks.
+    ///
+    /// Returns the number of hooks that ran.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `sh` executable cannot be found.
+    pub fn post_deploy(&self) -> Result<usize, String> {
+        self.run_hooks(&self.scripts.post_deploy)
+    }
+
     /// Run "pre-status" hooks.
     ///
     /// Returns the number of hooks that ran.
@@ -398,6 +430,8 @@ impl<'a> Hooks<'a> {
             .chain(self.scripts.post_rsync.iter())
             .chain(self.scripts.pre_link.iter())
             .chain(self.scripts.post_link.iter())
+            .chain(self.scripts.pre_deploy.iter())
+            .chain(self.scripts.post_deploy.iter())
             .chain(self.scripts.pre_status.iter())
             .chain(self.scripts.post_status.iter())
             .chain(self.scripts.pre_diff.iter())
@@ -422,6 +456,8 @@ mod tests {
             post_rsync: Vec::new(),
             pre_link: Vec::new(),
             post_link: Vec::new(),
+            pre_deploy: Vec::new(),
+            post_deploy: Vec::new(),
             pre_status: Vec::new(),
             post_status: Vec::new(),
             pre_diff: Vec::new(),
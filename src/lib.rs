@@ -3,8 +3,13 @@
 //! Copy, sync, or symlink configuration files into the user's home
 //! directory.
 
+pub mod alternates;
+pub mod attributes;
 pub mod hooks;
 pub mod pathspec;
+pub mod state;
+pub mod targets;
+pub mod templates;
 pub mod ui;
 pub mod utils;
 pub mod walk;
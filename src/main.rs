@@ -28,26 +28,65 @@ fn main() {
     } else if args.version {
         version();
     } else if let Some(command) = args.command {
-        let root = args.root.as_ref();
+        // The root can be a single file, inside a root.
+        let (root, file) = match cmd::common::split_file_root(args.root.as_ref(), !args.pathspecs.is_empty()) {
+            Ok(split) => split,
+            Err(code) => process::exit(code),
+        };
+        let root = root.as_ref();
+        if let Some(home) = &args.home {
+            cmd::common::set_home_directory(home);
+        }
         let verbose = args.verbose;
+        let dry_run = args.dry_run;
 
         // Lazy, not all commands need it.
-        let pathspec = || parse_pathspecs_or_exit(&args.pathspecs);
+        let pathspec = || match &file {
+            Some(file) => PathSpec::file(file),
+            None => parse_pathspecs_or_exit(&args.pathspecs),
+        };
+
+        // The other commands don't know how to stack layers.
+        let single_root_command = match command {
+            cli::Command::Link => Some("link"),
+            cli::Command::Deploy => Some("deploy"),
+            cli::Command::Unlink => Some("unlink"),
+            cli::Command::Apply => Some("apply"),
+            cli::Command::Clean => Some("clean"),
+            // Layers are listed from the working tree, not the revision.
+            cli::Command::Status | cli::Command::Diff if args.rev.is_some() => Some("--rev"),
+            _ => None,
+        };
+        if let Some(name) = single_root_command && let Err(code) = cmd::layers::ensure_not_layered(root, name) {
+            process::exit(code);
+        }
 
         if let Err(code) = match command {
-            cli::Command::Sync => cmd::sync(root, verbose, args.pull_before_command, &pathspec()),
-            cli::Command::RSync => cmd::rsync(root, verbose, args.pull_before_command, &pathspec()),
-            cli::Command::Link => cmd::link(root, verbose, args.pull_before_command, &pathspec()),
-            cli::Command::Status => cmd::status(root, verbose, args.pull_before_command, &pathspec()),
+            cli::Command::Sync => cmd::layered(root, &args.layers, &pathspec(), args.atomic && !dry_run, |root, pathspec| cmd::sync(root, verbose, args.pull_before_command, dry_run, args.prune, args.on_conflict, args.interactive, args.atomic, pathspec)),
+            cli::Command::RSync => cmd::layered(root, &args.layers, &pathspec(), false, |root, pathspec| cmd::rsync(root, verbose, args.pull_before_command, dry_run, args.on_conflict, args.interactive, pathspec)),
+            cli::Command::Link => cmd::link(root, verbose, args.pull_before_command, dry_run, args.prune, args.relative, args.atomic, false, &pathspec()),
+            cli::Command::Relink => cmd::relink(args.old_root.as_ref(), verbose, dry_run),
+            cli::Command::Deploy => cmd::deploy(root, verbose, args.pull_before_command, dry_run, args.prune, args.atomic, &pathspec()),
+            cli::Command::Unlink => cmd::unlink(root, verbose, args.pull_before_command, dry_run, &pathspec()),
+            cli::Command::Add => cmd::add(&args.paths, args.link, dry_run),
+            cli::Command::Forget => cmd::forget(&args.paths, &args.pathspecs, verbose, dry_run),
+            cli::Command::Status => cmd::layered(root, &args.layers, &pathspec(), false, |root, pathspec| cmd::status(root, verbose, args.pull_before_command, args.rev.as_ref(), pathspec)),
             cli::Command::Diff => {
                 if args.incoming_diff {
                     // `diff -i` forwards raw pathspecs to Git, no parsing needed.
-                    cmd::diff_incoming(root, verbose, args.pull_before_command, args.reversed_diff, &args.pathspecs)
+                    let pathspecs = match &file {
+                        Some(file) => vec![format!(":(literal){}", file.display())],
+                        None => args.pathspecs.clone(),
+                    };
+                    cmd::diff_incoming(root, verbose, args.pull_before_command, args.reversed_diff, &pathspecs)
                 } else {
-                    cmd::diff(root, verbose, args.pull_before_command, args.reversed_diff, &pathspec())
+                    cmd::layered(root, &args.layers, &pathspec(), false, |root, pathspec| cmd::diff(root, verbose, args.pull_before_command, args.reversed_diff, args.rev.as_ref(), pathspec))
                 }
             }
-            cli::Command::Clean => cmd::clean(root, verbose, args.pull_before_command, &pathspec()),
+            cli::Command::Apply => cmd::apply(root, verbose, args.pull_before_command, args.reversed_diff, args.patch, dry_run, &pathspec()),
+            cli::Command::Clean => cmd::clean(root, verbose, args.pull_before_command, dry_run, args.interactive, &pathspec()),
+            cli::Command::Undo => cmd::undo(verbose, dry_run),
+            cli::Command::Restore => cmd::restore(&args.paths, args.run_id.as_ref(), verbose, dry_run),
             cli::Command::Run => cmd::run(&args.run_args, verbose),
             cli::Command::Nuts => {
                 println!("Ha! Got 'em!");
@@ -89,20 +128,51 @@ Usage: {bin} [<options>] <command> [<args>] [-- <pathspec>...]
 
 Commands:
   sync [<root>|<git>]    Update home from configs
+    -i, --interactive
+    --prune
+    --atomic
+    --ours, --theirs
+    --force
   rsync [<root>]         Update configs from home
+    -i, --interactive
+    --ours, --theirs
+    --force
+  add <path>...          Adopt files from home
+    --link
+  forget <path>...       Stop managing files
   link [<root>]          Symlink configs to home
+    --prune
+    --relative
+    --atomic
+  relink [<old-root>]    Repoint links to a moved root
+  unlink [<root>]        Turn links into copies
+  deploy [<root>]        Sync or link, per file
+    --prune
+    --atomic
 
   status [<root>|<git>]  List files and their status
+    --rev <commit>
   diff [<root>|<git>]    Show what has changed
     -r, --reversed
     -i, --incoming
+    --rev <commit>
+  apply [<root>]         Apply changes hunk by hunk
+    -p, --patch
+    -r, --reversed
   clean [<root>|<git>]   Remove all configs from home
+    -i, --interactive
+
+  undo                   Revert the last sync, link, etc.
+  restore <path>...      Restore files from backups
+    --run <id>
 
   run                    Run command inside the root
 
 Options:
   -p, --pull             Git-pull the root first
+  -n, --dry-run          Show what would be done
   -v, --verbose          Show files being copied
+  --home <dir>           Use <dir> as home
 
   -h, --help             Show this message and exit
   -V, --version          Show the version and exit
@@ -126,10 +196,10 @@ What does {package} do?
   {package} is very unopinionated by default. It tries to do its job
   well (syncing config files), while avoiding doing what other tools do
   better. For instance, there is no automatic versioning, no embedded
-  text editor, and no templating. You absolutely {i}can{rt} do all of the
-  above, but it's not something that's forced on you. It's {i}your{rt}
-  processes, {i}your{rt} tools. All the extensibility power lies in hooks
-  (read further below).
+  text editor, and only the most basic templating. You absolutely
+  {i}can{rt} do all of the above, but it's not something that's forced on
+  you. It's {i}your{rt} processes, {i}your{rt} tools. All the extensibility
+  power lies in hooks (read further below).
 
 Copying vs. Linking:
   {package} supports two configuration models: copying and linking.
@@ -170,15 +240,80 @@ The Config Root:
       {highlight}${rt} export DEEZ_ROOT=/home/deez/root
       {highlight}${rt} {bin} status
 
+  The root can also be a single file inside a root. {package} then
+  finds the enclosing root (the closest parent with a `.deez` file), and
+  works on that one file only, as if given as a pathspec.
+
+      {attenuate}# Sync only Git's config.{rt}
+      {highlight}${rt} {bin} sync ~/configs/.config/git/config
+
+Layers:
+  Configs that differ per machine can live in layers: a base root, plus
+  overlay roots on top of it. Give them all, from lowest to highest
+  precedence, or list them in the `.deez` file of a root that holds
+  them. Files from later layers override files at the same path from
+  earlier ones.
+
+      {attenuate}# `laptop` wins over `work`, which wins over `base`.{rt}
+      {highlight}${rt} {bin} sync base work laptop
+
+      {attenuate}# .deez{rt}
+      layer base
+      layer work
+      layer laptop
+
+  Each layer is a root of its own (with its own `.deez` file), relative
+  to the root listing it. Lines of `.deez` that don't start with `layer`
+  are ignored. `sync`, `rsync`, `status` and `diff` go through the
+  layers in order, and tell which files each layer provides, and which
+  ones are overridden. `rsync` writes changes back to the layer that
+  provides the file. Other commands refuse layered roots.
+
+  If a layer fails, the layers after it are not synced, and the ones
+  before it stay synced, unless `sync` is `--atomic`: then they are all
+  rolled back.
+
 Home:
   This is the directory where config files are copied or symlinked to.
   On Unix, this is read from the `HOME` environment variable, and on
   Windows from `USERPROFILE`.
 
-  Using a different home is not natively supported by an argument, but
-  you can override the environment variable to achieve what you want.
+  To deploy somewhere else (e.g., a staging directory, a chroot, or a
+  container image), use `--home`, or set the `DEEZ_HOME` environment
+  variable. Contrary to overriding `HOME`, this leaves the home of
+  hooks, Git and the pager alone. The directory must exist.
+
+      {highlight}${rt} {bin} --home /mnt/image/root sync
+
+  Backups and other state stay in your own home directory.
+
+Targets:
+  Some configs don't belong in the home (e.g., `/etc`), or in the same
+  place on every machine (e.g., `$XDG_CONFIG_HOME`). Map directories of
+  the root to other targets in a `.deeztargets` file, at the root. Files
+  outside mapped directories go to the home, as usual.
 
-      {highlight}${rt} HOME=/home/other {bin} sync
+      {attenuate}# .deeztargets{rt}
+      etc/      /etc/
+      .config/  $XDG_CONFIG_HOME/
+
+  Environment variables are expanded. If one is unset, the mapping is
+  ignored, and the files go to the home. Relative targets are relative
+  to the home. When directories are nested, the deepest one wins.
+
+Alternates:
+  Configs that differ per machine, but only slightly, can have
+  alternates next to them: `<file>##os.<os>` or `<file>##host.<host>`.
+  Alternates are deployed as `<file>`, but only on machines they're
+  meant for. The most specific one wins: the host, then the OS, then the
+  plain file.
+
+      {highlight}${rt} ls -A
+      .gitconfig  .gitconfig##host.work-laptop  .bashrc  .bashrc##os.macos
+
+  The OS is named as in Rust's `std::consts::OS` (e.g., `linux`,
+  `macos`, `windows`). The host name is the machine's (up to the first
+  `.`), unless the `DEEZ_HOST` environment variable says otherwise.
 
 Sync:
   Syncing in {package} replicates the file structure from the config
@@ -193,6 +328,16 @@ Sync:
       {attenuate}# Sync from remote.{rt}
       {highlight}${rt} {bin} sync https://github.com/qrichert/configs
 
+  Files get the same permissions in the home as in the root (e.g.,
+  `0600` for `.ssh/config`, or `+x` for scripts). If only the
+  permissions differ, they are updated in place.
+
+  Files are replaced atomically: each one is written next to its
+  destination, then renamed over it. If {bin} gets interrupted (or the
+  disk fills up), files in the home are either the old version or the
+  new one, never half-written. The same goes for `rsync` writing to
+  the root.
+
 rSync:
   Reverse-syncing reverses the direction of syncing: it updates your
   config files in the root with the current content from home.
@@ -206,6 +351,39 @@ rSync:
       {attenuate}# 3. rSync the changes back into your root.{rt}
       {highlight}${rt} {bin} rsync
 
+  Permission changes are carried back too (e.g., after a `chmod +x`),
+  unless it is the root's permissions that changed since the last sync.
+
+Add:
+  Adding adopts files from your home into the root, at the same
+  relative location. Directories are added whole, minus what the root's
+  `.ignore` and `.gitignore` files ignore. With `--link`, the files in
+  the home are replaced by links to their new copy in the root.
+
+      {attenuate}# Start managing a config file.{rt}
+      {highlight}${rt} {bin} add ~/.config/foo/bar.toml
+
+      {attenuate}# Move a directory into the root, and link it back.{rt}
+      {highlight}${rt} {bin} add --link ~/.config/foo
+
+  Files already in the root are left as is (see `rsync`), and so are
+  paths outside of the home.
+
+Forget:
+  Forgetting is the reverse of adding: files are removed from the root,
+  but the home keeps them. Links in the home are replaced by real
+  files, with the same contents. Paths can be given from the home, or
+  from the root, and pathspecs forget whole subtrees.
+
+      {attenuate}# Stop managing a config file.{rt}
+      {highlight}${rt} {bin} forget ~/.config/foo/bar.toml
+
+      {attenuate}# Stop managing Neovim's config, except for the init file.{rt}
+      {highlight}${rt} {bin} forget -- .config/nvim ':!.config/nvim/init.lua'
+
+  Files missing from the home are kept in the root, so nothing is lost.
+  Folded directories can only be forgotten as a whole.
+
 Link:
   Linking is the same as syncing, but it creates symbolic links in the
   home instead of copying files. Linking has no `rsync` equivalent
@@ -214,6 +392,49 @@ Link:
       {attenuate}# Symlink current config root.{rt}
       {highlight}${rt} {bin} link
 
+  Symlinks point to the root with absolute paths. Use `--relative` to
+  make them relative to their own directory instead, so they keep
+  working if the root and the home move together (e.g., a home mounted
+  elsewhere in a container, or shared across machines).
+
+      {attenuate}# Symlink with relative paths.{rt}
+      {highlight}${rt} {bin} link --relative
+
+  Directories can be linked as a whole instead (like GNU Stow does), by
+  giving them the `fold` attribute in a `.deezattributes` file at the
+  root of the config root. Files added to a folded directory show up in
+  the home right away. If the directory already exists in the home, its
+  files are linked one by one, as usual.
+
+      {attenuate}# .deezattributes{rt}
+      .config/nvim fold
+
+Relink:
+  If you move or rename the root, the links in the home point to where
+  it used to be. Relinking repoints them to the current root. The
+  previous location is found on its own if the root was linked from
+  there before; otherwise, give it. Links to files that are not in the
+  root anymore are reported, and left as is.
+
+      {attenuate}# Repoint links to the current root.{rt}
+      {highlight}${rt} {bin} relink
+
+      {attenuate}# Same, from a given location.{rt}
+      {highlight}${rt} {bin} relink ~/old-dotfiles
+
+Unlink:
+  Unlinking turns the links of the root in the home into copies of the
+  files they point to, in place. It switches a machine from linking to
+  syncing, without the home ever going without its configs (like it
+  would with a `clean` then a `sync`). Links that point elsewhere are
+  left as is, and folded directories become real ones.
+
+      {attenuate}# Switch from linking to syncing.{rt}
+      {highlight}${rt} {bin} unlink
+
+      {attenuate}# Only unlink fish's config.{rt}
+      {highlight}${rt} {bin} unlink -- .config/fish
+
 Status:
   Status prints the list of configuration files with their respective
   state of 'syncness', and also prints your hooks.
@@ -222,12 +443,13 @@ Status:
 
       {in_sync}  In Sync
       {modified}  Modified
+      {permissions_changed}  Permissions Changed
       {missing}  Missing
 
 Diff:
   Diffing prints the line-diff between your config root and your home.
-  This shows you exactly what has changed and where. There is no merge
-  feature, however, as merging is best done by your VCS.
+  This shows you exactly what has changed and where. To bring over only
+  some of the changes, see Apply below.
 
   By default, `diff` uses the home as the {i}before{rt}, and the config root
   as the {i}after{rt}. This assumes you make changes inside the config root,
@@ -264,6 +486,28 @@ Diff:
       {attenuate}# See what you haven't shared yet.{rt}
       {highlight}${rt} {bin} diff -i -r
 
+Apply:
+  `apply` takes the changes `diff` shows, and applies them. With
+  `--patch` (`-p`), it goes through them hunk by hunk, and asks which
+  ones to apply, like `git add -p` does:
+
+      y - apply this hunk
+      n - do not apply this hunk
+      a - apply this hunk, and all later hunks in the file
+      d - do not apply this hunk, nor any later hunk in the file
+      q - quit; do not apply this hunk, nor any of the remaining ones
+
+  By default, changes go from the root to the home, like with `sync`.
+  With `--reversed`, they go from the home to the root, like with
+  `rsync`. This is handy to bring back a single tweak, without the
+  machine-specific lines around it:
+
+      {attenuate}# Pick what to bring back from `~/.gitconfig`.{rt}
+      {highlight}${rt} {bin} apply -p -r -- .gitconfig
+
+  Files that get changed are backed up first. Note that in `apply`,
+  `-p` stands for `--patch`; use `--pull` to pull the root first.
+
 Clean:
   Cleaning is removing all the files and symlinks from the home.
 
@@ -273,6 +517,143 @@ Clean:
       {attenuate}# 2. Now remove all the links you've just created.{rt}
       {highlight}${rt} {bin} clean
 
+Dry Run:
+  Commands that modify the file system (`sync`, `rsync`, `link` and
+  `clean`) accept the `--dry-run` flag. With it, {package} walks the root
+  exactly like it normally would, but only prints what it would do to
+  each file, without touching anything.
+  `apply`, `undo` and `restore` accept it too: `apply` only prints the
+  hunks it would apply, the others the files they would put back.
+
+      {attenuate}# Preview what `sync` would change.{rt}
+      {highlight}${rt} {bin} sync --dry-run
+      create           .config/fish/config.fish
+      no-op            .config/nvim/init.lua
+      overwrite        .gitconfig
+      Would sync 3 files (dry run).
+
+  Possible actions are `create`, `overwrite`, `update mode`, `replace
+  symlink`, `remove dir`, `delete`, `skip` (see Conflicts below) and
+  `no-op`. Hooks still run, but they are told about the dry run (see
+  `DEEZ_DRY_RUN` below), so they can skip their own side effects.
+  For the same reason, a root without a `.deez` file still asks for
+  confirmation.
+
+Interactive:
+  `sync`, `rsync` and `clean` accept the `--interactive` (`-i`) flag.
+  With it, {package} goes through the files it would change one at a
+  time, shows their diff, and asks what to do:
+
+      y - apply this change
+      n - skip this file
+      d - show the full diff
+      e - edit the incoming file in $EDITOR before applying it
+      a - apply this change, and all the remaining ones
+      q - quit; skip this file, and all the remaining ones
+
+  Files that are already up-to-date are not asked about. Edits made
+  with `e` only apply to the receiving end; the sending end is left as
+  is, so the next run sees the file as changed there.
+
+Backups:
+  Before `sync` or `link` replaces a file in your home, {package} backs
+  it up, so local edits you forgot to `rsync` are never lost. Only files
+  whose content differs from the root are backed up; the others can be
+  restored from the root anyway. Likewise, `rsync` backs up the files it
+  replaces in the root, and `clean` backs up every file it removes.
+
+  Backups are stored in `$XDG_STATE_HOME/deez/backups/` (which defaults
+  to `~/.local/state/deez/backups/`), in one directory per run, named
+  after the time of the run. Inside, files keep their full path (e.g.,
+  `files/home/deez/.gitconfig`), next to a `journal` listing everything
+  the run changed. Use `--verbose` to see where the files of the current
+  run went.
+
+Undo and Restore:
+  `undo` reverts the most recent `sync`, `rsync`, `link` or `clean`:
+  created files are deleted, and replaced or removed files are put back
+  from the backups. Running `undo` again reverts the run before that,
+  and so on.
+  The run is forgotten too: the next `sync` deploys the reverted files
+  again, instead of taking them for edits made in the home.
+
+      {attenuate}# Oops, wrong root.{rt}
+      {highlight}${rt} {bin} sync ~/work-configs
+
+      {attenuate}# Put everything back the way it was.{rt}
+      {highlight}${rt} {bin} undo
+
+  `restore` brings back a single file from the most recent backup of
+  it, or from a given run with `--run`.
+
+      {highlight}${rt} {bin} restore ~/.gitconfig
+      {highlight}${rt} {bin} restore --run 20250131-235959-999 ~/.gitconfig
+
+  Neither command ever loses anything either: if a file has changed
+  since it was backed up, its current version is backed up before it
+  gets replaced. This also means a `restore` can be undone.
+
+  By default, `sync`, `link` and `deploy` carry on past errors, which
+  may leave the home half old, half new. With `--atomic`, the run is
+  all or nothing: if any file fails (or the command's `post-` hook
+  does), every file it touched is rolled back, like an `undo` on the
+  spot.
+
+      {highlight}${rt} {bin} sync --atomic
+      error: Could not remove exising directory '/home/deez/.vim': Directory not empty
+      Rolled back 3 files.
+
+Manifest:
+  After each `sync` or `link`, {package} records what it deployed in a
+  manifest, in `$XDG_STATE_HOME/deez/manifests/`. There is one manifest
+  per root (and home), listing for each file its path in the root and
+  in the home, its mode, a hash of its content, whether it was copied or
+  linked, and when.
+
+  This is how {package} knows what it put in your home itself, as
+  opposed to what was already there. `clean` removes the files it cleans
+  up from the manifest.
+
+  The manifest also lets `sync` and `link` clean up after files you
+  deleted or renamed in the root. With `--prune`, they remove the home
+  files they deployed in a previous run, but that are no longer in the
+  root. Only files that are still exactly as deployed are removed (and
+  backed up first); files you edited since are left alone, with a
+  warning.
+
+      {attenuate}# `.bashrc` was renamed to `.bash_profile` in the root.{rt}
+      {highlight}${rt} {bin} sync --prune
+      Pruned 1 file.
+      Synced 3 files.
+
+Conflicts:
+  The manifest also records the last version of each file that was
+  synced between the root and the home. Using it as a base, `sync` and
+  `rsync` can tell which end changed since:
+
+  - Only the sending end changed: the file is copied over, as usual.
+  - Only the receiving end changed: the file is skipped, with a warning.
+    `sync` never drops your home edits, and `rsync` never drops what
+    you pulled into the root.
+  - Both ends changed: this is a conflict. The file is left as is, and
+    reported as an error.
+
+  `--ours` keeps the receiving end's version (the home's for `sync`,
+  the root's for `rsync`), and `--theirs` overwrites it. Skipped files
+  are counted apart in the summary. Since nothing gets written with
+  `--ours`, the file stays a conflict until both ends agree (e.g., once
+  its edits are brought to the other end, with `rsync` or `apply -r`).
+  With `--theirs`, the conflict is resolved, and later runs only see
+  what changed after it.
+  `--force` skips the checks altogether, and overwrites every file that
+  differs, like earlier versions of {package} did.
+
+      {attenuate}# Both `.gitconfig`s changed, keep the root's.{rt}
+      {highlight}${rt} {bin} sync --theirs
+
+  Files that were never synced, and symlinks, have no base. They are
+  overwritten if they differ.
+
 Filtering:
   You can narrow the set of files {package} works on with pathspecs.
   Pathspecs must be listed at the end of the command, after the `--`
@@ -331,10 +712,10 @@ Shortcuts:
       run    {u}r{rt}
 
 Ignore some files:
-  By default, {package} ignores all the hook files (at the root), the
-  `.git` directory at the root (if any), all `.ignore` and `.gitignore`
-  files, and all `.deez` files, wherever they are (enabling multi-root
-  repos).
+  By default, {package} ignores all the hook files, and the
+  `.deezattributes`, `.deeztargets` and `.deezvars` files (at the
+  root), the `.git` directory at the root (if any), all `.ignore` and
+  `.gitignore` files, and all `.deez` files, wherever they are (enabling multi-root repos).
 
   You can extend this list by adding entries to your `.ignore` and/or
   `.gitignore` files; they are both respected by {package}.
@@ -371,7 +752,9 @@ Git:
       {highlight}${rt} {bin} sync gh:qrichert/configs[sub/directory]
 
   Instead of assuming the root to be at the repository root, this allows
-  using a sub-directory as the root.
+  using a sub-directory as the root. If the sub-root is a file, only
+  that file is used (the root being the closest parent with a `.deez`
+  file, or else the repository root).
 
   If you're using Git, you can also pull and run a command in one shot
   with the `--pull` flag:
@@ -390,6 +773,20 @@ Git:
 
   Like `--pull`, this only works with local roots.
 
+  To compare the home against any revision of the root instead (e.g.,
+  the tag you deployed last month, or `origin/main`), give `status` or
+  `diff` a `--rev`:
+
+      {attenuate}# Is this machine still on the configs of v1.0?{rt}
+      {highlight}${rt} {bin} status --rev v1.0
+
+      {attenuate}# What would syncing `origin/main` change?{rt}
+      {highlight}${rt} {bin} diff --rev origin/main
+
+  The files are read from that revision's tree, without checking it
+  out (ignore rules, attributes, etc. included). This also only works
+  with local roots, and with a single root (not layers).
+
 Hooks:
   {package} lets you run hooks before and after commands. Hooks are
   scripts or executables located at the root and whose names match the
@@ -402,6 +799,7 @@ Hooks:
       pre-sync    post-sync
       pre-rsync   post-rsync
       pre-link    post-link
+      pre-deploy  post-deploy
       pre-status  post-status
       pre-diff    post-diff
       pre-clean   post-clean
@@ -446,26 +844,66 @@ Hooks:
 
   - `DEEZ_ROOT` Absolute path to the config root. This is equal to `pwd`
     on Unix systems, since hooks are run in the root.
-  - `DEEZ_HOME` Absolute path to the home directory. This is equal to
-    `$HOME` on Unix systems.
+  - `DEEZ_HOME` Absolute path to the home directory configs are deployed
+    into. This is equal to `$HOME` on Unix systems, unless `--home` or
+    `DEEZ_HOME` say otherwise.
   - `DEEZ_VERBOSE` Will be `true` if run in verbose mode, otherwise it
     will be unset (hint: use `[[ -n $DEEZ_VERBOSE ]]` to test for
     existence).
   - `DEEZ_OS` Contains the name of the current operating system (e.g.,
     `linux`, `macos`, `windows`, etc.). The name is a re-export of
     Rust's `std::consts::OS`.
+  - `DEEZ_DRY_RUN` Will be `true` if run with `--dry-run`, otherwise it
+    will be unset. Hooks should not make any changes if it is set.
 
 Templating:
-  There is no built-in templating in {package}, but hooks let you
-  implement anything from simple `sed` commands to more advanced
-  templating with Jinja2 in Python.
+  Files ending in `.tmpl` are templates. They are rendered, and
+  deployed without the extension: `.gitconfig.tmpl` becomes
+  `.gitconfig`. Every `{{{{ variable }}}}` in them is replaced by its value.
+
+      {attenuate}# .gitconfig.tmpl{rt}
+      [user]
+          email = {{{{ email }}}}
+      [core]
+          editor = {{{{ env.EDITOR }}}}
+
+  The built-in variables are `os`, `host` (as in alternates), `user`,
+  and `env.<NAME>` for environment variables. Define your own in a
+  `.deezvars` file, at the root; they take precedence over the built-in
+  ones.
+
+      {attenuate}# .deezvars{rt}
+      email = deez@example.com
+
+  Every command works with the rendered files (e.g., `status` and
+  `diff` compare the home against them). Unknown variables are errors.
+  Templates are always copied, never linked, and `rsync` leaves them
+  alone: edit them in the root.
+
+  For anything more advanced, hooks let you implement anything from
+  simple `sed` commands to templating with Jinja2 in Python.
 
 Copy some files, and link others:
-  Use multiple roots. You can have multiple roots (sub-directories) in
-  one repo. Use `sync` in one, and `link` in the other.
+  Give files the `link` or `copy` attribute in the `.deezattributes`
+  file of the root (the last matching line wins), and `deploy` them.
+  `deploy` links the files marked `link`, and syncs all the others.
+
+      {attenuate}# .deezattributes{rt}
+      *            link
+      .gitconfig   copy
+
+      {highlight}${rt} {bin} deploy
+
+  `deploy` is a single run: only the `deploy` hooks run (not the `sync`
+  or `link` ones), one summary covers every file, and with `--atomic`,
+  copies and links are rolled back together.
+
+  `sync` leaves the files marked `link` alone, and `link` leaves the
+  ones marked `copy` alone, so running either one does not undo the
+  other. `status` shows the strategy of the files that declare one.
 
-  If you need anything more advanced than that, `{package}` is likely
-  not the right tool for you.
+  The command is not called `apply`, because `apply` already applies
+  changes hunk by hunk.
 ",
         help = short_help_message(),
         bin = env!("CARGO_BIN_NAME"),
@@ -476,6 +914,7 @@ Copy some files, and link others:
         u = ui::Color::maybe_color(ui::color::UNDERLINE),
         in_sync = ui::Color::in_sync("S"),
         modified = ui::Color::modified("M"),
+        permissions_changed = ui::Color::modified("P"),
         missing = ui::Color::missing("!"),
         rt = ui::Color::maybe_color(ui::color::RESET),
         windows_glob_escape = {
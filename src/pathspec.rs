@@ -1,3 +1,4 @@
+use std::collections::BTreeSet;
 use std::path::{Component, Path, PathBuf};
 
 use globset::{Candidate, Glob, GlobBuilder, GlobSet, GlobSetBuilder};
@@ -21,10 +22,13 @@ use globset::{Candidate, Glob, GlobBuilder, GlobSet, GlobSetBuilder};
 /// **Glob Set**: We call _glob set_ a group of globs that can be
 /// matched together in a single pass (as per the definition of the
 /// [`GlobSet`] struct).
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct PathSpec {
     positives: GlobSet,
     negatives: GlobSet,
+    /// Files excluded as is, on top of the negatives (see
+    /// [`PathSpec::excluding()`]).
+    excluded: BTreeSet<PathBuf>,
 }
 
 impl PathSpec {
@@ -63,12 +67,46 @@ impl PathSpec {
         Ok(Self {
             positives: build_glob_set(&positive_globs)?,
             negatives: build_glob_set(&negative_globs)?,
+            excluded: BTreeSet::new(),
         })
     }
 
+    /// A [`PathSpec`] matching `path` (relative to the root), and
+    /// nothing else.
+    ///
+    /// Contrary to [`PathSpec::parse()`], `path` is taken literally: it
+    /// is not a glob, and does not match anything below it.
+    #[must_use]
+    pub fn file(path: &Path) -> Self {
+        let mut positive_globs = GlobSetBuilder::new();
+        positive_globs.add(
+            GlobBuilder::new(&globset::escape(&path.to_string_lossy()))
+                .literal_separator(true)
+                .build()
+                .expect("escaped path is a valid glob"),
+        );
+
+        Self {
+            positives: positive_globs.build().unwrap_or_default(),
+            negatives: GlobSet::empty(),
+            excluded: BTreeSet::new(),
+        }
+    }
+
+    /// The same [`PathSpec`], but not matching `files` (relative to the
+    /// root).
+    ///
+    /// Like [`PathSpec::file()`], `files` are taken literally.
+    #[must_use]
+    pub fn excluding(&self, files: impl IntoIterator<Item = PathBuf>) -> Self {
+        let mut pathspec = self.clone();
+        pathspec.excluded.extend(files);
+        pathspec
+    }
+
     #[must_use]
     pub fn is_empty(&self) -> bool {
-        self.positives.is_empty() && self.negatives.is_empty()
+        self.positives.is_empty() && self.negatives.is_empty() && self.excluded.is_empty()
     }
 
     /// Whether `path` (relative to the root) passes the filters.
@@ -80,12 +118,28 @@ impl PathSpec {
     /// If filters are empty, it always matches (passes).
     #[must_use]
     pub fn matches(&self, path: &Path) -> bool {
+        self.matches_any(&[path])
+    }
+
+    /// Whether a file known by several `paths` passes the filters.
+    ///
+    /// This is for alternates, which are deployed under another path
+    /// than theirs. It is enough for one path to be included, but any
+    /// path being excluded excludes the file.
+    #[must_use]
+    pub fn matches_any(&self, paths: &[&Path]) -> bool {
         if self.is_empty() {
             return true;
         }
-        let candidate = Candidate::new(path);
-        let included = self.positives.is_empty() || self.positives.is_match_candidate(&candidate);
-        let excluded = self.negatives.is_match_candidate(&candidate);
+        let candidates = paths.iter().map(Candidate::new).collect::<Vec<_>>();
+        let included = self.positives.is_empty()
+            || candidates
+                .iter()
+                .any(|candidate| self.positives.is_match_candidate(candidate));
+        let excluded = candidates
+            .iter()
+            .any(|candidate| self.negatives.is_match_candidate(candidate))
+            || paths.iter().any(|path| self.excluded.contains(*path));
         included && !excluded
     }
 }
@@ -343,4 +397,52 @@ mod tests {
         assert!(err.contains(&format!("Invalid pathspec '{pathspec}'")));
         assert!(err.contains("unclosed character class"));
     }
+
+    #[test]
+    fn file_matches_only_that_file() {
+        let s = PathSpec::file(Path::new(".config/fish/config.fish"));
+        assert!(!s.is_empty());
+        assert!(s.matches(Path::new(".config/fish/config.fish")));
+        assert!(!s.matches(Path::new(".config/fish/config.fish/foo")));
+        assert!(!s.matches(Path::new(".config/fish/other.fish")));
+    }
+
+    #[test]
+    fn file_is_not_a_glob() {
+        let s = PathSpec::file(Path::new("foo[1]*.toml"));
+        assert!(s.matches(Path::new("foo[1]*.toml")));
+        assert!(!s.matches(Path::new("foo1bar.toml")));
+    }
+
+    #[test]
+    fn excluding_files_keeps_the_filters() {
+        let s =
+            parse_pathspecs(&[".config"]).excluding([PathBuf::from(".config/fish/config.fish")]);
+        assert!(!s.matches(Path::new(".config/fish/config.fish")));
+        assert!(s.matches(Path::new(".config/fish/other.fish")));
+        assert!(!s.matches(Path::new(".gitconfig")));
+    }
+
+    #[test]
+    fn excluding_files_from_everything() {
+        let s = PathSpec::default().excluding([PathBuf::from(".gitconfig")]);
+        assert!(!s.is_empty());
+        assert!(!s.matches(Path::new(".gitconfig")));
+        assert!(s.matches(Path::new(".bashrc")));
+    }
+
+    #[test]
+    fn any_path_included_is_enough() {
+        let s = parse_pathspecs(&[".bashrc"]);
+        assert!(s.matches_any(&[Path::new(".bashrc##os.linux"), Path::new(".bashrc")]));
+        assert!(!s.matches_any(&[Path::new(".zshrc##os.linux"), Path::new(".zshrc")]));
+    }
+
+    #[test]
+    fn any_path_excluded_excludes() {
+        let s = parse_pathspecs(&[":!.bashrc"]);
+        assert!(!s.matches_any(&[Path::new(".bashrc##os.linux"), Path::new(".bashrc")]));
+        let s = PathSpec::default().excluding([PathBuf::from(".bashrc##os.linux")]);
+        assert!(!s.matches_any(&[Path::new(".bashrc##os.linux"), Path::new(".bashrc")]));
+    }
 }
@@ -0,0 +1,99 @@
+//! Persistent state of `deez`, kept outside of config roots.
+//!
+//! Everything lives in a single state directory, `$XDG_STATE_HOME/deez`
+//! (`~/.local/state/deez` by default).
+
+pub mod backup;
+pub mod journal;
+pub mod manifest;
+
+pub use backup::Backup;
+pub use journal::{Change, Journal};
+pub use manifest::Manifest;
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Get the state directory of `deez`.
+///
+/// This is `$XDG_STATE_HOME/deez`, or `<home>/.local/state/deez` if
+/// `XDG_STATE_HOME` is unset or invalid. As per the XDG spec, relative
+/// paths are invalid and must be ignored.
+#[must_use]
+pub fn state_directory(home: &Path) -> PathBuf {
+    if let Some(state_home) = env::var_os("XDG_STATE_HOME").map(PathBuf::from)
+        && state_home.is_absolute()
+    {
+        state_home.join("deez")
+    } else {
+        home.join(".local/state/deez")
+    }
+}
+
+/// Create an identifier for the current run.
+///
+/// The identifier is the current UTC time (e.g., `20250131-235959-999`),
+/// so that run ids sort chronologically.
+///
+/// # Panics
+///
+/// Panics if the system clock is set before the Unix epoch.
+#[must_use]
+pub fn new_run_id() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("current time > Unix epoch");
+    format_run_id(now.as_secs(), now.subsec_millis())
+}
+
+fn format_run_id(secs: u64, millis: u32) -> String {
+    let days = secs / 86_400;
+    let secs = secs % 86_400;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{year:04}{month:02}{day:02}-{:02}{:02}{:02}-{millis:03}",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60,
+    )
+}
+
+/// Convert days since the Unix epoch into a (year, month, day) date.
+///
+/// This is Howard Hinnant's `civil_from_days()` algorithm, restricted
+/// to dates after the epoch (which is all we need).
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_run_id_epoch() {
+        assert_eq!(format_run_id(0, 0), "19700101-000000-000");
+    }
+
+    #[test]
+    fn format_run_id_regular() {
+        // 2025-01-31T23:59:59.999Z
+        assert_eq!(format_run_id(1_738_367_999, 999), "20250131-235959-999");
+    }
+
+    #[test]
+    fn format_run_id_leap_day() {
+        // 2024-02-29T12:34:56.007Z
+        assert_eq!(format_run_id(1_709_210_096, 7), "20240229-123456-007");
+    }
+}
@@ -0,0 +1,232 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{self, Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use super::journal::{self, Change};
+
+/// Backups and journal of a single run.
+///
+/// Files are stored under `<state>/backups/<run-id>/files/`, at their
+/// full, absolute path (e.g., `/home/deez/.gitconfig` is backed up to
+/// `files/home/deez/.gitconfig`). Every change made by the run is also
+/// recorded in `<state>/backups/<run-id>/journal`, which is what lets
+/// `undo` revert the run.
+///
+/// The run directory is only created when the first file is backed up
+/// or recorded, so runs that don't change anything leave no trace.
+///
+/// `Backup` is `Sync`, and can be shared by the walker's threads.
+#[derive(Debug)]
+pub struct Backup {
+    backups_dir: PathBuf,
+    header: String,
+    run_dir: OnceLock<Result<PathBuf, String>>,
+    journal: Mutex<Option<File>>,
+    nb_files: AtomicUsize,
+}
+
+impl Backup {
+    /// Create a backup handler for a new run of `command`.
+    ///
+    /// `root` is the config root the command runs against, if any. It
+    /// is only recorded for information.
+    #[must_use]
+    pub fn new(state_dir: &Path, command: &str, root: Option<&Path>) -> Self {
+        let header = if let Some(root) = root {
+            let root = path::absolute(root).unwrap_or_else(|_| root.to_path_buf());
+            format!("# command: {command}\n# root: {}\n", root.display())
+        } else {
+            format!("# command: {command}\n")
+        };
+
+        Self {
+            backups_dir: state_dir.join("backups"),
+            header,
+            run_dir: OnceLock::new(),
+            journal: Mutex::new(None),
+            nb_files: AtomicUsize::new(0),
+        }
+    }
+
+    /// Check that a change to `file` can be recorded in the journal.
+    ///
+    /// Call this before changing `file`: a change that can't be
+    /// recorded can't be undone either, so it had better not be made.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the path of `file` doesn't fit in the journal.
+    pub fn check(&self, file: &Path) -> io::Result<()> {
+        journal::check_path(&path::absolute(file)?).map(|_| ())
+    }
+
+    /// Back up `file`.
+    ///
+    /// Symlinks are backed up as symlinks (i.e., the link itself, not
+    /// the contents of its target).
+    ///
+    /// Returns the path of the backup.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the change to `file` cannot be recorded (see
+    /// [`check()`](Self::check)), or if `file` cannot be copied into the
+    /// backup directory.
+    pub fn save(&self, file: &Path) -> io::Result<PathBuf> {
+        self.check(file)?;
+        let file = path::absolute(file)?;
+
+        let backup = self.run_dir()?.join("files").join(strip_root(&file));
+
+        if let Some(parent) = backup.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        copy_file_or_symlink(&file, &backup)?;
+
+        self.nb_files.fetch_add(1, Ordering::Relaxed);
+
+        Ok(backup)
+    }
+
+    /// Record a change to `file` in the journal.
+    ///
+    /// `backup` is the path returned by [`save()`](Self::save), if the
+    /// previous version of the file was backed up.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the journal cannot be written to.
+    pub fn record(&self, change: Change, file: &Path, backup: Option<&Path>) -> io::Result<()> {
+        let run_dir = self.run_dir()?;
+
+        let file = path::absolute(file)?;
+        let backup = backup.map(|backup| backup.strip_prefix(run_dir).unwrap_or(backup));
+        let line = journal::format_entry(change, &file, backup)?;
+
+        let mut journal = self
+            .journal
+            .lock()
+            .map_err(|_| io::Error::other("Journal lock is poisoned"))?;
+
+        if journal.is_none() {
+            let mut file = File::options()
+                .create(true)
+                .append(true)
+                .open(run_dir.join("journal"))?;
+            file.write_all(self.header.as_bytes())?;
+            *journal = Some(file);
+        }
+
+        if let Some(journal) = journal.as_mut() {
+            journal.write_all(line.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Directory of this run's backups and journal.
+    ///
+    /// This returns `None` if nothing was backed up or recorded.
+    #[must_use]
+    pub fn directory(&self) -> Option<&Path> {
+        self.run_dir.get()?.as_deref().ok()
+    }
+
+    /// Number of files backed up so far.
+    #[must_use]
+    pub fn nb_files(&self) -> usize {
+        self.nb_files.load(Ordering::Relaxed)
+    }
+
+    fn run_dir(&self) -> io::Result<&Path> {
+        // Only the first thread creates the directory; the others wait
+        // for it, and share the outcome.
+        self.run_dir
+            .get_or_init(|| create_run_dir(&self.backups_dir).map_err(|err| err.to_string()))
+            .as_deref()
+            .map_err(|err| io::Error::other(err.clone()))
+    }
+}
+
+/// Create a new, unique, run directory.
+///
+/// If two runs start within the same millisecond, the second one gets
+/// a suffix (e.g., `20250131-235959-999-1`). It still sorts after the
+/// first one.
+fn create_run_dir(backups_dir: &Path) -> io::Result<PathBuf> {
+    fs::create_dir_all(backups_dir)?;
+
+    let run_id = super::new_run_id();
+    let mut run_dir = backups_dir.join(&run_id);
+
+    for i in 1.. {
+        match fs::create_dir(&run_dir) {
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                run_dir = backups_dir.join(format!("{run_id}-{i}"));
+            }
+            res => return res.map(|()| run_dir),
+        }
+    }
+
+    unreachable!("there is always a free run directory")
+}
+
+/// Put a backed up file back in place, replacing `destination`.
+///
+/// Missing parent directories are created.
+///
+/// # Errors
+///
+/// Errors if `destination` cannot be removed, or if `backup` cannot be
+/// copied over.
+pub fn restore(backup: &Path, destination: &Path) -> io::Result<()> {
+    // Matches both files and symlinks (even broken ones).
+    if destination.is_file() || destination.is_symlink() {
+        fs::remove_file(destination)?;
+    }
+
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    copy_file_or_symlink(backup, destination)
+}
+
+/// Whether `file` is the same as its `backup`.
+///
+/// Symlinks are equal if they point to the same target; files are
+/// equal if they have the same content and permissions.
+#[must_use]
+pub fn is_same_as_backup(file: &Path, backup: &Path) -> bool {
+    if file.is_symlink() || backup.is_symlink() {
+        return file.is_symlink()
+            && backup.is_symlink()
+            && fs::read_link(file).ok() == fs::read_link(backup).ok();
+    }
+    matches!(crate::utils::are_files_equal(file, backup), Ok(true))
+        && matches!(crate::utils::are_permissions_equal(file, backup), Ok(true))
+}
+
+fn copy_file_or_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    if from.is_symlink() {
+        let target = fs::read_link(from)?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&target, to)?;
+        #[cfg(windows)]
+        std::os::windows::fs::symlink_file(&target, to)?;
+    } else {
+        // Also copies the permissions.
+        fs::copy(from, to)?;
+    }
+    Ok(())
+}
+
+/// Turn an absolute path into a relative one, so it can be `join()`ed.
+fn strip_root(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect()
+}
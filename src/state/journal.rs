@@ -0,0 +1,256 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// File created in a run directory once the run has been undone.
+const UNDONE_MARKER: &str = "undone";
+
+/// A change made to a file, as recorded in the journal.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Change {
+    /// The file did not exist before.
+    Created,
+    /// The file existed, and got overwritten.
+    Replaced,
+    /// The file existed, and got removed.
+    Removed,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Created => write!(f, "created"),
+            Self::Replaced => write!(f, "replaced"),
+            Self::Removed => write!(f, "removed"),
+        }
+    }
+}
+
+impl FromStr for Change {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "created" => Ok(Self::Created),
+            "replaced" => Ok(Self::Replaced),
+            "removed" => Ok(Self::Removed),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A single line of the journal.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Entry {
+    pub change: Change,
+    /// Absolute path of the file that changed.
+    pub path: PathBuf,
+    /// Absolute path of the backup of the previous version, if any.
+    pub backup: Option<PathBuf>,
+}
+
+/// The journal of a past run.
+#[derive(Debug)]
+pub struct Journal {
+    /// Run id (e.g., `20250131-235959-999`).
+    pub id: String,
+    /// Run directory, holding both the journal and the backups.
+    pub dir: PathBuf,
+    /// Command that was run (e.g., `sync`).
+    pub command: String,
+    /// Config root the command ran against, if any.
+    pub root: Option<String>,
+    /// Changes, in the order they were made.
+    pub entries: Vec<Entry>,
+    pub is_undone: bool,
+}
+
+impl Journal {
+    /// Read the journal of the run in `dir`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the journal cannot be read.
+    pub fn read(dir: &Path) -> io::Result<Self> {
+        let content = fs::read_to_string(dir.join("journal"))?;
+
+        let mut journal = Self {
+            id: dir
+                .file_name()
+                .map(|id| id.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            dir: dir.to_path_buf(),
+            command: String::new(),
+            root: None,
+            entries: Vec::new(),
+            is_undone: dir.join(UNDONE_MARKER).is_file(),
+        };
+
+        for line in content.lines() {
+            if let Some(command) = line.strip_prefix("# command: ") {
+                journal.command = command.to_string();
+            } else if let Some(root) = line.strip_prefix("# root: ") {
+                journal.root = Some(root.to_string());
+            } else if let Some(entry) = parse_entry(line, dir) {
+                journal.entries.push(entry);
+            }
+            // Silently skip anything else, it can only come from a
+            // crash mid-write, and the rest may still be useful.
+        }
+
+        Ok(journal)
+    }
+
+    /// Remember that the run has been undone.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the marker file cannot be created.
+    pub fn mark_undone(&mut self) -> io::Result<()> {
+        fs::File::create(self.dir.join(UNDONE_MARKER))?;
+        self.is_undone = true;
+        Ok(())
+    }
+}
+
+/// List the journals of all past runs, oldest first.
+///
+/// Runs without a journal are skipped.
+///
+/// # Errors
+///
+/// Errors if the backups directory exists but cannot be read.
+pub fn list(state_dir: &Path) -> io::Result<Vec<Journal>> {
+    let entries = match fs::read_dir(state_dir.join("backups")) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut dirs: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|dir| dir.join("journal").is_file())
+        .collect();
+    // Run ids are timestamps, they sort chronologically.
+    dirs.sort();
+
+    Ok(dirs
+        .iter()
+        .filter_map(|dir| Journal::read(dir).ok())
+        .collect())
+}
+
+/// Format a journal entry: `<change>\t<path>\t<backup>\n`.
+///
+/// `backup` is relative to the run directory, or `-` if there is none.
+pub(super) fn format_entry(
+    change: Change,
+    path: &Path,
+    backup: Option<&Path>,
+) -> io::Result<String> {
+    let path = check_path(path)?;
+    let backup = match backup {
+        Some(backup) => check_path(backup)?,
+        None => "-",
+    };
+
+    Ok(format!("{change}\t{path}\t{backup}\n"))
+}
+
+/// Check that `path` can be recorded in the journal.
+///
+/// The journal is tab-separated UTF-8 text, one entry per line; paths
+/// that aren't valid UTF-8, or hold tabs or newlines, don't fit in.
+pub(super) fn check_path(path: &Path) -> io::Result<&str> {
+    match path.to_str() {
+        Some(path) if !path.contains(['\t', '\n']) => Ok(path),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Path cannot be recorded in the journal",
+        )),
+    }
+}
+
+fn parse_entry(line: &str, run_dir: &Path) -> Option<Entry> {
+    let mut fields = line.split('\t');
+    let change = fields.next()?.parse().ok()?;
+    let path = PathBuf::from(fields.next()?);
+    let backup = match fields.next()? {
+        "-" => None,
+        backup => Some(run_dir.join(backup)),
+    };
+    Some(Entry {
+        change,
+        path,
+        backup,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_entry_with_backup() {
+        let line = format_entry(
+            Change::Replaced,
+            Path::new("/home/deez/.gitconfig"),
+            Some(Path::new("files/home/deez/.gitconfig")),
+        )
+        .unwrap();
+        assert_eq!(
+            line,
+            "replaced\t/home/deez/.gitconfig\tfiles/home/deez/.gitconfig\n"
+        );
+    }
+
+    #[test]
+    fn format_entry_without_backup() {
+        let line = format_entry(Change::Created, Path::new("/home/deez/.bashrc"), None).unwrap();
+        assert_eq!(line, "created\t/home/deez/.bashrc\t-\n");
+    }
+
+    #[test]
+    fn format_entry_rejects_tabs_and_newlines() {
+        assert!(format_entry(Change::Created, Path::new("/home/deez/a\tb"), None).is_err());
+        assert!(format_entry(Change::Created, Path::new("/home/deez/a\nb"), None).is_err());
+    }
+
+    #[test]
+    fn parse_entry_roundtrip() {
+        let run_dir = Path::new("/state/backups/20250131-235959-999");
+        let line = format_entry(
+            Change::Removed,
+            Path::new("/home/deez/.gitconfig"),
+            Some(Path::new("files/home/deez/.gitconfig")),
+        )
+        .unwrap();
+
+        assert_eq!(
+            parse_entry(line.trim_end(), run_dir),
+            Some(Entry {
+                change: Change::Removed,
+                path: PathBuf::from("/home/deez/.gitconfig"),
+                backup: Some(run_dir.join("files/home/deez/.gitconfig")),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_entry_without_backup() {
+        let entry = parse_entry("created\t/home/deez/.bashrc\t-", Path::new("/run")).unwrap();
+        assert_eq!(entry.change, Change::Created);
+        assert_eq!(entry.backup, None);
+    }
+
+    #[test]
+    fn parse_entry_invalid() {
+        assert_eq!(parse_entry("", Path::new("/run")), None);
+        assert_eq!(parse_entry("# command: sync", Path::new("/run")), None);
+        assert_eq!(parse_entry("moved\t/a\t-", Path::new("/run")), None);
+        assert_eq!(parse_entry("created\t/a", Path::new("/run")), None);
+    }
+}
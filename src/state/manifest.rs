@@ -0,0 +1,483 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::utils;
+
+/// Mode of symlinks, as in Git (regular files use their actual mode).
+pub const SYMLINK_MODE: u32 = 0o120_000;
+
+/// How a file was deployed into the home.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Kind {
+    /// Copied with `sync`.
+    Copy,
+    /// Symlinked with `link`.
+    Link,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Copy => write!(f, "copy"),
+            Self::Link => write!(f, "link"),
+        }
+    }
+}
+
+impl FromStr for Kind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "copy" => Ok(Self::Copy),
+            "link" => Ok(Self::Link),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A file deployed by `deez`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Entry {
+    pub kind: Kind,
+    /// File mode (e.g., `0o100644`), or [`SYMLINK_MODE`].
+    pub mode: u32,
+    /// Hash of the content deployed (or of the target, for symlinks).
+    pub hash: u64,
+    /// When the file was deployed (seconds since the Unix epoch).
+    pub timestamp: u64,
+    /// Path of the file, relative to the root.
+    pub root_path: PathBuf,
+    /// Absolute path of the file in the home.
+    pub home_path: PathBuf,
+}
+
+impl Entry {
+    /// Describe `source`, as deployed to `home_path` right now.
+    ///
+    /// For copied symlinks, the mode is [`SYMLINK_MODE`] and the hash
+    /// is that of the symlink's target path. Everything else is hashed
+    /// by content (following symlinks).
+    ///
+    /// # Errors
+    ///
+    /// Errors if `source` cannot be read.
+    pub fn new(kind: Kind, source: &Path, root_path: &Path, home_path: &Path) -> io::Result<Self> {
+        let (mode, hash) = if kind == Kind::Copy && source.is_symlink() {
+            (SYMLINK_MODE, hash_symlink(source)?)
+        } else {
+            (file_mode(source)?, hash_file(source)?)
+        };
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs())
+            .unwrap_or_default();
+
+        Ok(Self {
+            kind,
+            mode,
+            hash,
+            timestamp,
+            root_path: root_path.to_path_buf(),
+            home_path: home_path.to_path_buf(),
+        })
+    }
+
+    /// Whether the home file is still exactly what was deployed.
+    ///
+    /// Copies must have kept their content (or symlink target). Links
+    /// must still point to one of `sources`, the paths the file in the
+    /// root may have been linked from (they need not exist anymore).
+    #[must_use]
+    pub fn is_unchanged(&self, sources: &[PathBuf]) -> bool {
+        let home_path = &self.home_path;
+        match self.kind {
+            Kind::Link => {
+                utils::read_link_absolute(home_path).is_ok_and(|target| sources.contains(&target))
+            }
+            Kind::Copy if self.mode == SYMLINK_MODE => {
+                home_path.is_symlink()
+                    && hash_symlink(home_path).is_ok_and(|hash| hash == self.hash)
+            }
+            Kind::Copy => {
+                !home_path.is_symlink() && hash_file(home_path).is_ok_and(|hash| hash == self.hash)
+            }
+        }
+    }
+}
+
+/// Which ends of a copied file changed since it was last synced.
+///
+/// The last synced version (the "base") is the one recorded in the
+/// manifest. Comparing both ends to it tells a root that moved on
+/// (e.g., after a `git pull`) from a home that was edited.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Divergence {
+    /// Root and home are the same.
+    None,
+    /// Only the root changed.
+    Root,
+    /// Only the home changed.
+    Home,
+    /// Both changed, and differently: this is a conflict.
+    Both,
+    /// Root and home differ, but there is no base to tell which changed.
+    Unknown,
+}
+
+impl Divergence {
+    /// Compare the root and the home versions of a file to its base.
+    ///
+    /// Only regular files that were copied have a usable base; links
+    /// and symlinks always compare as [`Unknown`](Self::Unknown) when
+    /// they differ.
+    ///
+    /// # Errors
+    ///
+    /// Errors if either file cannot be read.
+    pub fn of(entry: Option<&Entry>, root_file: &Path, home_file: &Path) -> io::Result<Self> {
+        let base = entry
+            .filter(|entry| entry.kind == Kind::Copy && entry.mode != SYMLINK_MODE)
+            .map(|entry| entry.hash);
+        Ok(Self::from_hashes(
+            base,
+            hash_file(root_file)?,
+            hash_file(home_file)?,
+        ))
+    }
+
+    fn from_hashes(base: Option<u64>, root: u64, home: u64) -> Self {
+        if root == home {
+            return Self::None;
+        }
+        match base {
+            None => Self::Unknown,
+            Some(base) if base == home => Self::Root,
+            Some(base) if base == root => Self::Home,
+            Some(_) => Self::Both,
+        }
+    }
+}
+
+/// The files `deez` deployed from a root into a home.
+///
+/// There is one manifest per root and home pair, stored in
+/// `<state>/manifests/`, and named after a hash of both.
+#[derive(Debug)]
+pub struct Manifest {
+    file: PathBuf,
+    root_id: String,
+    home: PathBuf,
+    entries: BTreeMap<PathBuf, Entry>,
+}
+
+impl Manifest {
+    /// Load the manifest of a root and home pair.
+    ///
+    /// `root_id` identifies the root (e.g., its canonical path). If no
+    /// manifest exists yet, an empty one is returned.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the manifest exists but cannot be read.
+    pub fn load(state_dir: &Path, root_id: &str, home: &Path) -> io::Result<Self> {
+        let key = xxh3_64(format!("{root_id}\0{}", home.display()).as_bytes());
+        let file = state_dir.join("manifests").join(format!("{key:016x}"));
+
+        let mut manifest = Self {
+            file,
+            root_id: root_id.to_string(),
+            home: home.to_path_buf(),
+            entries: BTreeMap::new(),
+        };
+
+        let content = match fs::read_to_string(&manifest.file) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(manifest),
+            Err(err) => return Err(err),
+        };
+
+        for entry in content.lines().filter_map(parse_entry) {
+            manifest.entries.insert(entry.root_path.clone(), entry);
+        }
+
+        Ok(manifest)
+    }
+
+    /// Load the manifests of all the roots deployed into `home`.
+    ///
+    /// Manifests are sorted by root id. This includes the roots that
+    /// don't exist anymore (e.g., that were moved).
+    ///
+    /// # Errors
+    ///
+    /// Errors if the manifests cannot be read.
+    pub fn list(state_dir: &Path, home: &Path) -> io::Result<Vec<Self>> {
+        let dir = match fs::read_dir(state_dir.join("manifests")) {
+            Ok(dir) => dir,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let home_header = format!("# home: {}", home.display());
+
+        let mut manifests = Vec::new();
+        for file in dir {
+            let file = file?.path();
+            // Leftovers of interrupted writes (see `save()`).
+            if file.extension().is_some_and(|extension| extension == "tmp") {
+                continue;
+            }
+
+            let content = fs::read_to_string(&file)?;
+            let mut headers = content.lines().take(2);
+            let (Some(root_id), Some(home_line)) = (
+                headers
+                    .next()
+                    .and_then(|line| line.strip_prefix("# root: ")),
+                headers.next(),
+            ) else {
+                continue;
+            };
+            if home_line != home_header {
+                continue;
+            }
+
+            manifests.push(Self::load(state_dir, root_id, home)?);
+        }
+
+        manifests.sort_by(|a, b| a.root_id.cmp(&b.root_id));
+        Ok(manifests)
+    }
+
+    /// Write the manifest to disk.
+    ///
+    /// An empty manifest is deleted instead.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the manifest cannot be written.
+    pub fn save(&self) -> io::Result<()> {
+        if self.entries.is_empty() {
+            return match fs::remove_file(&self.file) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            };
+        }
+
+        let mut content = format!(
+            "# root: {}\n# home: {}\n",
+            self.root_id,
+            self.home.display()
+        );
+        for entry in self.entries.values() {
+            if let Some(line) = format_entry(entry) {
+                content.push_str(&line);
+            }
+        }
+
+        if let Some(parent) = self.file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Write to a temporary file first, so a crash never leaves a
+        // half-written manifest behind.
+        let tmp = self.file.with_extension("tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, &self.file)
+    }
+
+    /// Identifier of the root (see [`load()`](Self::load)).
+    #[must_use]
+    pub fn root_id(&self) -> &str {
+        &self.root_id
+    }
+
+    #[must_use]
+    pub fn home(&self) -> &Path {
+        &self.home
+    }
+
+    /// Add or replace the entry of a file.
+    pub fn insert(&mut self, entry: Entry) {
+        self.entries.insert(entry.root_path.clone(), entry);
+    }
+
+    /// Remove the entry of a file (given relative to the root).
+    pub fn remove(&mut self, root_path: &Path) -> Option<Entry> {
+        self.entries.remove(root_path)
+    }
+
+    /// Get the entry of a file (given relative to the root).
+    #[must_use]
+    pub fn get(&self, root_path: &Path) -> Option<&Entry> {
+        self.entries.get(root_path)
+    }
+
+    /// All entries, sorted by path.
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.values()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Hash the content of a file (following symlinks).
+///
+/// # Errors
+///
+/// Errors if the file cannot be read.
+pub fn hash_file(path: &Path) -> io::Result<u64> {
+    Ok(xxh3_64(&fs::read(path)?))
+}
+
+/// Hash the target path of a symlink (not the content it points to).
+///
+/// # Errors
+///
+/// Errors if `path` is not a symlink, or if it cannot be read.
+pub fn hash_symlink(path: &Path) -> io::Result<u64> {
+    let target = fs::read_link(path)?;
+    Ok(xxh3_64(target.as_os_str().as_encoded_bytes()))
+}
+
+/// Get the mode of a file (following symlinks).
+///
+/// # Errors
+///
+/// Errors if the file's metadata cannot be read.
+pub fn file_mode(path: &Path) -> io::Result<u32> {
+    let metadata = fs::metadata(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        Ok(metadata.permissions().mode())
+    }
+    #[cfg(not(unix))]
+    {
+        Ok(if metadata.permissions().readonly() {
+            0o100_444
+        } else {
+            0o100_644
+        })
+    }
+}
+
+/// Format an entry: `<kind>\t<mode>\t<hash>\t<timestamp>\t<root path>\t<home path>\n`.
+///
+/// Paths that cannot be represented on a single line are skipped.
+fn format_entry(entry: &Entry) -> Option<String> {
+    let root_path = entry.root_path.to_str()?;
+    let home_path = entry.home_path.to_str()?;
+    if [root_path, home_path]
+        .iter()
+        .any(|path| path.contains(['\t', '\n']))
+    {
+        return None;
+    }
+    Some(format!(
+        "{}\t{:o}\t{:016x}\t{}\t{root_path}\t{home_path}\n",
+        entry.kind, entry.mode, entry.hash, entry.timestamp,
+    ))
+}
+
+fn parse_entry(line: &str) -> Option<Entry> {
+    let mut fields = line.split('\t');
+    Some(Entry {
+        kind: fields.next()?.parse().ok()?,
+        mode: u32::from_str_radix(fields.next()?, 8).ok()?,
+        hash: u64::from_str_radix(fields.next()?, 16).ok()?,
+        timestamp: fields.next()?.parse().ok()?,
+        root_path: PathBuf::from(fields.next()?),
+        home_path: PathBuf::from(fields.next()?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> Entry {
+        Entry {
+            kind: Kind::Copy,
+            mode: 0o100_644,
+            hash: 0xdead_beef,
+            timestamp: 1_738_367_999,
+            root_path: PathBuf::from(".config/fish/config.fish"),
+            home_path: PathBuf::from("/home/deez/.config/fish/config.fish"),
+        }
+    }
+
+    #[test]
+    fn format_entry_regular() {
+        assert_eq!(
+            format_entry(&entry()).unwrap(),
+            "copy\t100644\t00000000deadbeef\t1738367999\t.config/fish/config.fish\t/home/deez/.config/fish/config.fish\n"
+        );
+    }
+
+    #[test]
+    fn format_entry_rejects_tabs_and_newlines() {
+        let mut entry = entry();
+        entry.root_path = PathBuf::from("a\tb");
+        assert_eq!(format_entry(&entry), None);
+    }
+
+    #[test]
+    fn parse_entry_roundtrip() {
+        let line = format_entry(&entry()).unwrap();
+        assert_eq!(parse_entry(line.trim_end()), Some(entry()));
+    }
+
+    #[test]
+    fn parse_entry_link() {
+        let entry = parse_entry("link\t100755\t1\t0\tfoo\t/home/deez/foo").unwrap();
+        assert_eq!(entry.kind, Kind::Link);
+        assert_eq!(entry.mode, 0o100_755);
+    }
+
+    #[test]
+    fn divergence_same_content() {
+        assert_eq!(Divergence::from_hashes(Some(1), 2, 2), Divergence::None);
+        assert_eq!(Divergence::from_hashes(None, 2, 2), Divergence::None);
+    }
+
+    #[test]
+    fn divergence_one_side_changed() {
+        assert_eq!(Divergence::from_hashes(Some(1), 2, 1), Divergence::Root);
+        assert_eq!(Divergence::from_hashes(Some(1), 1, 2), Divergence::Home);
+    }
+
+    #[test]
+    fn divergence_both_sides_changed() {
+        assert_eq!(Divergence::from_hashes(Some(1), 2, 3), Divergence::Both);
+    }
+
+    #[test]
+    fn divergence_without_base() {
+        assert_eq!(Divergence::from_hashes(None, 1, 2), Divergence::Unknown);
+    }
+
+    #[test]
+    fn parse_entry_invalid() {
+        assert_eq!(parse_entry(""), None);
+        assert_eq!(parse_entry("# root: /home/deez/configs"), None);
+        assert_eq!(parse_entry("move\t100644\t1\t0\tfoo\t/home/deez/foo"), None);
+        assert_eq!(
+            parse_entry("copy\t100644\tnot-hex\t0\tfoo\t/home/deez/foo"),
+            None
+        );
+        assert_eq!(parse_entry("copy\t100644\t1\t0\tfoo"), None);
+    }
+}
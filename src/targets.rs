@@ -0,0 +1,298 @@
+//! Where config files go, declared in the root's `.deeztargets` file.
+//!
+//! By default, files go to the home, at the same path as in the root.
+//! Each line of the file maps a directory of the root to another target
+//! directory instead. Blank lines and lines starting with `#` are
+//! ignored.
+//!
+//! ```text
+//! # System configs.
+//! etc/      /etc/
+//! # Follow the XDG spec.
+//! .config/  $XDG_CONFIG_HOME/
+//! ```
+//!
+//! Environment variables (`$VAR` or `${VAR}`) in targets are expanded.
+//! If one is unset (or empty), the mapping is ignored: the files go to
+//! the home. Relative targets (or starting with `~/`) are relative to
+//! the home. When several directories match a path, the deepest one
+//! wins.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::alternates;
+use crate::templates::Templates;
+
+/// Name of the targets file, at the root of a config root.
+pub const TARGETS_FILE: &str = ".deeztargets";
+
+#[derive(Debug)]
+struct Mapping {
+    /// Directory of the root, relative to it.
+    dir: PathBuf,
+    /// Where its files go, absolute or relative to the home.
+    target: PathBuf,
+}
+
+/// The targets of a config root.
+///
+/// A root without a `.deeztargets` file deploys everything to the home.
+#[derive(Debug, Default)]
+pub struct Targets {
+    mappings: Vec<Mapping>,
+}
+
+impl Targets {
+    /// Load the targets of `root`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the targets file exists but cannot be read, or is
+    /// invalid.
+    pub fn load(root: &Path) -> Result<Self, String> {
+        match fs::read_to_string(root.join(TARGETS_FILE)) {
+            Ok(content) => Self::parse(&content, |name| env::var(name).ok()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(format!("Could not read '{TARGETS_FILE}': {err}")),
+        }
+    }
+
+    /// Parse the content of a targets file.
+    ///
+    /// `var` gives the value of environment variables.
+    ///
+    /// # Errors
+    ///
+    /// Errors on invalid lines, and on directories that are not inside
+    /// the root.
+    pub fn parse(content: &str, var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let mut mappings = Vec::new();
+
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |reason: String| format!("{TARGETS_FILE}, line {}: {reason}", i + 1);
+
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let [dir, target] = words[..] else {
+                return Err(error(String::from("Expected a directory, and its target")));
+            };
+
+            let dir = PathBuf::from(dir);
+            let is_inside_root = dir
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+            if !is_inside_root || dir.as_os_str().is_empty() {
+                return Err(error(format!(
+                    "Invalid directory '{}': It must be inside the root",
+                    dir.display()
+                )));
+            }
+
+            let Some(target) = expand_vars(target, &var).map_err(error)? else {
+                // Unset variable, the files go to the home.
+                continue;
+            };
+            let target = match target.strip_prefix('~') {
+                Some(target) if target.is_empty() || target.starts_with('/') => {
+                    PathBuf::from(target.trim_start_matches('/'))
+                }
+                _ => PathBuf::from(target),
+            };
+
+            mappings.push(Mapping { dir, target });
+        }
+
+        Ok(Self { mappings })
+    }
+
+    /// Where `path` (relative to the root) goes, given the `home`.
+    ///
+    /// Alternates go where their plain file would (see [`alternates`]),
+    /// and templates where their rendered file would (see
+    /// [`templates`](crate::templates)).
+    #[must_use]
+    pub fn destination(&self, home: &Path, path: &Path) -> PathBuf {
+        let path = alternates::base_path(path);
+        let path = Templates::rendered_path(&path);
+        let path = path.as_ref();
+        let mapping = self
+            .mappings
+            .iter()
+            .filter(|mapping| path.starts_with(&mapping.dir))
+            .max_by_key(|mapping| mapping.dir.components().count());
+
+        match mapping {
+            Some(mapping) => home.join(&mapping.target).join(
+                path.strip_prefix(&mapping.dir)
+                    .expect("the path starts with the directory"),
+            ),
+            None => home.join(path),
+        }
+    }
+
+    /// Whether no directory is mapped (all files go to the home).
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+}
+
+/// Expand `$VAR` and `${VAR}` in `s`.
+///
+/// Returns `None` if a variable is unset, or empty.
+fn expand_vars(s: &str, var: impl Fn(&str) -> Option<String>) -> Result<Option<String>, String> {
+    let mut expanded = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+
+        let (name, after) = if let Some(braced) = rest.strip_prefix('{') {
+            let Some(end) = braced.find('}') else {
+                return Err(format!("Unclosed variable in '{s}'"));
+            };
+            (&braced[..end], &braced[end + 1..])
+        } else {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+        if name.is_empty() {
+            return Err(format!("Invalid variable in '{s}'"));
+        }
+
+        match var(name) {
+            Some(value) if !value.is_empty() => expanded.push_str(&value),
+            _ => return Ok(None),
+        }
+        rest = after;
+    }
+    expanded.push_str(rest);
+
+    Ok(Some(expanded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(name: &str) -> Option<String> {
+        match name {
+            "XDG_CONFIG_HOME" => Some(String::from("/home/deez/.xdg")),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn empty_file_maps_nothing() {
+        let targets = Targets::parse("# Comment.\n\n", var).unwrap();
+        assert!(targets.is_empty());
+        assert_eq!(
+            targets.destination(
+                Path::new("/home/deez"),
+                Path::new(".config/fish/config.fish")
+            ),
+            Path::new("/home/deez/.config/fish/config.fish")
+        );
+    }
+
+    #[test]
+    fn absolute_target() {
+        let targets = Targets::parse("etc/ /etc/", var).unwrap();
+        assert_eq!(
+            targets.destination(Path::new("/home/deez"), Path::new("etc/hosts.d/lan")),
+            Path::new("/etc/hosts.d/lan")
+        );
+        // Only whole components match.
+        assert_eq!(
+            targets.destination(Path::new("/home/deez"), Path::new("etcetera")),
+            Path::new("/home/deez/etcetera")
+        );
+    }
+
+    #[test]
+    fn relative_target_is_relative_to_home() {
+        let targets = Targets::parse("share .local/share\nbin ~/.local/bin\n", var).unwrap();
+        assert_eq!(
+            targets.destination(Path::new("/home/deez"), Path::new("share/foo")),
+            Path::new("/home/deez/.local/share/foo")
+        );
+        assert_eq!(
+            targets.destination(Path::new("/home/deez"), Path::new("bin/foo")),
+            Path::new("/home/deez/.local/bin/foo")
+        );
+    }
+
+    #[test]
+    fn variables_are_expanded() {
+        let targets = Targets::parse(
+            ".config $XDG_CONFIG_HOME\n.cache ${XDG_CONFIG_HOME}/../cache\n",
+            var,
+        )
+        .unwrap();
+        assert_eq!(
+            targets.destination(
+                Path::new("/home/deez"),
+                Path::new(".config/fish/config.fish")
+            ),
+            Path::new("/home/deez/.xdg/fish/config.fish")
+        );
+        assert_eq!(
+            targets.destination(Path::new("/home/deez"), Path::new(".cache/foo")),
+            Path::new("/home/deez/.xdg/../cache/foo")
+        );
+    }
+
+    #[test]
+    fn unset_variables_ignore_the_mapping() {
+        let targets = Targets::parse(".config $UNSET/\n.cache $EMPTY/\n", var).unwrap();
+        assert!(targets.is_empty());
+    }
+
+    #[test]
+    fn deepest_directory_wins() {
+        let targets = Targets::parse("etc /etc\netc/nginx /opt/nginx/etc\n", var).unwrap();
+        assert_eq!(
+            targets.destination(Path::new("/home/deez"), Path::new("etc/nginx/nginx.conf")),
+            Path::new("/opt/nginx/etc/nginx.conf")
+        );
+        assert_eq!(
+            targets.destination(Path::new("/home/deez"), Path::new("etc/hosts")),
+            Path::new("/etc/hosts")
+        );
+    }
+
+    #[test]
+    fn directory_outside_root_is_an_error() {
+        let err = Targets::parse("../etc /etc", var).unwrap_err();
+        assert_eq!(
+            err,
+            ".deeztargets, line 1: Invalid directory '../etc': It must be inside the root"
+        );
+        assert!(Targets::parse("/etc /etc", var).is_err());
+    }
+
+    #[test]
+    fn line_without_target_is_an_error() {
+        let err = Targets::parse("etc /etc\nusr\n", var).unwrap_err();
+        assert_eq!(
+            err,
+            ".deeztargets, line 2: Expected a directory, and its target"
+        );
+    }
+
+    #[test]
+    fn unclosed_variable_is_an_error() {
+        assert!(Targets::parse(".config ${XDG_CONFIG_HOME", var).is_err());
+    }
+}
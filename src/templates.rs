@@ -0,0 +1,331 @@
+//! Config files rendered from templates.
+//!
+//! A file named `<name>.tmpl` is a template: it is deployed as `<name>`,
+//! with every `{{ variable }}` in it replaced by its value.
+//!
+//! - `os` is the name of the operating system (e.g., `linux`).
+//! - `host` is the host name (see [`alternates`]).
+//! - `user` is the name of the current user.
+//! - `env.<NAME>` is the value of the `NAME` environment variable.
+//!
+//! More variables can be defined in the root's `.deezvars` file, one
+//! `<name> = <value>` per line. Blank lines and lines starting with `#`
+//! are ignored. They take precedence over the built-in ones.
+//!
+//! ```text
+//! # .deezvars
+//! email = deez@example.com
+//! ```
+//!
+//! Unknown (or unset) variables are errors. What's between braces but
+//! can't be a variable name is left as is, so are single braces.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::{alternates, utils};
+
+/// Extension of template files.
+pub const TEMPLATE_EXTENSION: &str = "tmpl";
+
+/// Name of the variables file, at the root of a config root.
+pub const VARIABLES_FILE: &str = ".deezvars";
+
+/// The templates of a config root, and where they get rendered.
+///
+/// Templates are rendered into a temporary directory, deleted on drop.
+/// This way, commands compare, copy and diff the rendered files like
+/// any other file.
+#[derive(Debug)]
+pub struct Templates {
+    /// User-defined variables.
+    variables: BTreeMap<String, String>,
+    /// Where rendered templates go, created on first render.
+    ///
+    /// It is private to the user, since templates may render secrets.
+    render_dir: OnceLock<PathBuf>,
+}
+
+impl Templates {
+    /// Load the variables of `root`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the variables file exists but cannot be read, or is
+    /// invalid.
+    pub fn load(root: &Path) -> Result<Self, String> {
+        let variables = match fs::read_to_string(root.join(VARIABLES_FILE)) {
+            Ok(content) => parse_variables(&content)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(format!("Could not read '{VARIABLES_FILE}': {err}")),
+        };
+
+        Ok(Self {
+            variables,
+            render_dir: OnceLock::new(),
+        })
+    }
+
+    /// Whether `path` (relative to the root) is a template.
+    #[must_use]
+    pub fn is_template(path: &Path) -> bool {
+        alternates::base_path(path)
+            .extension()
+            .is_some_and(|extension| extension == TEMPLATE_EXTENSION)
+    }
+
+    /// The path `path` (relative to the root) is deployed as, without
+    /// its template extension (if any).
+    #[must_use]
+    pub fn rendered_path(path: &Path) -> Cow<'_, Path> {
+        if path
+            .extension()
+            .is_some_and(|extension| extension == TEMPLATE_EXTENSION)
+        {
+            Cow::Owned(path.with_extension(""))
+        } else {
+            Cow::Borrowed(path)
+        }
+    }
+
+    /// The file to deploy for `path` (relative to `root`).
+    ///
+    /// For templates, this is the rendered file. For other files, this
+    /// is the file in `root`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the template cannot be read, rendered, or written.
+    pub fn source(&self, root: &Path, path: &Path) -> Result<PathBuf, String> {
+        let template = root.join(path);
+        if !Self::is_template(path) {
+            return Ok(template);
+        }
+
+        let content = fs::read_to_string(&template)
+            .map_err(|err| format!("Could not read template '{}': {err}", path.display()))?;
+        let rendered = self
+            .render(&content)
+            .map_err(|err| format!("Could not render '{}': {err}", path.display()))?;
+
+        self.write_rendered(&template, path, &rendered)
+            .map_err(|err| format!("Could not render '{}': {err}", path.display()))
+    }
+
+    /// Write the `rendered` content of `template` (`path` relative to the
+    /// root) into the render directory.
+    fn write_rendered(&self, template: &Path, path: &Path, rendered: &str) -> io::Result<PathBuf> {
+        let render_dir = match self.render_dir.get() {
+            Some(render_dir) => render_dir,
+            None => {
+                let render_dir = utils::create_private_temp_dir("deez-templates")?;
+                // Files are walked in parallel, another thread may have
+                // beaten us to it.
+                if let Err(render_dir) = self.render_dir.set(render_dir) {
+                    _ = fs::remove_dir_all(render_dir);
+                }
+                self.render_dir.get().expect("just set")
+            }
+        };
+
+        let output = render_dir.join(path);
+        fs::create_dir_all(
+            output
+                .parent()
+                .expect("at the bare minimum, `parent` is the render directory"),
+        )?;
+        // Rendering the same template twice is fine, the directory is ours.
+        match fs::remove_file(&output) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => (),
+        }
+        let mut file = utils::create_new_file(&output, fs::metadata(template)?.permissions())?;
+        file.write_all(rendered.as_bytes())?;
+
+        Ok(output)
+    }
+
+    /// Replace the variables in `content` by their value.
+    ///
+    /// # Errors
+    ///
+    /// Errors on unknown variables.
+    pub fn render(&self, content: &str) -> Result<String, String> {
+        let mut rendered = String::with_capacity(content.len());
+
+        for (i, line) in content.split_inclusive('\n').enumerate() {
+            let mut rest = line;
+            while let Some(start) = rest.find("{{") {
+                let Some(end) = rest[start + 2..].find("}}").map(|end| start + 2 + end) else {
+                    break;
+                };
+
+                let name = rest[start + 2..end].trim();
+                if !is_variable_name(name) {
+                    // Not ours, leave it as is.
+                    rendered.push_str(&rest[..end + 2]);
+                    rest = &rest[end + 2..];
+                    continue;
+                }
+
+                let Some(value) = self.value(name) else {
+                    return Err(format!("Unknown variable '{name}' on line {}", i + 1));
+                };
+                rendered.push_str(&rest[..start]);
+                rendered.push_str(&value);
+                rest = &rest[end + 2..];
+            }
+            rendered.push_str(rest);
+        }
+
+        Ok(rendered)
+    }
+
+    fn value(&self, name: &str) -> Option<Cow<'_, str>> {
+        if let Some(value) = self.variables.get(name) {
+            return Some(Cow::Borrowed(value));
+        }
+        match name {
+            "os" => Some(Cow::Borrowed(env::consts::OS)),
+            "host" => alternates::host().map(Cow::Borrowed),
+            "user" => env::var("USER")
+                .or_else(|_| env::var("USERNAME"))
+                .ok()
+                .map(Cow::Owned),
+            _ => env::var(name.strip_prefix("env.")?).ok().map(Cow::Owned),
+        }
+    }
+}
+
+impl Drop for Templates {
+    fn drop(&mut self) {
+        if let Some(render_dir) = self.render_dir.get() {
+            _ = fs::remove_dir_all(render_dir);
+        }
+    }
+}
+
+fn is_variable_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// Parse the content of a variables file.
+fn parse_variables(content: &str) -> Result<BTreeMap<String, String>, String> {
+    let mut variables = BTreeMap::new();
+
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let error = |reason: String| format!("{VARIABLES_FILE}, line {}: {reason}", i + 1);
+
+        let Some((name, value)) = line.split_once('=') else {
+            return Err(error(String::from("Expected a name, '=', and a value")));
+        };
+        let name = name.trim();
+        if !is_variable_name(name) {
+            return Err(error(format!("Invalid variable name '{name}'")));
+        }
+
+        variables.insert(name.to_string(), value.trim().to_string());
+    }
+
+    Ok(variables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn templates(variables: &str) -> Templates {
+        Templates {
+            variables: parse_variables(variables).unwrap(),
+            render_dir: OnceLock::new(),
+        }
+    }
+
+    #[test]
+    fn templates_are_detected_by_extension() {
+        assert!(Templates::is_template(Path::new(".gitconfig.tmpl")));
+        assert!(Templates::is_template(Path::new(
+            ".gitconfig.tmpl##host.laptop"
+        )));
+        assert!(!Templates::is_template(Path::new(".gitconfig")));
+        assert!(!Templates::is_template(Path::new(".tmpl")));
+    }
+
+    #[test]
+    fn rendered_path_strips_extension() {
+        assert_eq!(
+            Templates::rendered_path(Path::new(".config/git/config.tmpl")),
+            Path::new(".config/git/config")
+        );
+        assert_eq!(
+            Templates::rendered_path(Path::new(".bashrc")),
+            Path::new(".bashrc")
+        );
+    }
+
+    #[test]
+    fn variables_are_replaced() {
+        let templates = templates("email = deez@example.com\n");
+        assert_eq!(
+            templates
+                .render("[user]\n    email = {{ email }}\n    os = {{os}}\n")
+                .unwrap(),
+            format!(
+                "[user]\n    email = deez@example.com\n    os = {}\n",
+                env::consts::OS
+            )
+        );
+    }
+
+    #[test]
+    fn user_variables_take_precedence() {
+        let templates = templates("os = plan9\n");
+        assert_eq!(templates.render("{{ os }}").unwrap(), "plan9");
+    }
+
+    #[test]
+    fn environment_variables_are_replaced() {
+        let templates = templates("");
+        let path = env::var("PATH").unwrap();
+        assert_eq!(templates.render("{{ env.PATH }}").unwrap(), path);
+        assert!(templates.render("{{ env.DEEZ_UNSET_VARIABLE }}").is_err());
+    }
+
+    #[test]
+    fn unknown_variable_is_an_error() {
+        let templates = templates("");
+        assert_eq!(
+            templates.render("ok\n{{ unknown }}\n").unwrap_err(),
+            "Unknown variable 'unknown' on line 2"
+        );
+    }
+
+    #[test]
+    fn non_variables_are_left_as_is() {
+        let templates = templates("");
+        let content = "{{ .Go }} {{}} { os } {{ unclosed\n";
+        assert_eq!(templates.render(content).unwrap(), content);
+    }
+
+    #[test]
+    fn invalid_variables_file_is_an_error() {
+        assert_eq!(
+            parse_variables("email\n").unwrap_err(),
+            ".deezvars, line 1: Expected a name, '=', and a value"
+        );
+        assert!(parse_variables("e mail = x\n").is_err());
+    }
+}
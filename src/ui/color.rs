@@ -65,6 +65,16 @@ impl Color {
         Self::color(BLUE, string)
     }
 
+    #[must_use]
+    pub fn strategy(string: &str) -> Cow<'_, str> {
+        Self::color(ATTENUATE, string)
+    }
+
+    #[must_use]
+    pub fn layer(string: &str) -> Cow<'_, str> {
+        Self::color(BOLD_PURPLE, string)
+    }
+
     // Diff.
 
     #[must_use]
@@ -17,11 +17,52 @@ pub fn ask_confirmation_with_prompt(prompt: &str) -> bool {
     matches!(answer.to_ascii_lowercase().trim(), "y" | "yes")
 }
 
+/// Prompt the user to pick one of `choices` (single letters).
+///
+/// The prompt is repeated until a valid choice is given. Returns `None`
+/// if there is no more input to read (e.g., stdin is closed).
+#[must_use]
+pub fn ask_choice_with_prompt(prompt: &str, choices: &[char]) -> Option<char> {
+    let choices_list = choices
+        .iter()
+        .map(char::to_string)
+        .collect::<Vec<_>>()
+        .join(",");
+
+    loop {
+        print!("{prompt} [{choices_list}] ");
+        _ = io::stdout().flush();
+
+        let mut answer = String::new();
+        match io::stdin().read_line(&mut answer) {
+            Ok(0) => {
+                // End the prompt's line.
+                println!();
+                return None;
+            }
+            Ok(_) => (),
+            Err(_) => {
+                eprintln!("Error reading user input.");
+                return None;
+            }
+        }
+
+        let answer = answer.trim().to_ascii_lowercase();
+        let mut chars = answer.chars();
+        if let (Some(choice), None) = (chars.next(), chars.next())
+            && choices.contains(&choice)
+        {
+            return Some(choice);
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Action {
     Sync,
     RSync,
     Link,
+    Deploy,
     Clean,
 }
 
@@ -31,11 +72,67 @@ impl fmt::Display for Action {
             Self::Sync => write!(f, "Synced"),
             Self::RSync => write!(f, "rSynced"),
             Self::Link => write!(f, "Linked"),
+            Self::Deploy => write!(f, "Deployed"),
             Self::Clean => write!(f, "Removed"),
         }
     }
 }
 
+impl Action {
+    /// Verb used in dry-run summaries ("Would sync 3 files.").
+    #[must_use]
+    pub fn infinitive(&self) -> &'static str {
+        match self {
+            Self::Sync => "sync",
+            Self::RSync => "rsync",
+            Self::Link => "link",
+            Self::Deploy => "deploy",
+            Self::Clean => "remove",
+        }
+    }
+}
+
+/// What a mutating command does (or would do, in dry-run) to a file.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Operation {
+    /// The file does not exist yet.
+    Create,
+    /// The file exists and its content will be replaced.
+    Overwrite,
+    /// The file has the same content, but its permissions get replaced.
+    UpdateMode,
+    /// A symlink is replaced by a file, or by a different symlink.
+    ReplaceSymlink,
+    /// An empty directory stands in the way, and gets removed.
+    RemoveDir,
+    /// The file gets removed.
+    Delete,
+    /// The file is already up-to-date, or there is nothing to remove.
+    NoOp,
+    /// The file changed on the receiving end only, and is left as is.
+    Skip,
+    /// The file changed on both ends, and is left as is.
+    Conflict,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operation = match self {
+            Self::Create => "create",
+            Self::Overwrite => "overwrite",
+            Self::UpdateMode => "update mode",
+            Self::ReplaceSymlink => "replace symlink",
+            Self::RemoveDir => "remove dir",
+            Self::Delete => "delete",
+            Self::NoOp => "no-op",
+            Self::Skip => "skip",
+            Self::Conflict => "conflict",
+        };
+        // Honour width/alignment, so operations can be lined up.
+        f.pad(operation)
+    }
+}
+
 pub fn print_files(files: &[String]) {
     let mut stdout = io::stdout().lock();
     for file in files {
@@ -43,14 +140,31 @@ pub fn print_files(files: &[String]) {
     }
 }
 
+/// Print planned operations, one file per line (dry-run).
+pub fn print_operations(operations: &[(String, Operation)]) {
+    // Longest operation ("replace symlink"), so files line up.
+    const WIDTH: usize = 15;
+
+    let mut stdout = io::stdout().lock();
+    for (file, operation) in operations {
+        _ = writeln!(stdout, "{operation:<WIDTH$}  {file}");
+    }
+}
+
+/// Print how many files were processed.
+///
+/// Skipped files are files left as is on purpose (e.g., to keep edits
+/// made on the receiving end); they are only mentioned if there are
+/// any.
 pub fn print_summary(
     action: Action,
     root: impl AsRef<Path>,
     nb_files: usize,
+    nb_skipped: usize,
     nb_errors: usize,
     nb_hooks_ran: usize,
 ) {
-    print_files_summary(action, root, nb_files, nb_errors);
+    print_files_summary(action, root, nb_files, nb_skipped, nb_errors);
     print_hooks_summary(nb_hooks_ran);
 }
 
@@ -58,11 +172,12 @@ pub fn print_files_summary(
     action: Action,
     root: impl AsRef<Path>,
     nb_files: usize,
+    nb_skipped: usize,
     nb_errors: usize,
 ) {
     let root = root.as_ref();
 
-    if nb_files + nb_errors == 0 {
+    if nb_files + nb_skipped + nb_errors == 0 {
         println!("No config files found in '{}'.", root.display());
     }
 
@@ -73,6 +188,9 @@ pub fn print_files_summary(
         "{action} {nb_files} file{}",
         if nb_files == 1 { "" } else { "s" }
     );
+    if nb_skipped > 0 {
+        _ = write!(stdout, ", {nb_skipped} skipped");
+    }
     if nb_errors > 0 {
         _ = write!(
             stdout,
@@ -83,6 +201,91 @@ pub fn print_files_summary(
     _ = writeln!(stdout, ".");
 }
 
+/// Same as [`print_summary()`], but for dry-runs.
+pub fn print_dry_run_summary(
+    action: Action,
+    root: impl AsRef<Path>,
+    nb_files: usize,
+    nb_skipped: usize,
+    nb_errors: usize,
+    nb_hooks_ran: usize,
+) {
+    let root = root.as_ref();
+
+    if nb_files + nb_skipped + nb_errors == 0 {
+        println!("No config files found in '{}'.", root.display());
+    }
+
+    let mut stdout = io::stdout().lock();
+
+    _ = write!(
+        stdout,
+        "Would {} {nb_files} file{}",
+        action.infinitive(),
+        if nb_files == 1 { "" } else { "s" }
+    );
+    if nb_skipped > 0 {
+        _ = write!(stdout, ", {nb_skipped} skipped");
+    }
+    if nb_errors > 0 {
+        _ = write!(
+            stdout,
+            ", {nb_errors} error{}",
+            if nb_errors == 1 { "" } else { "s" }
+        );
+    }
+    _ = writeln!(stdout, " (dry run).");
+    drop(stdout);
+
+    print_hooks_summary(nb_hooks_ran);
+}
+
+/// Print how many files deleted from the root were removed from home.
+pub fn print_prune_summary(nb_files: usize, dry_run: bool) {
+    let plural = if nb_files == 1 { "" } else { "s" };
+    if dry_run {
+        println!("Would prune {nb_files} file{plural} (dry run).");
+    } else {
+        println!("Pruned {nb_files} file{plural}.");
+    }
+}
+
+/// Print files that changed on both ends, and how to resolve them.
+pub fn print_conflicts(conflicts: &[String]) {
+    if conflicts.is_empty() {
+        return;
+    }
+
+    let mut stderr = io::stderr().lock();
+
+    _ = writeln!(
+        stderr,
+        "{error}: {} file{} changed in both the root and the home since the last sync:",
+        conflicts.len(),
+        if conflicts.len() == 1 { "" } else { "s" },
+        error = super::Color::error("error"),
+    );
+    for file in conflicts {
+        _ = writeln!(stderr, "  {file}");
+    }
+    _ = write!(
+        stderr,
+        "\
+Review them with `diff`, then re-run with `--ours` to keep them as they
+are, or with `--theirs` to overwrite them.
+"
+    );
+}
+
+/// Print where overwritten files were backed up.
+pub fn print_backup_summary(backup_dir: impl AsRef<Path>, nb_files: usize) {
+    println!(
+        "Backed up {nb_files} file{} to '{}'.",
+        if nb_files == 1 { "" } else { "s" },
+        backup_dir.as_ref().display()
+    );
+}
+
 pub fn print_hooks_summary(nb_hooks_ran: usize) {
     if nb_hooks_ran == 0 {
         return;
@@ -1,6 +1,13 @@
-use std::fs::File;
-use std::io::{self, Read};
-use std::path::Path;
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process;
+
+/// Suffix of the temporary files written before being renamed into place.
+///
+/// See [`copy_atomically()`] and [`symlink_atomically()`].
+const TEMP_FILE_SUFFIX: &str = ".deez-tmp";
 
 /// Read to pre-allocated `String` buffer.
 ///
@@ -29,5 +36,410 @@ pub fn read_to_string_buffer(buffer: &mut String, path: &Path) -> io::Result<usi
 pub fn read_to_bytes_buffer(buffer: &mut Vec<u8>, path: &Path) -> io::Result<usize> {
     let mut file = File::open(path)?;
     buffer.clear();
-    file.read(buffer)
+    file.read_to_end(buffer)
+}
+
+/// Compare the permissions of two files.
+///
+/// Symlinks are followed: it is the permissions of their targets that
+/// are compared. On Unix, these are the mode bits (e.g., `0o755`).
+///
+/// # Errors
+///
+/// Errors if the metadata of either file cannot be read.
+pub fn are_permissions_equal(a: &Path, b: &Path) -> io::Result<bool> {
+    Ok(fs::metadata(a)?.permissions() == fs::metadata(b)?.permissions())
+}
+
+/// Give `to` the same permissions as `from`.
+///
+/// Symlinks are followed, on both ends.
+///
+/// # Errors
+///
+/// Errors if the metadata of `from` cannot be read, or if the
+/// permissions of `to` cannot be changed.
+pub fn copy_permissions(from: &Path, to: &Path) -> io::Result<()> {
+    fs::set_permissions(to, fs::metadata(from)?.permissions())
+}
+
+/// Compare the contents of two files.
+///
+/// Symlinks are followed: it is the contents of their targets that
+/// are compared.
+///
+/// # Errors
+///
+/// Errors if either file cannot be read.
+pub fn are_files_equal(a: &Path, b: &Path) -> io::Result<bool> {
+    // Possible improvements if this is a bottleneck:
+    //  - Compare _streaming_ bytes, to cater for big files.
+    //  - Compare hashes (e.g., xxHashes) if we do the streaming.
+    //    Streaming is slower because you have to jump back-and-forth
+    //    between files.
+
+    // 1. Compare by file size (quick).
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+
+    // 2. Compare contents (slow; as raw bytes to avoid UTF-8 overhead).
+    thread_local! {
+        static BUFFERS: RefCell<(Vec<u8>, Vec<u8>)> = RefCell::new(
+            // 64 Kb should be plenty for the majority of config files.
+            (Vec::with_capacity(65_536), Vec::with_capacity(65_536))
+        );
+    }
+
+    BUFFERS.with_borrow_mut(|(a_buf, b_buf)| {
+        read_to_bytes_buffer(a_buf, a)?;
+        read_to_bytes_buffer(b_buf, b)?;
+
+        Ok(a_buf == b_buf)
+    })
+}
+
+/// Copy `from` onto `to`, atomically.
+///
+/// The copy is written to a temporary sibling of `to`, flushed to disk,
+/// then renamed into place. Whatever happens (e.g., full disk,
+/// interrupted run, power loss), `to` is always either the old file or
+/// the new one, never a partial copy.
+///
+/// Same as [`std::fs::copy()`], symlinks are followed when reading
+/// `from`, and permissions are copied. If `to` is a symlink, the symlink
+/// itself is replaced, not its target.
+///
+/// # Errors
+///
+/// Errors if `from` cannot be read, or if `to` cannot be written. In
+/// both cases, `to` is left untouched.
+pub fn copy_atomically(from: &Path, to: &Path) -> io::Result<()> {
+    let temp = temp_sibling(to);
+    // New files don't overwrite anything, clear leftovers first.
+    _ = fs::remove_file(&temp);
+
+    let copy = || -> io::Result<()> {
+        let mut source = File::open(from)?;
+        let mut file = create_new_file(&temp, source.metadata()?.permissions())?;
+        io::copy(&mut source, &mut file)?;
+        // Without this, the rename may reach the disk before the data.
+        file.sync_all()
+    };
+    replace_with(&temp, to, copy())
+}
+
+/// Write `contents` to `path`, atomically.
+///
+/// Like [`copy_atomically()`], the contents are written to a temporary
+/// sibling of `path`, with the given `permissions`, then renamed into
+/// place.
+///
+/// # Errors
+///
+/// Errors if `path` cannot be written. In this case, `path` is left
+/// untouched.
+pub fn write_atomically(
+    path: &Path,
+    contents: &[u8],
+    permissions: fs::Permissions,
+) -> io::Result<()> {
+    let temp = temp_sibling(path);
+    // New files don't overwrite anything, clear leftovers first.
+    _ = fs::remove_file(&temp);
+
+    let write = || -> io::Result<()> {
+        let mut file = create_new_file(&temp, permissions)?;
+        file.write_all(contents)?;
+        file.sync_all()
+    };
+    replace_with(&temp, path, write())
+}
+
+/// Create a symlink at `link`, pointing to `target`, atomically.
+///
+/// Like [`copy_atomically()`], the symlink is created next to `link`,
+/// then renamed into place, replacing whatever file or symlink was
+/// there.
+///
+/// # Errors
+///
+/// Errors if the symlink cannot be created. In this case, `link` is
+/// left untouched.
+pub fn symlink_atomically(target: &Path, link: &Path) -> io::Result<()> {
+    let temp = temp_sibling(link);
+    // Symlinks don't overwrite anything, clear leftovers first.
+    _ = fs::remove_file(&temp);
+
+    #[cfg(unix)]
+    let res = std::os::unix::fs::symlink(target, &temp);
+    // Windows tells links to directories apart (e.g., folded ones).
+    #[cfg(windows)]
+    let res = if link
+        .parent()
+        .is_some_and(|parent| parent.join(target).is_dir())
+    {
+        std::os::windows::fs::symlink_dir(target, &temp)
+    } else {
+        std::os::windows::fs::symlink_file(target, &temp)
+    };
+
+    replace_with(&temp, link, res)
+}
+
+/// Create a new directory under the system temp dir, private to the
+/// current user.
+///
+/// The directory is named after `prefix`, and is guaranteed to be new:
+/// if a directory (or anything else) already exists with that name, the
+/// next name is tried. On Unix, it is only accessible to its owner
+/// (`0o700`), from the start.
+///
+/// # Errors
+///
+/// Errors if the directory cannot be created.
+pub fn create_private_temp_dir(prefix: &str) -> io::Result<PathBuf> {
+    let pid = process::id();
+    let uuid = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("current time > Unix epoch")
+        .as_millis();
+
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+
+    let mut attempt = 0;
+    loop {
+        let dir = std::env::temp_dir().join(format!("{prefix}-{pid}-{uuid}-{attempt}"));
+        match builder.create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => {
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Create a new file at `path`, with the given permissions.
+///
+/// This fails if anything exists at `path` (symlinks included, they are
+/// not followed). The file has its final permissions before anything is
+/// written to it.
+///
+/// # Errors
+///
+/// Errors if the file already exists, or cannot be created.
+pub fn create_new_file(path: &Path, permissions: fs::Permissions) -> io::Result<File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    // Never more permissive than the final permissions, even briefly.
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(
+        &mut options,
+        std::os::unix::fs::PermissionsExt::mode(&permissions),
+    );
+
+    let file = options.open(path)?;
+    // The mode above is subject to the umask, the permissions are not.
+    file.set_permissions(permissions)?;
+    Ok(file)
+}
+
+/// Whether `path` is a temporary file left by an interrupted write.
+///
+/// See [`copy_atomically()`] and [`symlink_atomically()`].
+#[must_use]
+pub fn is_temp_file(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().ends_with(TEMP_FILE_SUFFIX))
+}
+
+/// Path to `target`, relative to the directory `dir`.
+///
+/// This is purely lexical: both paths must be absolute and normalized
+/// (e.g., canonicalized) for the result to make sense.
+#[must_use]
+pub fn relative_path(dir: &Path, target: &Path) -> PathBuf {
+    let mut dir = dir.components().peekable();
+    let mut target = target.components().peekable();
+
+    // Skip the common prefix.
+    while let (Some(a), Some(b)) = (dir.peek(), target.peek()) {
+        if a != b {
+            break;
+        }
+        dir.next();
+        target.next();
+    }
+
+    dir.map(|_| Component::ParentDir).chain(target).collect()
+}
+
+/// Read the target of the symlink `link`, as an absolute path.
+///
+/// Relative targets are relative to the directory the link lives in.
+/// They are resolved lexically (from the canonical path of that
+/// directory), so the target need not exist.
+///
+/// # Errors
+///
+/// Errors if `link` is not a symlink, or if its directory cannot be
+/// canonicalized.
+pub fn read_link_absolute(link: &Path) -> io::Result<PathBuf> {
+    let target = fs::read_link(link)?;
+    if target.is_absolute() {
+        return Ok(target);
+    }
+
+    let dir = match link.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::canonicalize(parent)?,
+        _ => std::env::current_dir()?,
+    };
+
+    let mut absolute = dir;
+    for component in target.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            component => absolute.push(component),
+        }
+    }
+    Ok(absolute)
+}
+
+/// Hidden file next to `path`, unique to this process.
+fn temp_sibling(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{name}.{}{TEMP_FILE_SUFFIX}", process::id()))
+}
+
+/// Rename `temp` onto `path` if it was written successfully.
+///
+/// Either way, `temp` doesn't outlive this function. On success, the
+/// parent directory is flushed to disk, for the rename to persist.
+fn replace_with(temp: &Path, path: &Path, written: io::Result<()>) -> io::Result<()> {
+    let res = written.and_then(|()| fs::rename(temp, path));
+    if res.is_err() {
+        _ = fs::remove_file(temp);
+    }
+    res?;
+    sync_parent_dir(path)
+}
+
+/// Flush the directory holding `path` to disk.
+///
+/// Renames (and new files) are changes to the directory, not the file.
+/// Windows has no equivalent, there is nothing to do.
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    if cfg!(unix) {
+        File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temp_sibling_is_a_temp_file() {
+        let temp = temp_sibling(Path::new("/home/deez/.bashrc"));
+        assert_eq!(temp.parent(), Some(Path::new("/home/deez")));
+        assert!(is_temp_file(&temp));
+    }
+
+    #[test]
+    fn regular_file_is_not_a_temp_file() {
+        assert!(!is_temp_file(Path::new("/home/deez/.bashrc")));
+    }
+
+    #[test]
+    fn relative_path_goes_up_then_down() {
+        assert_eq!(
+            relative_path(
+                Path::new("/home/deez/.config/fish"),
+                Path::new("/home/deez/configs/.config/fish/config.fish")
+            ),
+            Path::new("../../configs/.config/fish/config.fish")
+        );
+    }
+
+    #[test]
+    fn relative_path_in_same_directory() {
+        assert_eq!(
+            relative_path(Path::new("/home/deez"), Path::new("/home/deez/.bashrc")),
+            Path::new(".bashrc")
+        );
+    }
+
+    #[test]
+    fn relative_path_without_common_prefix() {
+        assert_eq!(
+            relative_path(Path::new("/home/deez"), Path::new("/opt/configs/.bashrc")),
+            Path::new("../../opt/configs/.bashrc")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn private_temp_dir_is_new_and_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let a = create_private_temp_dir("deez-test").unwrap();
+        let b = create_private_temp_dir("deez-test").unwrap();
+        assert_ne!(a, b);
+        assert_eq!(
+            fs::metadata(&a).unwrap().permissions().mode() & 0o777,
+            0o700
+        );
+
+        // Symlinks are not followed.
+        let target = a.join("target");
+        let link = a.join("link");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        let file = create_new_file(&link, fs::Permissions::from_mode(0o640));
+        assert_eq!(file.unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        assert!(!target.exists());
+
+        let file = create_new_file(&target, fs::Permissions::from_mode(0o640));
+        assert!(file.is_ok());
+        assert_eq!(
+            fs::metadata(&target).unwrap().permissions().mode() & 0o777,
+            0o640
+        );
+
+        fs::remove_dir_all(a).unwrap();
+        fs::remove_dir_all(b).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_atomically_copies_read_only_files() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = create_private_temp_dir("deez-test").unwrap();
+        let from = dir.join("from");
+        let to = dir.join("to");
+        fs::write(&from, "from").unwrap();
+        fs::set_permissions(&from, fs::Permissions::from_mode(0o444)).unwrap();
+        fs::write(&to, "to").unwrap();
+
+        copy_atomically(&from, &to).unwrap();
+        assert_eq!(fs::read_to_string(&to).unwrap(), "from");
+        assert_eq!(
+            fs::metadata(&to).unwrap().permissions().mode() & 0o777,
+            0o444
+        );
+        assert!(!temp_sibling(&to).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -1,10 +1,17 @@
 use std::ffi::OsStr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
-use ignore::{self, DirEntry, WalkBuilder, WalkState};
+use ignore::gitignore::GitignoreBuilder;
+use ignore::{self, DirEntry, Match, WalkBuilder, WalkState};
 
+use crate::alternates;
+use crate::attributes::ATTRIBUTES_FILE;
 use crate::hooks;
 use crate::pathspec::PathSpec;
+use crate::targets::TARGETS_FILE;
+use crate::templates::{self, Templates};
+use crate::utils;
 
 /// Find files recursively, starting from `root` directory.
 ///
@@ -20,7 +27,11 @@ use crate::pathspec::PathSpec;
 ///
 /// `pathspec` filters which files reach `f`: only files matching it are
 /// yielded. An empty `pathspec` matches everything. We only filter
-/// files, not directories.
+/// files, not directories. Alternates match by their own path, and by
+/// the path they are deployed as.
+///
+/// Only the alternates picked for this machine are yielded (see
+/// [`alternates`]).
 ///
 /// # Panics
 ///
@@ -32,34 +43,6 @@ pub fn find_files_recursively(
 ) {
     let root = root.as_ref();
 
-    let does_dir_entry_match = move |path: &Path| {
-        // At the root.
-        if [".git"].map(Path::new).contains(&path) {
-            return false;
-        }
-
-        true
-    };
-
-    let does_file_entry_match = move |path: &Path| {
-        // At the root.
-        let is_at_root = path.components().count() == 1;
-        if is_at_root && hooks::is_hook(path) {
-            return false;
-        }
-
-        // Anywhere.
-        let file_name = path.file_name().expect("we don't have `..` here");
-        if [".deez", ".ignore", ".gitignore"]
-            .map(OsStr::new)
-            .contains(&file_name)
-        {
-            return false;
-        }
-
-        true
-    };
-
     // Note: We want a dir, not a file, but it's not the job of this
     // function to complain to the user.
     assert!(root.is_dir());
@@ -84,7 +67,14 @@ pub fn find_files_recursively(
                             WalkState::Skip
                         };
                     }
-                    if does_file_entry_match(path) && pathspec.matches(path) {
+                    if does_file_entry_match(path)
+                        && alternates::is_selected(root, path)
+                        && pathspec.matches_any(&[
+                            path,
+                            &alternates::base_path(path),
+                            &Templates::rendered_path(&alternates::base_path(path)),
+                        ])
+                    {
                         f(path);
                         return WalkState::Continue;
                     }
@@ -94,6 +84,122 @@ pub fn find_files_recursively(
         });
 }
 
+/// Same as [`find_files_recursively()`], but collect the files, sorted.
+///
+/// This is for callers that need to go through the files one at a
+/// time, in a predictable order (e.g., to prompt the user about each of
+/// them).
+///
+/// # Panics
+///
+/// This function panics if `root` is not a directory.
+pub fn collect_files_recursively(root: impl AsRef<Path>, pathspec: &PathSpec) -> Vec<PathBuf> {
+    let files = Mutex::new(Vec::new());
+
+    find_files_recursively(root, pathspec, |path| {
+        if let Ok(mut files) = files.lock() {
+            files.push(path.to_path_buf());
+        }
+    });
+
+    let mut files = files.into_inner().unwrap_or_default();
+    files.sort();
+    files
+}
+
+/// Whether `path` (relative to `root`) is ignored in `root`.
+///
+/// This is what [`find_files_recursively()`] skips, but for a single
+/// path, that needs not exist in `root` (e.g., a file about to be added
+/// to it). `path` is ignored if it, or any of its parents, is matched by
+/// the `.ignore` or `.gitignore` files of `root` (and its
+/// sub-directories), or if it is one of the files that are never config
+/// files (e.g., hooks).
+#[must_use]
+pub fn is_ignored(root: &Path, path: &Path, is_dir: bool) -> bool {
+    // Parents first, the root and the file itself being the extremes.
+    let mut parents: Vec<&Path> = path.ancestors().skip(1).collect();
+    parents.reverse();
+
+    for (i, parent) in parents.iter().enumerate().skip(1) {
+        if !does_dir_entry_match(parent) || is_ignored_by_files(root, &parents[..i], parent, true) {
+            return true;
+        }
+    }
+
+    if is_dir {
+        !does_dir_entry_match(path) || is_ignored_by_files(root, &parents, path, true)
+    } else {
+        !does_file_entry_match(path) || is_ignored_by_files(root, &parents, path, false)
+    }
+}
+
+/// Whether `path` is ignored by the ignore files in `dirs`.
+///
+/// Like Git, the ignore files closest to `path` take precedence, and
+/// `.ignore` files take precedence over `.gitignore` files.
+fn is_ignored_by_files(root: &Path, dirs: &[&Path], path: &Path, is_dir: bool) -> bool {
+    for dir in dirs.iter().rev() {
+        let dir = root.join(dir);
+        for ignore_file in [".ignore", ".gitignore"] {
+            let ignore_file = dir.join(ignore_file);
+            if !ignore_file.is_file() {
+                continue;
+            }
+            let mut builder = GitignoreBuilder::new(&dir);
+            if builder.add(&ignore_file).is_some() {
+                continue;
+            }
+            let Ok(gitignore) = builder.build() else {
+                continue;
+            };
+            match gitignore.matched(root.join(path), is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => (),
+            }
+        }
+    }
+    false
+}
+
+fn does_dir_entry_match(path: &Path) -> bool {
+    // At the root.
+    if [".git"].map(Path::new).contains(&path) {
+        return false;
+    }
+
+    true
+}
+
+fn does_file_entry_match(path: &Path) -> bool {
+    // At the root.
+    let is_at_root = path.components().count() == 1;
+    if is_at_root
+        && (hooks::is_hook(path)
+            || path == Path::new(ATTRIBUTES_FILE)
+            || path == Path::new(TARGETS_FILE)
+            || path == Path::new(templates::VARIABLES_FILE))
+    {
+        return false;
+    }
+
+    // Anywhere.
+    let file_name = path.file_name().expect("we don't have `..` here");
+    if [".deez", ".ignore", ".gitignore"]
+        .map(OsStr::new)
+        .contains(&file_name)
+    {
+        return false;
+    }
+    // Being written to by `rsync`, or left over by an interrupted run.
+    if utils::is_temp_file(path) {
+        return false;
+    }
+
+    true
+}
+
 #[inline]
 fn strip_root<'a>(root: &Path, path: &'a Path) -> &'a Path {
     // Since `root` is the root, `path` _always_ contains `root`.
@@ -0,0 +1,4 @@
+#!/usr/bin/env sh
+
+# Mimic a user appending a line to the file being edited.
+echo "edited" >> "$1"
@@ -0,0 +1,34 @@
+#!/usr/bin/env sh
+#
+# Mock `git` for `--rev`: revision `v1.0` has an old `.gitconfig`.
+
+THIS_SCRIPTS_PARENT_DIR=$(dirname "$0")
+
+# Append: `--rev` calls Git more than once.
+echo "$@" >> $THIS_SCRIPTS_PARENT_DIR/output_git_args.txt
+
+case "$1" in
+rev-parse)
+    pwd
+    echo
+    ;;
+read-tree)
+    case "$2" in
+    v1.0:) exit 0 ;;
+    esac
+    echo "fatal: Not a valid object name $2" >&2
+    exit 128
+    ;;
+checkout-index)
+    for arg do
+        case "$arg" in
+            --prefix=*) prefix=${arg#--prefix=} ;;
+        esac
+    done
+    touch "${prefix}.deez"
+    printf '[user]\n\tname = Old Name\n' > "${prefix}.gitconfig"
+    ;;
+*)
+    exit 1
+    ;;
+esac
@@ -40,6 +40,7 @@ fn help() {
       pre-sync    post-sync
       pre-rsync   post-rsync
       pre-link    post-link
+      pre-deploy  post-deploy
       pre-status  post-status
       pre-diff    post-diff
       pre-clean   post-clean"
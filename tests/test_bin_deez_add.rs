@@ -0,0 +1,248 @@
+mod utils;
+
+use std::path::Path;
+
+use utils::conf;
+use utils::files;
+use utils::run::{run, run_in_dir, run_with_env};
+
+// Warning: These tests MUST be run sequentially. Running them in
+// parallel threads may cause conflicts with environment variables,
+// as a variable may be overridden before it is used.
+
+fn home(path: &str) -> String {
+    Path::new(conf::HOME).join(path).display().to_string()
+}
+
+#[test]
+fn add_copies_file_into_root() {
+    conf::init();
+
+    conf::create_file_in_home(".config/foo/bar.toml", Some("bar"));
+
+    let output = run_in_dir(&["add", &home(".config/foo/bar.toml")], conf::CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        output.stdout,
+        format!(
+            ".config/foo/bar.toml\nAdded 1 file to '{}'.\n",
+            conf::root()
+        )
+    );
+
+    assert_eq!(files::read_in_configs(".config/foo/bar.toml"), "bar");
+    // The home is left as is.
+    assert!(!files::symlink_exists_in_home(".config/foo/bar.toml"));
+    assert_eq!(files::read_in_home(".config/foo/bar.toml"), "bar");
+}
+
+#[test]
+fn add_relative_path() {
+    conf::init();
+
+    conf::create_file_in_home(".config/foo/bar.toml", Some("bar"));
+
+    // From inside the home, with the root taken from the environment.
+    let output = run_with_env(
+        &["add", "foo/bar.toml"],
+        Path::new(conf::HOME).join(".config"),
+        &[("DEEZ_ROOT", Some(conf::CONFIGS))],
+    );
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_configs(".config/foo/bar.toml"), "bar");
+}
+
+#[test]
+fn add_directory_recursively() {
+    conf::init();
+
+    conf::create_file_in_home(".config/foo/bar.toml", None);
+    conf::create_file_in_home(".config/foo/baz/qux.toml", None);
+
+    let output = run_in_dir(&["add", &home(".config/foo")], conf::CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(
+        output
+            .stdout
+            .starts_with(".config/foo/bar.toml\n.config/foo/baz/qux.toml\nAdded 2 files")
+    );
+
+    assert!(files::file_exists_in_configs(".config/foo/bar.toml"));
+    assert!(files::file_exists_in_configs(".config/foo/baz/qux.toml"));
+}
+
+#[test]
+fn add_link_moves_file_and_links_it_back() {
+    conf::init();
+
+    conf::create_file_in_home(".config/foo/bar.toml", Some("bar"));
+
+    let output = run_in_dir(
+        &["add", "--link", &home(".config/foo/bar.toml")],
+        conf::CONFIGS,
+    );
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert_eq!(files::read_in_configs(".config/foo/bar.toml"), "bar");
+    assert!(files::symlink_exists_in_home(".config/foo/bar.toml"));
+    assert_eq!(
+        files::read_symlink_in_home(".config/foo/bar.toml"),
+        Path::new(conf::CONFIGS).join(".config/foo/bar.toml")
+    );
+
+    // It is now linked from the root.
+    let output = run(&["status", &conf::root()]);
+    dbg!(&output.stdout);
+    assert!(output.stdout.contains("  S  .config/foo/bar.toml@\n"));
+}
+
+#[test]
+fn add_records_files_in_manifest() {
+    conf::init();
+
+    conf::create_file_in_home(".gitconfig", None);
+
+    let output = run_in_dir(&["add", &home(".gitconfig")], conf::CONFIGS);
+    assert_eq!(output.exit_code, 0);
+
+    let manifest = files::read_manifest().unwrap();
+    assert!(manifest.contains("copy\t"));
+    assert!(manifest.contains("\t.gitconfig\t"));
+}
+
+#[test]
+fn add_refuses_paths_outside_home() {
+    conf::init();
+
+    let outside = Path::new(conf::HOME).with_file_name("outside");
+    let outside = outside.display().to_string();
+    std::fs::write(&outside, "").unwrap();
+
+    let output = run_in_dir(&["add", &outside], conf::CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert_eq!(
+        output.stderr,
+        format!("error: Not adding '{outside}': It is not in the home.\n")
+    );
+    assert!(!files::file_exists_in_configs("outside"));
+}
+
+#[test]
+fn add_refuses_missing_paths() {
+    conf::init();
+
+    let output = run_in_dir(&["add", &home(".missing")], conf::CONFIGS);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stderr.contains("No such file or directory"));
+}
+
+#[test]
+fn add_respects_ignore_files_of_root() {
+    conf::init();
+
+    conf::create_file_in_configs(".ignore", Some("*.log\n.config/foo/cache/\n"));
+    conf::create_file_in_home(".config/foo/bar.toml", None);
+    conf::create_file_in_home(".config/foo/debug.log", None);
+    conf::create_file_in_home(".config/foo/cache/data", None);
+
+    let output = run_in_dir(&["add", &home(".config/foo")], conf::CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(files::file_exists_in_configs(".config/foo/bar.toml"));
+    assert!(!files::file_exists_in_configs(".config/foo/debug.log"));
+    assert!(!files::file_exists_in_configs(".config/foo/cache/data"));
+
+    // Given explicitly.
+    let output = run_in_dir(&["add", &home(".config/foo/cache/data")], conf::CONFIGS);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stderr.contains("It is ignored by the root."));
+    assert!(!files::file_exists_in_configs(".config/foo/cache/data"));
+}
+
+#[test]
+fn add_skips_files_already_in_root() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("root"));
+    conf::create_file_in_home(".gitconfig", Some("home"));
+
+    let output = run_in_dir(&["add", &home(".gitconfig")], conf::CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stderr.contains("It is already in the root."));
+    assert_eq!(files::read_in_configs(".gitconfig"), "root");
+}
+
+#[test]
+fn add_dry_run_changes_nothing() {
+    conf::init();
+
+    conf::create_file_in_home(".gitconfig", None);
+
+    let output = run_in_dir(
+        &["--dry-run", "add", "--link", &home(".gitconfig")],
+        conf::CONFIGS,
+    );
+    dbg!(&output.stdout);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(
+        output
+            .stdout
+            .starts_with("replace symlink  .gitconfig\nWould add 1 file")
+    );
+    assert!(!files::file_exists_in_configs(".gitconfig"));
+    assert!(!files::symlink_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn add_can_be_undone() {
+    conf::init();
+
+    conf::create_file_in_home(".gitconfig", Some("home"));
+
+    let output = run_in_dir(&["add", "--link", &home(".gitconfig")], conf::CONFIGS);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["undo"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::file_exists_in_configs(".gitconfig"));
+    assert!(!files::symlink_exists_in_home(".gitconfig"));
+    assert_eq!(files::read_in_home(".gitconfig"), "home");
+}
+
+#[test]
+fn add_without_paths_errors() {
+    conf::init();
+
+    let output = run_in_dir(&["add"], conf::CONFIGS);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 2);
+}
@@ -0,0 +1,312 @@
+mod utils;
+
+use utils::conf;
+use utils::files;
+use utils::run::{run, run_with_input};
+
+// Warning: These tests MUST be run sequentially. Running them in
+// parallel threads may cause conflicts with environment variables,
+// as a variable may be overridden before it is used.
+
+// Far enough apart to make two hunks.
+const HOME_GITCONFIG: &str = "\
+[user]
+    name = Jane
+[core]
+    editor = vim
+[alias]
+    st = status
+[pull]
+    rebase = false
+[machine]
+    gpg = /opt/homebrew/bin/gpg
+";
+
+const ROOT_GITCONFIG: &str = "\
+[user]
+    name = Jane Doe
+[core]
+    editor = vim
+[alias]
+    st = status
+[pull]
+    rebase = false
+";
+
+#[test]
+fn apply_regular() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some(ROOT_GITCONFIG));
+    conf::create_file_in_home(".gitconfig", Some(HOME_GITCONFIG));
+    conf::create_file_in_configs(".bashrc", Some("from root\n"));
+
+    let output = run(&["apply", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "Applied 3 hunks to 2 files.\n");
+
+    assert_eq!(files::read_in_home(".gitconfig"), ROOT_GITCONFIG);
+    // Missing files are created.
+    assert_eq!(files::read_in_home(".bashrc"), "from root\n");
+}
+
+#[test]
+fn apply_dry_run_only_prints_hunks() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some(ROOT_GITCONFIG));
+    conf::create_file_in_home(".gitconfig", Some(HOME_GITCONFIG));
+    conf::create_file_in_configs(".bashrc", Some("from root\n"));
+
+    // Patch mode doesn't ask anything in a dry-run.
+    let output = run(&["-n", "apply", "-p", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.contains("+    name = Jane Doe"));
+    assert!(output.stdout.contains("-    gpg = /opt/homebrew/bin/gpg"));
+    assert!(!output.stdout.contains("Apply this hunk"));
+    assert!(
+        output
+            .stdout
+            .ends_with("Would apply 3 hunks to 2 files (dry run).\n")
+    );
+
+    assert_eq!(files::read_in_home(".gitconfig"), HOME_GITCONFIG);
+    assert!(!files::file_exists_in_home(".bashrc"));
+    assert!(files::read_manifest().is_none());
+}
+
+#[test]
+fn apply_patch_asks_hunk_by_hunk() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some(ROOT_GITCONFIG));
+    conf::create_file_in_home(".gitconfig", Some(HOME_GITCONFIG));
+
+    // Take the new name, keep the machine-specific lines.
+    let output = run_with_input(&["apply", "-p", &conf::root()], "y\nn\n");
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(
+        output
+            .stdout
+            .contains("-    name = Jane\n+    name = Jane Doe\n")
+    );
+    assert!(
+        output
+            .stdout
+            .contains("(1/2) Apply this hunk to home? [y,n,a,d,q,?]")
+    );
+    assert!(output.stdout.contains("(2/2) Apply this hunk to home?"));
+    assert!(output.stdout.contains("Applied 1 hunk to 1 file."));
+
+    assert_eq!(
+        files::read_in_home(".gitconfig"),
+        HOME_GITCONFIG.replace("name = Jane\n", "name = Jane Doe\n")
+    );
+    assert_eq!(files::read_in_configs(".gitconfig"), ROOT_GITCONFIG);
+}
+
+#[test]
+fn apply_patch_reversed_applies_to_root() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some(ROOT_GITCONFIG));
+    conf::create_file_in_home(".gitconfig", Some(HOME_GITCONFIG));
+
+    // Bring back the old name, but not the machine-specific lines.
+    let output = run_with_input(&["apply", "-p", "-r", &conf::root()], "y\nn\n");
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.contains("(1/2) Apply this hunk to root?"));
+
+    assert_eq!(
+        files::read_in_configs(".gitconfig"),
+        ROOT_GITCONFIG.replace("name = Jane Doe\n", "name = Jane\n")
+    );
+    assert_eq!(files::read_in_home(".gitconfig"), HOME_GITCONFIG);
+}
+
+#[test]
+fn apply_patch_all_remaining_hunks_in_file() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some(ROOT_GITCONFIG));
+    conf::create_file_in_home(".gitconfig", Some(HOME_GITCONFIG));
+
+    let output = run_with_input(&["apply", "-p", &conf::root()], "a\n");
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!output.stdout.contains("(2/2)"));
+    assert_eq!(files::read_in_home(".gitconfig"), ROOT_GITCONFIG);
+}
+
+#[test]
+fn apply_patch_quit_keeps_hunks_applied_so_far() {
+    conf::init();
+
+    conf::create_file_in_configs(".bashrc", Some("from root\n"));
+    conf::create_file_in_home(".bashrc", Some("from home\n"));
+    conf::create_file_in_configs(".gitconfig", Some(ROOT_GITCONFIG));
+    conf::create_file_in_home(".gitconfig", Some(HOME_GITCONFIG));
+
+    // Files come in order: `.bashrc`, then `.gitconfig`.
+    let output = run_with_input(&["apply", "-p", &conf::root()], "y\nq\n");
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.contains("Applied 1 hunk to 1 file."));
+
+    assert_eq!(files::read_in_home(".bashrc"), "from root\n");
+    assert_eq!(files::read_in_home(".gitconfig"), HOME_GITCONFIG);
+}
+
+#[test]
+fn apply_reversed_skips_files_missing_from_home() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some(ROOT_GITCONFIG));
+
+    let output = run(&["apply", "-r", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "Applied 0 hunks to 0 files.\n");
+    assert_eq!(files::read_in_configs(".gitconfig"), ROOT_GITCONFIG);
+}
+
+#[test]
+fn apply_skips_symlinks() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some(ROOT_GITCONFIG));
+    conf::create_file_in_home("gitconfig", Some(HOME_GITCONFIG));
+    conf::create_symlink_in_home(".gitconfig", Some("gitconfig"));
+
+    let output = run(&["apply", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stderr.contains("Skipping '.gitconfig'"));
+    assert_eq!(files::read_in_home("gitconfig"), HOME_GITCONFIG);
+}
+
+#[test]
+fn apply_backs_up_and_can_be_undone() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some(ROOT_GITCONFIG));
+    conf::create_file_in_home(".gitconfig", Some(HOME_GITCONFIG));
+
+    let output = run_with_input(&["apply", "-p", &conf::root()], "n\ny\n");
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_latest_backup(".gitconfig"), HOME_GITCONFIG);
+
+    let output = run(&["undo"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.contains("from `apply` run"));
+    assert_eq!(files::read_in_home(".gitconfig"), HOME_GITCONFIG);
+}
+
+#[test]
+fn apply_respects_pathspec() {
+    conf::init();
+
+    conf::create_file_in_configs(".bashrc", Some("from root\n"));
+    conf::create_file_in_configs(".gitconfig", Some(ROOT_GITCONFIG));
+
+    let output = run(&["apply", &conf::root(), "--", ".gitconfig"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(files::file_exists_in_home(".gitconfig"));
+    assert!(!files::file_exists_in_home(".bashrc"));
+}
+
+#[cfg(unix)]
+#[test]
+fn apply_keeps_permissions() {
+    conf::init();
+
+    conf::create_file_in_configs("bin/script", Some("echo root\n"));
+    conf::set_mode_in_configs("bin/script", 0o750);
+    conf::create_file_in_configs(".gitconfig", Some(ROOT_GITCONFIG));
+    conf::create_file_in_home(".gitconfig", Some(HOME_GITCONFIG));
+    conf::set_mode_in_home(".gitconfig", 0o600);
+
+    let output = run(&["apply", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    // Created files take the permissions of the root's.
+    assert_eq!(files::mode_in_home("bin/script"), 0o750);
+    // Patched ones keep theirs.
+    assert_eq!(files::mode_in_home(".gitconfig"), 0o600);
+}
+
+#[test]
+fn apply_reversed_moves_sync_base() {
+    conf::init();
+
+    conf::create_file_in_configs(".bashrc", Some("synced\n"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    conf::create_file_in_home(".bashrc", Some("edited in home\n"));
+
+    let output = run(&["apply", "-r", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_configs(".bashrc"), "edited in home\n");
+
+    // Only the root changed since the apply, this is not a conflict.
+    conf::create_file_in_configs(".bashrc", Some("edited in root\n"));
+
+    let output = run(&["sync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".bashrc"), "edited in root\n");
+}
+
+#[test]
+fn apply_skips_binary_files() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some(ROOT_GITCONFIG));
+    conf::create_file_in_configs("image.bin", Some("\0root"));
+    conf::create_file_in_home("image.bin", Some("\0home"));
+
+    let output = run(&["apply", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(
+        output
+            .stderr
+            .contains("Skipping 'image.bin': It is not a text file.")
+    );
+    assert_eq!(output.stdout, "Applied 1 hunk to 1 file.\n");
+    assert_eq!(files::read_in_home("image.bin"), "\0home");
+}
@@ -11,7 +11,7 @@ use std::path::Path;
 
 use utils::conf;
 use utils::files;
-use utils::run::{run, run_in_dir};
+use utils::run::{run, run_in_dir, run_with_input};
 use utils::{mock_bin, output_file_exists, read_output_file, remove_output_file};
 
 // Warning: These tests MUST be run sequentially. Running them in
@@ -185,18 +185,25 @@ fn clean_output_verbose() {
 
     assert_eq!(output.exit_code, 0);
 
+    // Removed files are always backed up.
+    let backup_dir = files::backup_runs().pop().unwrap();
+
     assert_eq!(
         output.stdout,
-        "\
+        format!(
+            "\
 hook: pre-clean.sh
 .config/fish/config.fish
 .config/ghostty/config
 .config/nvim/init.lua
 .gitconfig
+Backed up 4 files to '{}'.
 hook: post-clean.sh
 Removed 4 files.
 Ran 2 hooks.
-"
+",
+            backup_dir.display()
+        )
     );
 }
 
@@ -520,3 +527,186 @@ fn clean_hooks_abort_execution_if_exit_code_is_non_zero() {
     // The aborted `clean` did not delete anything from the home.
     assert!(files::file_exists_in_home(".gitconfig"));
 }
+
+#[test]
+fn clean_dry_run_does_not_touch_home() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+    conf::create_file_in_configs(".config/nvim/init.lua", None);
+
+    conf::create_file_in_home(".gitconfig", None);
+
+    let output = run(&["clean", "--dry-run", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert!(output.stdout.contains("delete           .gitconfig\n"));
+    assert!(
+        output
+            .stdout
+            .contains("no-op            .config/nvim/init.lua\n")
+    );
+    assert!(output.stdout.contains("Would remove 2 files (dry run)."));
+
+    assert!(files::file_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn clean_removes_files_from_manifest() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+    conf::create_file_in_configs(".bashrc", None);
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["clean", &conf::root(), "--", ".gitconfig"]);
+    assert_eq!(output.exit_code, 0);
+
+    let manifest = files::read_manifest().unwrap();
+    dbg!(&manifest);
+
+    assert!(!manifest.contains("\t.gitconfig\t"));
+    assert!(manifest.contains("\t.bashrc\t"));
+
+    let output = run(&["clean", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    // Empty manifests are deleted.
+    assert!(files::read_manifest().is_none());
+}
+
+#[test]
+fn clean_interactive_asks_before_each_file() {
+    conf::init();
+
+    conf::create_file_in_configs(".bashrc", None);
+    conf::create_file_in_home(".bashrc", Some("home"));
+    conf::create_file_in_configs(".gitconfig", None);
+    conf::create_file_in_home(".gitconfig", Some("home"));
+
+    let output = run_with_input(&["clean", "-i", &conf::root()], "n\ny\n");
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    // Nothing to edit when removing.
+    assert!(output.stdout.contains("delete '.bashrc'? [y,n,d,a,q,?]"));
+    assert!(output.stdout.contains("-home\n"));
+    assert!(output.stdout.contains("Removed 1 file."));
+
+    assert!(files::file_exists_in_home(".bashrc"));
+    assert!(!files::file_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn clean_removes_folded_directories_not_their_files() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/nvim fold\n"));
+    conf::create_file_in_configs(".config/nvim/init.lua", None);
+    conf::create_file_in_configs(".config/nvim/lua/plugins.lua", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["--verbose", "clean", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.contains(".config/nvim/\n"));
+    assert!(output.stdout.contains("Removed 2 files"));
+
+    assert!(!files::symlink_exists_in_home(".config/nvim"));
+    // The root is untouched.
+    assert!(files::file_exists_in_configs(".config/nvim/init.lua"));
+    assert!(files::file_exists_in_configs(
+        ".config/nvim/lua/plugins.lua"
+    ));
+    assert!(files::read_manifest().is_none());
+}
+
+#[test]
+fn clean_interactive_skips_all_files_of_skipped_folded_directory() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/nvim fold\n"));
+    conf::create_file_in_configs(".config/nvim/init.lua", None);
+    conf::create_file_in_configs(".config/nvim/lua/plugins.lua", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run_with_input(&["clean", "-i", &conf::root()], "n\n");
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.contains("delete '.config/nvim'?"));
+    assert!(output.stdout.contains("Removed 0 files"));
+    assert!(files::symlink_exists_in_home(".config/nvim"));
+}
+
+#[test]
+fn clean_follows_mapped_targets() {
+    conf::init();
+
+    conf::create_file_in_configs(".deeztargets", Some("share .local/share\n"));
+    conf::create_file_in_configs("share/fonts/fonts.conf", None);
+
+    conf::create_file_in_home(".local/share/fonts/fonts.conf", None);
+    conf::create_file_in_home("share/fonts/fonts.conf", None);
+
+    let output = run(&["clean", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::file_exists_in_home(".local/share/fonts/fonts.conf"));
+    // Not where the file goes, so not ours.
+    assert!(files::file_exists_in_home("share/fonts/fonts.conf"));
+}
+
+#[test]
+fn clean_single_file_root() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+    conf::create_file_in_configs(".bashrc", None);
+    conf::create_file_in_home(".gitconfig", None);
+    conf::create_file_in_home(".bashrc", None);
+
+    let file = Path::new(conf::CONFIGS).join(".gitconfig");
+    let output = run(&["clean", &file.display().to_string()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::file_exists_in_home(".gitconfig"));
+    assert!(files::file_exists_in_home(".bashrc"));
+}
+
+#[test]
+fn clean_resolves_alternates() {
+    conf::init();
+
+    conf::create_file_in_configs(&format!(".bashrc##os.{}", env::consts::OS), None);
+    conf::create_file_in_configs(".zshrc##os.nowhere", None);
+
+    conf::create_file_in_home(".bashrc", None);
+    conf::create_file_in_home(".zshrc", None);
+
+    let output = run(&["clean", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::file_exists_in_home(".bashrc"));
+    // Another machine's, so not ours.
+    assert!(files::file_exists_in_home(".zshrc"));
+}
@@ -0,0 +1,175 @@
+mod utils;
+
+#[path = "utils/hook_macros.rs"]
+mod hook_macros;
+
+// Generate shared hook tests for this command.
+hook_macros::hook_tests!(deploy);
+
+use utils::conf;
+use utils::files;
+use utils::run::run;
+
+// Warning: These tests MUST be run sequentially. Running them in
+// parallel threads may cause conflicts with environment variables,
+// as a variable may be overridden before it is used.
+
+#[test]
+fn deploy_links_and_copies_per_attributes() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some("* link\n.gitconfig copy\n"));
+    conf::create_file_in_configs(".config/fish/config.fish", Some("fish"));
+    conf::create_file_in_configs(".gitconfig", Some("gitconfig"));
+
+    let output = run(&["deploy", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    // One run, one summary.
+    assert_eq!(output.stdout, "Deployed 2 files.\n");
+
+    assert!(files::symlink_exists_in_home(".config/fish/config.fish"));
+    assert_eq!(files::read_in_home(".config/fish/config.fish"), "fish");
+    assert!(!files::symlink_exists_in_home(".gitconfig"));
+    assert_eq!(files::read_in_home(".gitconfig"), "gitconfig");
+}
+
+#[test]
+fn deploy_copies_files_without_strategy() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/fish link\n"));
+    conf::create_file_in_configs(".config/fish/config.fish", None);
+    conf::create_file_in_configs(".bashrc", None);
+
+    let output = run(&["deploy", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(files::symlink_exists_in_home(".config/fish/config.fish"));
+    assert!(files::file_exists_in_home(".bashrc"));
+    assert!(!files::symlink_exists_in_home(".bashrc"));
+}
+
+#[test]
+fn deploy_records_strategies_in_manifest() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/fish link\n"));
+    conf::create_file_in_configs(".config/fish/config.fish", None);
+    conf::create_file_in_configs(".bashrc", None);
+
+    let output = run(&["deploy", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let manifest = files::read_manifest().unwrap();
+    assert!(
+        manifest
+            .lines()
+            .any(|line| line.starts_with("link\t") && line.contains("\t.config/fish/config.fish\t"))
+    );
+    assert!(
+        manifest
+            .lines()
+            .any(|line| line.starts_with("copy\t") && line.contains("\t.bashrc\t"))
+    );
+}
+
+#[test]
+fn deploy_switches_strategy_of_a_file() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".gitconfig link\n"));
+    conf::create_file_in_configs(".gitconfig", Some("gitconfig"));
+
+    let output = run(&["deploy", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    assert!(files::symlink_exists_in_home(".gitconfig"));
+
+    conf::create_file_in_configs(".deezattributes", Some(".gitconfig copy\n"));
+
+    let output = run(&["deploy", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::symlink_exists_in_home(".gitconfig"));
+    assert_eq!(files::read_in_home(".gitconfig"), "gitconfig");
+}
+
+#[test]
+fn deploy_dry_run_changes_nothing() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/fish link\n"));
+    conf::create_file_in_configs(".config/fish/config.fish", None);
+    conf::create_file_in_configs(".bashrc", None);
+
+    let output = run(&["--dry-run", "deploy", &conf::root()]);
+    dbg!(&output.stdout);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        output.stdout,
+        "\
+create           .bashrc
+create           .config/fish/config.fish
+Would deploy 2 files (dry run).
+"
+    );
+    assert!(!files::file_exists_in_home(".bashrc"));
+    assert!(!files::symlink_exists_in_home(".config/fish/config.fish"));
+}
+
+#[test]
+fn deploy_rejects_remote_roots() {
+    conf::init();
+
+    let output = run(&["deploy", "gh:mystery/dotfiles"]);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stderr.contains("Cannot deploy from a Git remote"));
+}
+
+#[test]
+fn deploy_atomic_rolls_back_copies_and_links() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/fish link\n"));
+    conf::create_file_in_configs(".config/fish/config.fish", None);
+    conf::create_file_in_configs(".bashrc", Some("from root"));
+    conf::create_file_in_home(".bashrc", Some("from home"));
+    conf::create_executable_file_in_configs("post-deploy.sh", Some("exit 1"));
+
+    let output = run(&["deploy", "--atomic", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stdout.contains("Rolled back 2 files."));
+    assert_eq!(files::read_in_home(".bashrc"), "from home");
+    assert!(!files::symlink_exists_in_home(".config/fish/config.fish"));
+    assert!(files::read_manifest().is_none());
+}
+
+#[test]
+fn deploy_without_atomic_keeps_what_was_deployed() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/fish link\n"));
+    conf::create_file_in_configs(".config/fish/config.fish", None);
+    conf::create_file_in_configs(".bashrc", Some("from root"));
+    conf::create_executable_file_in_configs("post-deploy.sh", Some("exit 1"));
+
+    let output = run(&["deploy", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert_eq!(files::read_in_home(".bashrc"), "from root");
+    assert!(files::symlink_exists_in_home(".config/fish/config.fish"));
+}
@@ -817,3 +817,86 @@ fn diff_hooks_abort_execution_if_exit_code_is_non_zero() {
     // The aborted `diff` printed nothing about the configs.
     assert!(!output.stdout.contains(".gitconfig"));
 }
+
+#[test]
+fn diff_single_file_root() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("root\n"));
+    conf::create_file_in_configs(".bashrc", Some("root\n"));
+    conf::create_file_in_home(".gitconfig", Some("home\n"));
+    conf::create_file_in_home(".bashrc", Some("home\n"));
+
+    let file = Path::new(conf::CONFIGS).join(".gitconfig");
+    let output = run(&["diff", &file.display().to_string()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.contains(".gitconfig"));
+    assert!(!output.stdout.contains(".bashrc"));
+}
+
+#[test]
+fn diff_resolves_alternates() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("plain\n"));
+    conf::create_file_in_configs(".gitconfig##host.work-laptop", Some("host\n"));
+
+    conf::create_file_in_home(".gitconfig", Some("host\n"));
+
+    let output = run_with_env(
+        &["diff", &conf::root()],
+        conf::CONFIGS,
+        &[("DEEZ_HOST", Some("work-laptop"))],
+    );
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "Home is in sync.\n");
+}
+
+#[test]
+fn diff_compares_rendered_templates() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig.tmpl", Some("host = {{ host }}\n"));
+
+    conf::create_file_in_home(".gitconfig", Some("host = desktop\n"));
+
+    let output = run_with_env(
+        &["diff", &conf::root()],
+        conf::CONFIGS,
+        &[("DEEZ_HOST", Some("work-laptop"))],
+    );
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.contains("-host = desktop"));
+    assert!(output.stdout.contains("+host = work-laptop"));
+    assert!(!output.stdout.contains("{{ host }}"));
+}
+
+#[test]
+fn diff_rev_compares_home_against_revision() {
+    conf::init();
+
+    remove_output_file("output_git_args");
+    mock_bin("git", "bin_git_rev");
+
+    conf::create_file_in_configs(".gitconfig", Some("[user]\n\tname = Working Name\n"));
+
+    conf::create_file_in_home(".gitconfig", Some("[user]\n\tname = New Name\n"));
+
+    let output = run(&["diff", "--rev", "v1.0", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.contains("-\tname = New Name"));
+    assert!(output.stdout.contains("+\tname = Old Name"));
+    assert!(!output.stdout.contains("Working Name"));
+}
@@ -0,0 +1,251 @@
+mod utils;
+
+use std::path::Path;
+
+use utils::conf;
+use utils::files;
+use utils::run::{run, run_in_dir};
+
+// Warning: These tests MUST be run sequentially. Running them in
+// parallel threads may cause conflicts with environment variables,
+// as a variable may be overridden before it is used.
+
+fn home(path: &str) -> String {
+    Path::new(conf::HOME).join(path).display().to_string()
+}
+
+#[test]
+fn forget_turns_link_into_file_and_removes_it_from_root() {
+    conf::init();
+
+    conf::create_file_in_configs(".config/foo/bar.toml", Some("bar"));
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    assert!(files::symlink_exists_in_home(".config/foo/bar.toml"));
+
+    let output = run_in_dir(&["forget", &home(".config/foo/bar.toml")], conf::CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        output.stdout,
+        format!(
+            ".config/foo/bar.toml\nForgot 1 file from '{}'.\n",
+            conf::root()
+        )
+    );
+
+    assert!(!files::symlink_exists_in_home(".config/foo/bar.toml"));
+    assert_eq!(files::read_in_home(".config/foo/bar.toml"), "bar");
+    assert!(!files::file_exists_in_configs(".config/foo/bar.toml"));
+    // Empty directories go with it.
+    assert!(!files::dir_exists_in_configs(".config"));
+}
+
+#[test]
+fn forget_keeps_synced_file_in_home() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("gitconfig"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run_in_dir(&["forget", &home(".gitconfig")], conf::CONFIGS);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "gitconfig");
+    assert!(!files::file_exists_in_configs(".gitconfig"));
+
+    // The manifest doesn't know it anymore.
+    assert!(files::read_manifest().is_none_or(|manifest| !manifest.contains("\t.gitconfig\t")));
+}
+
+#[test]
+fn forget_path_from_root() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run_in_dir(&["forget", ".gitconfig"], conf::CONFIGS);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::symlink_exists_in_home(".gitconfig"));
+    assert!(files::file_exists_in_home(".gitconfig"));
+    assert!(!files::file_exists_in_configs(".gitconfig"));
+}
+
+#[test]
+fn forget_directory_recursively() {
+    conf::init();
+
+    conf::create_file_in_configs(".config/foo/bar.toml", None);
+    conf::create_file_in_configs(".config/foo/baz/qux.toml", None);
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run_in_dir(&["forget", &home(".config/foo")], conf::CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(
+        output
+            .stdout
+            .starts_with(".config/foo/bar.toml\n.config/foo/baz/qux.toml\nForgot 2 files")
+    );
+
+    assert!(!files::file_exists_in_configs(".config/foo/bar.toml"));
+    assert!(!files::file_exists_in_configs(".config/foo/baz/qux.toml"));
+    assert!(!files::symlink_exists_in_home(".config/foo/baz/qux.toml"));
+    assert!(files::file_exists_in_home(".config/foo/baz/qux.toml"));
+    // The rest is left alone.
+    assert!(files::file_exists_in_configs(".gitconfig"));
+    assert!(files::symlink_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn forget_with_pathspecs() {
+    conf::init();
+
+    conf::create_file_in_configs(".config/nvim/init.lua", None);
+    conf::create_file_in_configs(".config/nvim/lua/plugins.lua", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run_in_dir(
+        &["forget", "--", ".config/nvim", ":!.config/nvim/init.lua"],
+        conf::CONFIGS,
+    );
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::file_exists_in_configs(
+        ".config/nvim/lua/plugins.lua"
+    ));
+    assert!(files::file_exists_in_home(".config/nvim/lua/plugins.lua"));
+    assert!(files::file_exists_in_configs(".config/nvim/init.lua"));
+    assert!(files::symlink_exists_in_home(".config/nvim/init.lua"));
+}
+
+#[test]
+fn forget_keeps_files_missing_from_home_in_root() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run_in_dir(&["forget", ".gitconfig"], conf::CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stderr.contains("It is not in the home."));
+    assert!(files::file_exists_in_configs(".gitconfig"));
+}
+
+#[test]
+fn forget_whole_folded_directory() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/nvim fold\n"));
+    conf::create_file_in_configs(".config/nvim/init.lua", Some("init"));
+    conf::create_file_in_configs(".config/nvim/lua/plugins.lua", Some("plugins"));
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    assert!(files::symlink_exists_in_home(".config/nvim"));
+
+    let output = run_in_dir(&["forget", &home(".config/nvim")], conf::CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::symlink_exists_in_home(".config/nvim"));
+    assert!(files::dir_exists_in_home(".config/nvim"));
+    assert_eq!(files::read_in_home(".config/nvim/init.lua"), "init");
+    assert_eq!(
+        files::read_in_home(".config/nvim/lua/plugins.lua"),
+        "plugins"
+    );
+    assert!(!files::dir_exists_in_configs(".config/nvim"));
+}
+
+#[test]
+fn forget_refuses_part_of_folded_directory() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/nvim fold\n"));
+    conf::create_file_in_configs(".config/nvim/init.lua", None);
+    conf::create_file_in_configs(".config/nvim/lua/plugins.lua", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run_in_dir(&["forget", &home(".config/nvim/init.lua")], conf::CONFIGS);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stderr.contains("forget the whole directory instead"));
+    assert!(files::symlink_exists_in_home(".config/nvim"));
+    assert!(files::file_exists_in_configs(".config/nvim/init.lua"));
+}
+
+#[test]
+fn forget_dry_run_changes_nothing() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run_in_dir(&["--dry-run", "forget", ".gitconfig"], conf::CONFIGS);
+    dbg!(&output.stdout);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.starts_with(".gitconfig\nWould forget 1 file"));
+    assert!(files::file_exists_in_configs(".gitconfig"));
+    assert!(files::symlink_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn forget_can_be_undone() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("gitconfig"));
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run_in_dir(&["forget", ".gitconfig"], conf::CONFIGS);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["undo"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_configs(".gitconfig"), "gitconfig");
+    assert!(files::symlink_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn forget_without_paths_errors() {
+    conf::init();
+
+    let output = run_in_dir(&["forget"], conf::CONFIGS);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 2);
+}
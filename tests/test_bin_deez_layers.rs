@@ -0,0 +1,221 @@
+mod utils;
+
+use utils::conf::{self, CONFIGS};
+use utils::files;
+use utils::run::run_in_dir;
+
+// Warning: These tests MUST be run sequentially. Running them in
+// parallel threads may cause conflicts with environment variables,
+// as a variable may be overridden before it is used.
+
+/// A `base` layer, and a `laptop` layer overriding `.gitconfig`.
+fn create_layers() {
+    conf::create_file_in_configs("base/.deez", None);
+    conf::create_file_in_configs("base/.gitconfig", Some("base"));
+    conf::create_file_in_configs("base/.bashrc", Some("base"));
+    conf::create_file_in_configs("laptop/.deez", None);
+    conf::create_file_in_configs("laptop/.gitconfig", Some("laptop"));
+}
+
+#[test]
+fn sync_layers_given_on_cli() {
+    conf::init();
+    create_layers();
+
+    let output = run_in_dir(&["sync", "base", "laptop"], CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(
+        output
+            .stdout
+            .starts_with("Layer 'base'\n  .gitconfig  (overridden by 'laptop')\n")
+    );
+    assert!(output.stdout.contains("Layer 'laptop'\n"));
+
+    assert_eq!(files::read_in_home(".gitconfig"), "laptop");
+    assert_eq!(files::read_in_home(".bashrc"), "base");
+}
+
+#[test]
+fn sync_layers_listed_in_deez_file() {
+    conf::init();
+    create_layers();
+    conf::create_file_in_configs(".deez", Some("# Lowest first.\nlayer base\nlayer laptop\n"));
+
+    let output = run_in_dir(&["sync"], CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(
+        output
+            .stdout
+            .contains("/base'\n  .gitconfig  (overridden by '")
+    );
+    assert_eq!(files::read_in_home(".gitconfig"), "laptop");
+    assert_eq!(files::read_in_home(".bashrc"), "base");
+    // Layers are not deployed as directories.
+    assert!(!files::dir_exists_in_home("base"));
+}
+
+#[test]
+fn status_shows_which_layer_files_come_from() {
+    conf::init();
+    create_layers();
+
+    conf::create_file_in_home(".gitconfig", Some("laptop"));
+
+    let output = run_in_dir(&["status", "base", "laptop"], CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        output.stdout,
+        "\
+Layer 'base'
+  .gitconfig  (overridden by 'laptop')
+Files
+  !  .bashrc
+0 in sync, 0 modified, 1 missing.
+Layer 'laptop'
+Files
+  S  .gitconfig
+1 in sync, 0 modified, 0 missing.
+"
+    );
+}
+
+#[test]
+fn diff_compares_with_the_overriding_layer() {
+    conf::init();
+    create_layers();
+
+    conf::create_file_in_home(".gitconfig", Some("laptop"));
+    conf::create_file_in_home(".bashrc", Some("base"));
+
+    let output = run_in_dir(&["diff", "base", "laptop"], CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    // Both layers are in sync, `base`'s `.gitconfig` doesn't count.
+    assert_eq!(output.stdout.matches("Home is in sync.").count(), 2);
+}
+
+#[test]
+fn rsync_writes_back_to_the_providing_layer() {
+    conf::init();
+    create_layers();
+
+    conf::create_file_in_home(".gitconfig", Some("edited"));
+    conf::create_file_in_home(".bashrc", Some("edited"));
+
+    let output = run_in_dir(&["rsync", "base", "laptop"], CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_configs("laptop/.gitconfig"), "edited");
+    assert_eq!(files::read_in_configs("base/.gitconfig"), "base");
+    assert_eq!(files::read_in_configs("base/.bashrc"), "edited");
+}
+
+#[test]
+fn missing_layer_errors() {
+    conf::init();
+    create_layers();
+
+    let output = run_in_dir(&["sync", "base", "desktop"], CONFIGS);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(
+        output
+            .stderr
+            .contains("Layer 'desktop' is not a directory.")
+    );
+    assert!(!files::file_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn layered_root_is_refused_by_single_root_commands() {
+    conf::init();
+    create_layers();
+    conf::create_file_in_configs(".deez", Some("layer base\nlayer laptop\n"));
+
+    let output = run_in_dir(&["link"], CONFIGS);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 2);
+    assert!(output.stderr.contains("`link` works on a single root."));
+    assert!(!files::file_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn layered_root_is_refused_by_rev() {
+    conf::init();
+    create_layers();
+    conf::create_file_in_configs(".deez", Some("layer base\nlayer laptop\n"));
+
+    let output = run_in_dir(&["status", "--rev", "v1.0"], CONFIGS);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 2);
+    assert!(output.stderr.contains("`--rev` works on a single root."));
+}
+
+#[test]
+fn deez_file_lines_other_than_layers_are_ignored() {
+    conf::init();
+    conf::create_file_in_configs(".deez", Some("my dotfiles marker\n"));
+    conf::create_file_in_configs(".gitconfig", Some("root"));
+
+    let output = run_in_dir(&["sync"], CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!output.stdout.contains("Layer"));
+    assert_eq!(files::read_in_home(".gitconfig"), "root");
+
+    // Not layered, single root commands accept it.
+    let output = run_in_dir(&["clean"], CONFIGS);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::file_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn sync_atomic_rolls_back_every_layer() {
+    conf::init();
+    create_layers();
+    conf::create_executable_file_in_configs("laptop/post-sync.sh", Some("exit 1"));
+
+    let output = run_in_dir(&["sync", "--atomic", "base", "laptop"], CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    // `laptop` rolls itself back, then `base` gets rolled back.
+    assert!(output.stdout.contains("Rolled back 1 file."));
+    assert!(!files::file_exists_in_home(".gitconfig"));
+    assert!(!files::file_exists_in_home(".bashrc"));
+}
+
+#[test]
+fn sync_without_atomic_keeps_layers_synced_before_failure() {
+    conf::init();
+    create_layers();
+    conf::create_executable_file_in_configs("laptop/post-sync.sh", Some("exit 1"));
+
+    let output = run_in_dir(&["sync", "base", "laptop"], CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert_eq!(files::read_in_home(".bashrc"), "base");
+}
@@ -7,11 +7,12 @@ mod hook_macros;
 hook_macros::hook_tests!(link);
 
 use std::env;
+use std::fs;
 use std::path::Path;
 
 use utils::conf;
 use utils::files;
-use utils::run::{run, run_in_dir};
+use utils::run::{run, run_in_dir, run_with_env};
 use utils::{mock_bin, output_file_exists, read_output_file, remove_output_file};
 
 // Warning: These tests MUST be run sequentially. Running them in
@@ -468,3 +469,540 @@ fn link_hooks_abort_execution_if_exit_code_is_non_zero() {
     // The aborted `link` did not symlink anything into the home.
     assert!(!files::symlink_exists_in_home(".gitconfig"));
 }
+
+#[test]
+fn link_dry_run_does_not_touch_home() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+    conf::create_file_in_configs(".bashrc", None);
+
+    conf::create_file_in_home(".bashrc", Some("regular file"));
+
+    let output = run(&["link", "--dry-run", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert!(output.stdout.contains("create           .gitconfig\n"));
+    assert!(output.stdout.contains("overwrite        .bashrc\n"));
+    assert!(output.stdout.contains("Would link 2 files (dry run)."));
+
+    assert!(!files::file_exists_in_home(".gitconfig"));
+    assert!(!files::symlink_exists_in_home(".bashrc"));
+    assert_eq!(files::read_in_home(".bashrc"), "regular file");
+}
+
+#[test]
+fn link_backs_up_replaced_files() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("from root"));
+    conf::create_file_in_configs(".bashrc", Some("same"));
+
+    conf::create_file_in_home(".gitconfig", Some("local edits"));
+    conf::create_file_in_home(".bashrc", Some("same"));
+
+    let output = run(&["--verbose", "link", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert!(files::symlink_exists_in_home(".gitconfig"));
+    assert_eq!(files::read_in_latest_backup(".gitconfig"), "local edits");
+    // Same content, nothing to lose.
+    assert!(!files::backup_exists(".bashrc"));
+    assert!(output.stdout.contains("Backed up 1 file to '"));
+}
+
+#[test]
+fn link_does_not_back_up_existing_links_to_root() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    // Linking again replaces nothing the root doesn't already have.
+    let output = run(&["link", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert!(!files::backup_exists(".gitconfig"));
+}
+
+#[test]
+fn link_records_deployed_files_in_manifest() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run(&["link", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    let manifest = files::read_manifest().unwrap();
+    dbg!(&manifest);
+
+    assert!(manifest.starts_with("# root: "));
+    assert!(manifest.contains("link\t100644\t"));
+    assert!(manifest.contains("\t.gitconfig\t"));
+}
+
+#[test]
+fn link_prune_removes_dangling_links() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+    let bashrc = conf::create_file_in_configs(".bashrc", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    std::fs::remove_file(bashrc).unwrap();
+
+    let output = run(&["link", "--prune", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert!(output.stdout.contains("Pruned 1 file.\n"));
+    assert!(files::symlink_exists_in_home(".gitconfig"));
+    assert!(!files::symlink_exists_in_home(".bashrc"));
+}
+
+#[test]
+fn link_prune_keeps_links_pointing_elsewhere() {
+    conf::init();
+
+    let bashrc = conf::create_file_in_configs(".bashrc", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    std::fs::remove_file(bashrc).unwrap();
+    std::fs::remove_file(Path::new(conf::HOME).join(".bashrc")).unwrap();
+    conf::create_symlink_in_home(".bashrc", None);
+
+    let output = run(&["link", "--prune", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert!(output.stderr.contains("Not pruning"));
+    assert!(files::symlink_exists_in_home(".bashrc"));
+}
+
+#[test]
+fn link_atomic_rolls_back_every_link_if_one_fails() {
+    conf::init();
+
+    conf::create_file_in_configs(".bashrc", None);
+    conf::create_file_in_configs(".gitconfig", None);
+    conf::create_file_in_home(".gitconfig", Some("from home"));
+    // `foo.txt` directory is not empty, it can't be replaced.
+    conf::create_file_in_configs("foo.txt", None);
+    conf::create_file_in_home("foo.txt/baz.log", None);
+
+    let output = run(&["link", "--atomic", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stdout.contains("Rolled back 2 files."));
+
+    assert!(!files::symlink_exists_in_home(".bashrc"));
+    assert!(!files::symlink_exists_in_home(".gitconfig"));
+    assert_eq!(files::read_in_home(".gitconfig"), "from home");
+    assert!(files::read_manifest().is_none());
+}
+
+#[test]
+fn link_atomic_rolls_back_if_post_link_hook_fails() {
+    conf::init();
+
+    conf::create_file_in_configs(".bashrc", None);
+    conf::create_executable_file_in_configs("post-link.sh", Some("exit 1"));
+
+    let output = run(&["link", "--atomic", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stdout.contains("Rolled back 1 file."));
+    assert!(!files::symlink_exists_in_home(".bashrc"));
+    assert!(files::read_manifest().is_none());
+}
+
+#[test]
+fn link_folds_directories() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/nvim fold\n"));
+    conf::create_file_in_configs(".config/nvim/init.lua", Some("-- init"));
+    conf::create_file_in_configs(".config/nvim/lua/plugins.lua", None);
+    conf::create_file_in_configs(".bashrc", None);
+
+    let output = run(&["--verbose", "link", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.contains(".config/nvim/\n"));
+    assert!(output.stdout.contains("Linked 3 files"));
+
+    // The directory is linked, not the files in it.
+    assert!(files::symlink_exists_in_home(".config/nvim"));
+    assert_eq!(
+        files::read_symlink_in_home(".config/nvim"),
+        Path::new(&conf::root()).join(".config/nvim")
+    );
+    assert_eq!(files::read_in_home(".config/nvim/init.lua"), "-- init");
+    assert!(files::symlink_exists_in_home(".bashrc"));
+    // The attributes file is not a config file.
+    assert!(!files::symlink_exists_in_home(".deezattributes"));
+
+    // Files are still recorded one by one.
+    let manifest = files::read_manifest().unwrap();
+    assert!(manifest.contains("\t.config/nvim/init.lua\t"));
+    assert!(manifest.contains("\t.config/nvim/lua/plugins.lua\t"));
+
+    // Linking again is a no-op.
+    let output = run(&["link", "--dry-run", &conf::root()]);
+    assert!(output.stdout.contains("no-op            .config/nvim/"));
+}
+
+#[test]
+fn link_unfolds_directories_into_existing_ones() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/nvim fold\n"));
+    conf::create_file_in_configs(".config/nvim/init.lua", None);
+    conf::create_file_in_home(".config/nvim/local.lua", Some("machine-specific"));
+
+    let output = run(&["link", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    // The directory is never replaced, its files are linked into it.
+    assert!(files::dir_exists_in_home(".config/nvim"));
+    assert!(!files::symlink_exists_in_home(".config/nvim"));
+    assert!(files::symlink_exists_in_home(".config/nvim/init.lua"));
+    assert_eq!(
+        files::read_in_home(".config/nvim/local.lua"),
+        "machine-specific"
+    );
+}
+
+#[test]
+fn link_unfolds_directories_that_are_no_longer_folded() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/nvim fold\n"));
+    conf::create_file_in_configs(".config/nvim/init.lua", Some("-- init"));
+    conf::create_file_in_configs(".config/nvim/lua/plugins.lua", Some("-- plugins"));
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    assert!(files::symlink_exists_in_home(".config/nvim"));
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/nvim -fold\n"));
+
+    let output = run(&["link", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert!(files::dir_exists_in_home(".config/nvim"));
+    assert!(!files::symlink_exists_in_home(".config/nvim"));
+    assert!(files::symlink_exists_in_home(".config/nvim/init.lua"));
+    assert!(files::symlink_exists_in_home(
+        ".config/nvim/lua/plugins.lua"
+    ));
+
+    // Nothing was linked through the directory's link.
+    assert!(!files::symlink_exists_in_configs(".config/nvim/init.lua"));
+    assert_eq!(files::read_in_configs(".config/nvim/init.lua"), "-- init");
+
+    // And it can be folded back.
+    let output = run(&["undo"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+    assert_eq!(output.exit_code, 0);
+    assert!(files::symlink_exists_in_home(".config/nvim"));
+}
+
+#[test]
+fn link_undo_removes_folded_directories() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/nvim fold\n"));
+    conf::create_file_in_configs(".config/nvim/init.lua", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["undo"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::symlink_exists_in_home(".config/nvim"));
+    assert!(files::file_exists_in_configs(".config/nvim/init.lua"));
+}
+
+#[test]
+fn link_prune_removes_folded_directories_deleted_from_root() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/nvim fold\n"));
+    conf::create_file_in_configs(".config/nvim/init.lua", None);
+    conf::create_file_in_configs(".bashrc", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    std::fs::remove_dir_all(Path::new(&conf::root()).join(".config")).unwrap();
+
+    let output = run(&["link", "--prune", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.contains("Pruned 1 file."));
+    assert!(!files::symlink_exists_in_home(".config/nvim"));
+    assert!(!files::read_manifest().unwrap().contains(".config/nvim"));
+}
+
+#[test]
+fn link_folds_directories_into_mapped_targets() {
+    conf::init();
+
+    conf::create_file_in_configs(".deeztargets", Some(".config $XDG_CONFIG_HOME/\n"));
+    conf::create_file_in_configs(".deezattributes", Some(".config/nvim fold\n"));
+    conf::create_file_in_configs(".config/nvim/init.lua", Some("-- init"));
+
+    let xdg = Path::new(conf::HOME).join("xdg").display().to_string();
+    let env = [("XDG_CONFIG_HOME", Some(xdg.as_str()))];
+
+    let output = run_with_env(&["link", &conf::root()], conf::CONFIGS, &env);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    // The directory is linked where `.deeztargets` maps it.
+    assert!(files::symlink_exists_in_home("xdg/nvim"));
+    assert_eq!(
+        files::read_symlink_in_home("xdg/nvim"),
+        Path::new(&conf::root()).join(".config/nvim")
+    );
+    assert!(!files::file_exists_in_home(".config/nvim"));
+
+    // Linking again is a no-op.
+    let output = run_with_env(&["link", "--dry-run", &conf::root()], conf::CONFIGS, &env);
+    assert!(output.stdout.contains("no-op            .config/nvim/"));
+
+    let output = run_with_env(&["status", &conf::root()], conf::CONFIGS, &env);
+    dbg!(&output.stdout);
+    assert!(output.stdout.contains("  S  .config/nvim/init.lua@\n"));
+
+    let output = run_with_env(&["clean", &conf::root()], conf::CONFIGS, &env);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::symlink_exists_in_home("xdg/nvim"));
+    assert!(files::file_exists_in_configs(".config/nvim/init.lua"));
+}
+
+#[test]
+fn link_with_invalid_attributes_file_errors() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/nvim shiny\n"));
+    conf::create_file_in_configs(".config/nvim/init.lua", None);
+
+    let output = run(&["link", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(
+        output
+            .stderr
+            .contains(".deezattributes, line 1: Unknown attribute 'shiny'")
+    );
+    assert!(!files::symlink_exists_in_home(".config/nvim/init.lua"));
+}
+
+#[test]
+fn link_relative_creates_relative_symlinks() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("gitconfig"));
+    conf::create_file_in_configs(".config/fish/config.fish", Some("fish"));
+
+    let output = run(&["link", "--relative", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert_eq!(
+        files::read_symlink_in_home(".gitconfig"),
+        Path::new("../configs/.gitconfig")
+    );
+    assert_eq!(
+        files::read_symlink_in_home(".config/fish/config.fish"),
+        Path::new("../../../configs/.config/fish/config.fish")
+    );
+    assert_eq!(files::read_in_home(".config/fish/config.fish"), "fish");
+}
+
+#[test]
+fn link_relative_replaces_absolute_symlinks_and_back() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    assert!(files::read_symlink_in_home(".gitconfig").is_absolute());
+
+    let output = run(&["link", "--relative", "--dry-run", &conf::root()]);
+    dbg!(&output.stdout);
+    assert!(output.stdout.contains("replace symlink  .gitconfig"));
+
+    let output = run(&["link", "--relative", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    assert!(files::read_symlink_in_home(".gitconfig").is_relative());
+
+    let output = run(&["link", "--relative", "--dry-run", &conf::root()]);
+    dbg!(&output.stdout);
+    assert!(output.stdout.contains("no-op            .gitconfig"));
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    assert!(files::read_symlink_in_home(".gitconfig").is_absolute());
+}
+
+#[test]
+fn link_relative_symlinks_survive_moving_root_and_home_together() {
+    conf::init();
+
+    let tmp = Path::new(conf::HOME).parent().unwrap();
+    let moved = tmp.join("moved");
+    if moved.exists() {
+        fs::remove_dir_all(&moved).unwrap();
+    }
+
+    conf::create_file_in_configs(".config/fish/config.fish", Some("fish"));
+
+    let output = run(&["link", "--relative", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    // Move both, keeping their relative layout.
+    fs::create_dir(&moved).unwrap();
+    fs::rename(conf::HOME, moved.join("home")).unwrap();
+    fs::rename(conf::CONFIGS, moved.join("configs")).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(moved.join("home/.config/fish/config.fish")).unwrap(),
+        "fish"
+    );
+
+    fs::remove_dir_all(&moved).unwrap();
+}
+
+#[test]
+fn link_relative_folds_directories() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/nvim fold\n"));
+    conf::create_file_in_configs(".config/nvim/init.lua", None);
+
+    let output = run(&["link", "--relative", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        files::read_symlink_in_home(".config/nvim"),
+        Path::new("../../configs/.config/nvim")
+    );
+    assert!(files::file_exists_in_home(".config/nvim/init.lua"));
+}
+
+#[test]
+fn link_prune_removes_relative_symlinks() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+    conf::create_file_in_configs(".config/fish/config.fish", None);
+
+    let output = run(&["link", "--relative", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    fs::remove_file(Path::new(conf::CONFIGS).join(".config/fish/config.fish")).unwrap();
+
+    let output = run(&["link", "--relative", "--prune", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!output.stderr.contains("Not pruning"));
+    assert!(!files::symlink_exists_in_home(".config/fish/config.fish"));
+    assert!(files::symlink_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn link_leaves_files_declared_as_copies_alone() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".gitconfig copy\n"));
+    conf::create_file_in_configs(".config/fish/config.fish", None);
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run(&["link", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.starts_with("Linked 1 file"));
+    assert!(files::symlink_exists_in_home(".config/fish/config.fish"));
+    assert!(!files::file_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn link_leaves_templates_alone() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig.tmpl", Some("os = {{ os }}\n"));
+    conf::create_file_in_configs(".bashrc", None);
+
+    let output = run(&["link", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(
+        output
+            .stderr
+            .contains("Skipping '.gitconfig.tmpl': It is a template, sync it instead.")
+    );
+    assert!(files::symlink_exists_in_home(".bashrc"));
+    assert!(!files::file_exists_in_home(".gitconfig"));
+    assert!(!files::file_exists_in_home(".gitconfig.tmpl"));
+}
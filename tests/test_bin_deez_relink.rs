@@ -0,0 +1,240 @@
+mod utils;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use utils::conf;
+use utils::files;
+use utils::run::{run, run_in_dir};
+
+// Warning: These tests MUST be run sequentially. Running them in
+// parallel threads may cause conflicts with environment variables,
+// as a variable may be overridden before it is used.
+
+/// Where the root was before it moved to `conf::CONFIGS`.
+fn old_root() -> PathBuf {
+    Path::new(conf::CONFIGS).with_file_name("old-configs")
+}
+
+/// Link the root from its old location, then move it to `conf::CONFIGS`.
+fn link_then_move_root(args: &[&str]) {
+    let old_root = old_root();
+    if old_root.exists() {
+        fs::remove_dir_all(&old_root).unwrap();
+    }
+    fs::rename(conf::CONFIGS, &old_root).unwrap();
+
+    let mut args = args.to_vec();
+    let old_root_arg = old_root.display().to_string();
+    args.push(&old_root_arg);
+    let output = run(&args);
+    assert_eq!(output.exit_code, 0);
+
+    fs::rename(&old_root, conf::CONFIGS).unwrap();
+}
+
+#[test]
+fn relink_repoints_links_to_moved_root() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("gitconfig"));
+    conf::create_file_in_configs(".config/fish/config.fish", Some("fish"));
+
+    link_then_move_root(&["link"]);
+
+    // Dangling.
+    assert!(!Path::new(conf::HOME).join(".gitconfig").exists());
+
+    let output = run_in_dir(&["--verbose", "relink"], conf::CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(
+        output
+            .stdout
+            .starts_with(".config/fish/config.fish\n.gitconfig\n")
+    );
+    assert!(output.stdout.ends_with(&format!(
+        "Relinked 2 files from '{}'.\n",
+        old_root().display()
+    )));
+
+    assert_eq!(
+        files::read_symlink_in_home(".gitconfig"),
+        Path::new(conf::CONFIGS).join(".gitconfig")
+    );
+    assert_eq!(files::read_in_home(".gitconfig"), "gitconfig");
+    assert_eq!(files::read_in_home(".config/fish/config.fish"), "fish");
+}
+
+#[test]
+fn relink_with_explicit_old_root() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("gitconfig"));
+
+    link_then_move_root(&["link"]);
+
+    let old_root = old_root().display().to_string();
+    let output = run_in_dir(&["relink", &old_root], conf::CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "gitconfig");
+}
+
+#[test]
+fn relink_keeps_relative_links_relative() {
+    conf::init();
+
+    conf::create_file_in_configs(".config/fish/config.fish", Some("fish"));
+
+    link_then_move_root(&["link", "--relative"]);
+
+    let output = run_in_dir(&["relink"], conf::CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        files::read_symlink_in_home(".config/fish/config.fish"),
+        Path::new("../../../configs/.config/fish/config.fish")
+    );
+    assert_eq!(files::read_in_home(".config/fish/config.fish"), "fish");
+}
+
+#[test]
+fn relink_repoints_folded_directories() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/nvim fold\n"));
+    conf::create_file_in_configs(".config/nvim/init.lua", Some("-- init"));
+    conf::create_file_in_configs(".config/nvim/lua/plugins.lua", None);
+
+    link_then_move_root(&["link"]);
+
+    let output = run_in_dir(&["--dry-run", "relink"], conf::CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(
+        output
+            .stdout
+            .starts_with("replace symlink  .config/nvim/\nWould relink 2 files from")
+    );
+
+    let output = run_in_dir(&["relink"], conf::CONFIGS);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        files::read_symlink_in_home(".config/nvim"),
+        Path::new(conf::CONFIGS).join(".config/nvim")
+    );
+    assert_eq!(files::read_in_home(".config/nvim/init.lua"), "-- init");
+}
+
+#[test]
+fn relink_reports_links_to_files_not_in_root() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+    conf::create_file_in_configs(".bashrc", None);
+
+    link_then_move_root(&["link"]);
+
+    fs::remove_file(Path::new(conf::CONFIGS).join(".bashrc")).unwrap();
+
+    let output = run_in_dir(&["relink"], conf::CONFIGS);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stderr.contains(&format!(
+        "warning: Not relinking '{}': It is not in the root anymore.",
+        Path::new(conf::HOME).join(".bashrc").display()
+    )));
+    assert!(output.stdout.ends_with(", 1 not in root.\n"));
+
+    assert_eq!(
+        files::read_symlink_in_home(".gitconfig"),
+        Path::new(conf::CONFIGS).join(".gitconfig")
+    );
+    // Left as is.
+    assert_eq!(
+        files::read_symlink_in_home(".bashrc"),
+        old_root().join(".bashrc")
+    );
+}
+
+#[test]
+fn relink_moves_manifest_to_new_root() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+    conf::create_file_in_configs(".config/fish/config.fish", None);
+
+    link_then_move_root(&["link"]);
+
+    let output = run_in_dir(&["relink"], conf::CONFIGS);
+    assert_eq!(output.exit_code, 0);
+
+    // Pruning from the new root works, since it knows what was linked.
+    fs::remove_file(Path::new(conf::CONFIGS).join(".config/fish/config.fish")).unwrap();
+
+    let output = run(&["link", "--prune", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::symlink_exists_in_home(".config/fish/config.fish"));
+
+    // And there's nothing left to relink.
+    let output = run_in_dir(&["relink"], conf::CONFIGS);
+    dbg!(&output.stderr);
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stderr.contains("Could not find where the root was"));
+}
+
+#[test]
+fn relink_can_be_undone() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+
+    link_then_move_root(&["link"]);
+
+    let output = run_in_dir(&["relink"], conf::CONFIGS);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["undo"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.contains("from `relink` run"));
+    assert_eq!(
+        files::read_symlink_in_home(".gitconfig"),
+        old_root().join(".gitconfig")
+    );
+}
+
+#[test]
+fn relink_without_previous_root_errors() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run_in_dir(&["relink"], conf::CONFIGS);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert_eq!(
+        output.stderr,
+        "fatal: Could not find where the root was. Please provide its previous path.\n"
+    );
+}
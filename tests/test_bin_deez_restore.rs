@@ -0,0 +1,218 @@
+mod utils;
+
+use std::path::PathBuf;
+
+use utils::conf::{self, HOME};
+use utils::files;
+use utils::run::run;
+
+// Warning: These tests MUST be run sequentially. Running them in
+// parallel threads may cause conflicts with environment variables,
+// as a variable may be overridden before it is used.
+
+fn home_path(file_path: &str) -> String {
+    PathBuf::from(HOME).join(file_path).display().to_string()
+}
+
+#[test]
+fn restore_regular() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("from root"));
+    conf::create_file_in_home(".gitconfig", Some("local edits"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["restore", &home_path(".gitconfig")]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert_eq!(files::read_in_home(".gitconfig"), "local edits");
+    assert_eq!(output.stdout, "Restored 1 file.\n");
+}
+
+#[test]
+fn restore_uses_most_recent_backup() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("from root"));
+
+    conf::create_file_in_home(".gitconfig", Some("edit 1"));
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    conf::create_file_in_home(".gitconfig", Some("edit 2"));
+    // Edited since the last sync, the home is kept unless forced.
+    let output = run(&["sync", "--force", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["restore", &home_path(".gitconfig")]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "edit 2");
+}
+
+#[test]
+fn restore_from_given_run() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("from root"));
+
+    conf::create_file_in_home(".gitconfig", Some("edit 1"));
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let first_run = files::backup_runs().pop().unwrap();
+    let first_run = first_run.file_name().unwrap().to_string_lossy();
+
+    conf::create_file_in_home(".gitconfig", Some("edit 2"));
+    // Edited since the last sync, the home is kept unless forced.
+    let output = run(&["sync", "--force", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["restore", "--run", &first_run, &home_path(".gitconfig")]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "edit 1");
+}
+
+#[test]
+fn restore_removed_file() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+    conf::create_file_in_home(".config/git/config", Some("local"));
+    conf::create_file_in_configs(".config/git/config", None);
+
+    let output = run(&["clean", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::file_exists_in_home(".config/git/config"));
+
+    let output = run(&["restore", &home_path(".config/git/config")]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".config/git/config"), "local");
+}
+
+#[test]
+fn restore_can_be_undone() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("from root"));
+    conf::create_file_in_home(".gitconfig", Some("local edits"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["restore", &home_path(".gitconfig")]);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "local edits");
+
+    let output = run(&["undo"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.contains("from `restore` run"));
+    assert_eq!(files::read_in_home(".gitconfig"), "from root");
+}
+
+#[test]
+fn restore_does_not_count_files_already_restored() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("from root"));
+    conf::create_file_in_home(".gitconfig", Some("local edits"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    // Already the same as the backup, nothing to write.
+    conf::create_file_in_home(".gitconfig", Some("local edits"));
+
+    let output = run(&["restore", &home_path(".gitconfig")]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "local edits");
+    assert_eq!(output.stdout, "Restored 0 files.\n");
+}
+
+#[test]
+fn restore_dry_run_does_not_restore() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("from root"));
+    conf::create_file_in_home(".gitconfig", Some("local edits"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["-n", "restore", &home_path(".gitconfig")]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "from root");
+    assert_eq!(
+        output.stdout,
+        format!(
+            "overwrite        {}\nWould restore 1 file (dry run).\n",
+            home_path(".gitconfig")
+        )
+    );
+}
+
+#[test]
+fn restore_file_without_backup_errors() {
+    conf::init();
+
+    let output = run(&["restore", &home_path(".gitconfig")]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stderr.contains("No backup of '"));
+}
+
+#[test]
+fn restore_unknown_run_errors() {
+    conf::init();
+
+    let output = run(&[
+        "restore",
+        "--run",
+        "19700101-000000-000",
+        &home_path(".gitconfig"),
+    ]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(
+        output
+            .stderr
+            .contains("No run with id '19700101-000000-000'.")
+    );
+}
+
+#[test]
+fn restore_without_path_errors() {
+    conf::init();
+
+    let output = run(&["restore"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 2);
+}
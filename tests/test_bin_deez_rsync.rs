@@ -13,7 +13,7 @@ use std::path::Path;
 
 use utils::conf;
 use utils::files;
-use utils::run::{run, run_in_dir};
+use utils::run::{run, run_in_dir, run_with_env, run_with_input};
 use utils::{mock_bin, output_file_exists, read_output_file, remove_output_file};
 
 // Warning: These tests MUST be run sequentially. Running them in
@@ -517,3 +517,396 @@ fn rsync_hooks_abort_execution_if_exit_code_is_non_zero() {
     // The aborted `rsync` did not pull the home version into configs.
     assert_eq!(files::read_in_configs(".gitconfig"), "old");
 }
+
+#[test]
+fn rsync_dry_run_does_not_touch_configs() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("old"));
+    conf::create_file_in_configs(".bashrc", Some("same"));
+
+    conf::create_file_in_home(".gitconfig", Some("new"));
+    conf::create_file_in_home(".bashrc", Some("same"));
+
+    let output = run(&["rsync", "--dry-run", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert!(output.stdout.contains("overwrite        .gitconfig\n"));
+    assert!(output.stdout.contains("no-op            .bashrc\n"));
+    assert!(output.stdout.contains("Would rsync 2 files (dry run)."));
+
+    assert_eq!(files::read_in_configs(".gitconfig"), "old");
+}
+
+#[test]
+fn rsync_keeps_files_changed_in_root_only() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("v1"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    // E.g., after a `git pull`.
+    conf::create_file_in_configs(".gitconfig", Some("pulled"));
+
+    let output = run(&["rsync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stderr.contains("Skipping '.gitconfig'"));
+    assert_eq!(files::read_in_configs(".gitconfig"), "pulled");
+}
+
+#[test]
+fn rsync_refuses_files_changed_on_both_ends() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("v1"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    conf::create_file_in_configs(".gitconfig", Some("root edits"));
+    conf::create_file_in_home(".gitconfig", Some("home edits"));
+
+    let output = run(&["rsync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stderr.contains("  .gitconfig\n"));
+    assert_eq!(files::read_in_configs(".gitconfig"), "root edits");
+}
+
+#[test]
+fn rsync_theirs_resolves_conflicts_with_home() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("v1"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    conf::create_file_in_configs(".gitconfig", Some("root edits"));
+    conf::create_file_in_home(".gitconfig", Some("home edits"));
+
+    let output = run(&["rsync", "--theirs", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_configs(".gitconfig"), "home edits");
+}
+
+#[test]
+fn rsync_skipped_files_keep_their_base() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("v1"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    let manifest = files::read_manifest().unwrap();
+
+    // Pulled into the root, not synced yet.
+    conf::create_file_in_configs(".gitconfig", Some("pulled"));
+
+    let output = run(&["rsync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "rSynced 0 files, 1 skipped.\n");
+    assert_eq!(files::read_manifest().unwrap(), manifest);
+
+    // The root's changes still go to the home.
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "pulled");
+}
+
+#[test]
+fn rsync_updates_last_synced_version() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("v1"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    conf::create_file_in_home(".gitconfig", Some("home edits"));
+
+    let output = run(&["rsync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_configs(".gitconfig"), "home edits");
+
+    // The root moves on from what was rsynced; not a conflict.
+    conf::create_file_in_configs(".gitconfig", Some("v2"));
+
+    let output = run(&["sync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "v2");
+}
+
+#[test]
+fn rsync_interactive_asks_before_each_file() {
+    conf::init();
+
+    conf::create_file_in_configs(".bashrc", Some("root"));
+    conf::create_file_in_home(".bashrc", Some("home"));
+    conf::create_file_in_configs(".gitconfig", Some("root"));
+    conf::create_file_in_home(".gitconfig", Some("home"));
+
+    let output = run_with_input(&["rsync", "-i", &conf::root()], "y\nn\n");
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(
+        output
+            .stdout
+            .contains("overwrite '.bashrc'? [y,n,d,e,a,q,?]")
+    );
+    // Changes go from home to root.
+    assert!(output.stdout.contains("-root\n+home\n"));
+
+    assert_eq!(files::read_in_configs(".bashrc"), "home");
+    assert_eq!(files::read_in_configs(".gitconfig"), "root");
+}
+
+#[test]
+fn rsync_interactive_edit_before_applying() {
+    conf::init();
+
+    mock_bin("fake-editor", "bin_editor_append");
+    unsafe {
+        env::set_var("EDITOR", "fake-editor");
+    }
+
+    conf::create_file_in_configs(".gitconfig", Some("root\n"));
+    conf::create_file_in_home(".gitconfig", Some("home\n"));
+
+    let output = run_with_input(&["rsync", "-i", &conf::root()], "e\ny\n");
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_configs(".gitconfig"), "home\nedited\n");
+    // The home is left untouched.
+    assert_eq!(files::read_in_home(".gitconfig"), "home\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn rsync_carries_permissions_back() {
+    conf::init();
+
+    conf::create_file_in_configs(".local/bin/script", Some("echo hi"));
+    conf::set_mode_in_configs(".local/bin/script", 0o644);
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    conf::set_mode_in_home(".local/bin/script", 0o755);
+
+    let output = run(&["rsync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::mode_in_configs(".local/bin/script"), 0o755);
+}
+
+#[cfg(unix)]
+#[test]
+fn rsync_keeps_permissions_changed_in_root() {
+    conf::init();
+
+    conf::create_file_in_configs(".local/bin/script", Some("echo hi"));
+    conf::set_mode_in_configs(".local/bin/script", 0o644);
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    // E.g., pulled from Git.
+    conf::set_mode_in_configs(".local/bin/script", 0o755);
+
+    let output = run(&["rsync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stderr.contains("Skipping '.local/bin/script'"));
+    assert_eq!(files::mode_in_configs(".local/bin/script"), 0o755);
+}
+
+/// The root is written to the same way `sync` writes to the home: next
+/// to the file, then renamed over it.
+#[cfg(unix)]
+#[test]
+fn rsync_replaces_files_atomically() {
+    conf::init();
+
+    conf::create_file_in_configs(".bashrc", Some("from root"));
+    conf::set_mode_in_configs(".bashrc", 0o444);
+    conf::create_file_in_home(".bashrc", Some("from home"));
+    conf::set_mode_in_home(".bashrc", 0o444);
+
+    let output = run(&["rsync", "--force", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_configs(".bashrc"), "from home");
+
+    let leftovers = std::fs::read_dir(conf::root())
+        .unwrap()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name().to_string_lossy().ends_with(".deez-tmp"))
+        .count();
+    assert_eq!(leftovers, 0);
+}
+
+#[test]
+fn rsync_leaves_folded_directories_alone() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/nvim fold\n"));
+    conf::create_file_in_configs(".config/nvim/init.lua", Some("-- init"));
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["rsync", "--dry-run", &conf::root()]);
+    dbg!(&output.stdout);
+    assert!(
+        output
+            .stdout
+            .contains("no-op            .config/nvim/init.lua")
+    );
+
+    let output = run(&["rsync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(files::file_exists_in_configs(".config/nvim/init.lua"));
+    assert_eq!(files::read_in_configs(".config/nvim/init.lua"), "-- init");
+}
+
+#[test]
+fn rsync_does_not_replace_relative_symlink_with_file() {
+    conf::init();
+
+    conf::create_file_in_configs(".config/fish/config.fish", Some("fish"));
+
+    let output = run(&["link", "--relative", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["rsync", "--dry-run", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+    assert!(
+        output
+            .stdout
+            .contains("no-op            .config/fish/config.fish")
+    );
+
+    let output = run(&["rsync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(files::symlink_exists_in_home(".config/fish/config.fish"));
+    assert_eq!(files::read_in_configs(".config/fish/config.fish"), "fish");
+}
+
+#[test]
+fn rsync_follows_mapped_targets() {
+    conf::init();
+
+    conf::create_file_in_configs(".deeztargets", Some("share .local/share\n"));
+    conf::create_file_in_configs("share/fonts/fonts.conf", Some("old"));
+
+    conf::create_file_in_home(".local/share/fonts/fonts.conf", Some("new"));
+
+    let output = run(&["rsync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_configs("share/fonts/fonts.conf"), "new");
+}
+
+#[test]
+fn rsync_single_file_root() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("old"));
+    conf::create_file_in_configs(".bashrc", Some("old"));
+    conf::create_file_in_home(".gitconfig", Some("new"));
+    conf::create_file_in_home(".bashrc", Some("new"));
+
+    let file = Path::new(conf::CONFIGS).join(".gitconfig");
+    let output = run(&["rsync", &file.display().to_string()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_configs(".gitconfig"), "new");
+    assert_eq!(files::read_in_configs(".bashrc"), "old");
+}
+
+#[test]
+fn rsync_writes_back_to_alternate() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("plain"));
+    conf::create_file_in_configs(".gitconfig##host.work-laptop", Some("host"));
+
+    conf::create_file_in_home(".gitconfig", Some("edited"));
+
+    let output = run_with_env(
+        &["rsync", &conf::root()],
+        conf::CONFIGS,
+        &[("DEEZ_HOST", Some("work-laptop"))],
+    );
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        files::read_in_configs(".gitconfig##host.work-laptop"),
+        "edited"
+    );
+    assert_eq!(files::read_in_configs(".gitconfig"), "plain");
+}
+
+#[test]
+fn rsync_leaves_templates_alone() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig.tmpl", Some("os = {{ os }}\n"));
+
+    conf::create_file_in_home(".gitconfig", Some("edited\n"));
+
+    let output = run(&["rsync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(
+        output
+            .stderr
+            .contains("Skipping '.gitconfig.tmpl': It is a template, edit it in the root.")
+    );
+    assert_eq!(files::read_in_configs(".gitconfig.tmpl"), "os = {{ os }}\n");
+}
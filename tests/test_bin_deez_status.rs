@@ -10,7 +10,7 @@ use std::env;
 use std::path::Path;
 
 use utils::conf;
-use utils::run::{run, run_in_dir, run_with_input};
+use utils::run::{run, run_in_dir, run_with_env, run_with_input};
 use utils::{mock_bin, output_file_exists, read_output_file, remove_output_file};
 
 // Warning: These tests MUST be run sequentially. Running them in
@@ -58,6 +58,22 @@ Files
     );
 }
 
+#[test]
+fn status_compares_content_of_files_of_same_size() {
+    conf::init();
+
+    conf::create_file_in_configs("foo.txt", Some("this is foo"));
+    conf::create_file_in_home("foo.txt", Some("this is bar"));
+
+    let output = run(&["status", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert!(output.stdout.contains("  M  foo.txt\n"));
+}
+
 #[test]
 fn status_with_pathspec_only_lists_that_subtree() {
     conf::init();
@@ -480,3 +496,286 @@ fn status_hooks_abort_execution_if_exit_code_is_non_zero() {
     // The aborted `status` printed nothing about the configs.
     assert!(!output.stdout.contains(".gitconfig"));
 }
+
+#[cfg(unix)]
+#[test]
+fn status_reports_permissions_changes() {
+    conf::init();
+
+    conf::create_file_in_configs("foo.txt", Some("this is foo"));
+    conf::create_file_in_configs("bar.txt", Some("this is bar"));
+
+    conf::create_file_in_home("foo.txt", Some("this is foo"));
+    conf::create_file_in_home("bar.txt", Some("this is bar"));
+    conf::set_mode_in_configs("bar.txt", 0o755);
+    conf::set_mode_in_home("bar.txt", 0o644);
+
+    let output = run(&["status", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        output.stdout,
+        "\
+Files
+  P  bar.txt
+  S  foo.txt
+1 in sync, 0 modified, 1 permissions changed, 0 missing.
+"
+    );
+}
+
+#[test]
+fn status_shows_files_of_folded_directories_as_linked() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/nvim fold\n"));
+    conf::create_file_in_configs(".config/nvim/init.lua", None);
+    conf::create_file_in_configs(".bashrc", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["status", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        output.stdout,
+        "\
+Files
+  S  .bashrc@
+  S  .config/nvim/init.lua@
+2 in sync, 0 modified, 0 missing.
+"
+    );
+}
+
+#[test]
+fn status_shows_relative_symlinks_as_linked() {
+    conf::init();
+
+    conf::create_file_in_configs(".config/fish/config.fish", None);
+
+    let output = run(&["link", "--relative", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["status", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        output.stdout,
+        "\
+Files
+  S  .config/fish/config.fish@
+1 in sync, 0 modified, 0 missing.
+"
+    );
+}
+
+#[test]
+fn status_shows_declared_strategies() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/* link\n.gitconfig copy\n"));
+    conf::create_file_in_configs(".config/fish/config.fish", None);
+    conf::create_file_in_configs(".gitconfig", None);
+    conf::create_file_in_configs(".bashrc", None);
+
+    let output = run(&["deploy", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["status", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        output.stdout,
+        "\
+Files
+  S  .bashrc
+  S  .config/fish/config.fish@  (link)
+  S  .gitconfig  (copy)
+3 in sync, 0 modified, 0 missing.
+"
+    );
+}
+
+#[test]
+fn status_follows_mapped_targets() {
+    conf::init();
+
+    conf::create_file_in_configs(".deeztargets", Some("share .local/share\n"));
+    conf::create_file_in_configs("share/fonts/font.ttf", Some("font"));
+
+    conf::create_file_in_home(".local/share/fonts/font.ttf", Some("font"));
+
+    let output = run(&["status", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        output.stdout,
+        "\
+Files
+  S  share/fonts/font.ttf
+1 in sync, 0 modified, 0 missing.
+"
+    );
+}
+
+#[test]
+fn status_single_file_root() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+    conf::create_file_in_configs(".bashrc", None);
+
+    let file = Path::new(conf::CONFIGS).join(".gitconfig");
+    let output = run(&["status", &file.display().to_string()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        output.stdout,
+        "\
+Files
+  !  .gitconfig
+0 in sync, 0 modified, 1 missing.
+"
+    );
+}
+
+#[test]
+fn status_resolves_alternates() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("plain"));
+    conf::create_file_in_configs(".gitconfig##host.work-laptop", Some("host"));
+
+    conf::create_file_in_home(".gitconfig", Some("host"));
+
+    let output = run_with_env(
+        &["status", &conf::root()],
+        conf::CONFIGS,
+        &[("DEEZ_HOST", Some("work-laptop"))],
+    );
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        output.stdout,
+        "\
+Files
+  S  .gitconfig##host.work-laptop
+1 in sync, 0 modified, 0 missing.
+"
+    );
+}
+
+#[test]
+fn status_compares_rendered_templates() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig.tmpl", Some("host = {{ host }}\n"));
+
+    conf::create_file_in_home(".gitconfig", Some("host = work-laptop\n"));
+
+    let output = run_with_env(
+        &["status", &conf::root()],
+        conf::CONFIGS,
+        &[("DEEZ_HOST", Some("work-laptop"))],
+    );
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        output.stdout,
+        "\
+Files
+  S  .gitconfig.tmpl
+1 in sync, 0 modified, 0 missing.
+"
+    );
+}
+
+#[test]
+fn status_rev_compares_home_against_revision() {
+    conf::init();
+
+    remove_output_file("output_git_args");
+    mock_bin("git", "bin_git_rev");
+
+    conf::create_file_in_configs(".gitconfig", Some("[user]\n\tname = New Name\n"));
+    conf::create_file_in_configs(".bashrc", None);
+
+    conf::create_file_in_home(".gitconfig", Some("[user]\n\tname = Old Name\n"));
+
+    let output = run(&["status", "--rev", "v1.0", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    // The home is still on `v1.0`, and `.bashrc` came after it.
+    assert_eq!(
+        output.stdout,
+        "\
+Files
+  S  .gitconfig
+1 in sync, 0 modified, 0 missing.
+"
+    );
+
+    let args = read_output_file("output_git_args");
+    let mut args = args.lines();
+    assert_eq!(args.next(), Some("rev-parse --show-toplevel --show-prefix"));
+    assert_eq!(args.next(), Some("read-tree v1.0:"));
+    assert!(
+        args.next()
+            .unwrap()
+            .starts_with("checkout-index --all --prefix=")
+    );
+}
+
+#[test]
+fn status_rev_unknown_revision_errors() {
+    conf::init();
+
+    remove_output_file("output_git_args");
+    mock_bin("git", "bin_git_rev");
+
+    let output = run(&["status", "--rev", "v0.0", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 128);
+    assert!(output.stderr.contains("Not a valid object name v0.0:"));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn status_rev_rejects_remote_root() {
+    conf::init();
+
+    mock_bin("git", "bin_git_clone");
+
+    let output = run(&["status", "--rev", "v1.0", "git:fake-url"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 2);
+    assert!(
+        output
+            .stderr
+            .contains("'--rev' only works with local config roots.")
+    );
+}
@@ -4,11 +4,12 @@ mod utils;
 mod hook_macros;
 
 use std::env;
+use std::fs;
 use std::path::{Path, PathBuf};
 
-use utils::conf::{self, CONFIGS};
+use utils::conf::{self, CONFIGS, HOME};
 use utils::files;
-use utils::run::{run, run_in_dir};
+use utils::run::{run, run_in_dir, run_with_env, run_with_input};
 use utils::{mock_bin, output_file_exists, read_output_file, remove_output_file};
 
 // Generate shared hook tests for this command.
@@ -557,3 +558,1324 @@ fn sync_hooks_abort_execution_if_exit_code_is_non_zero() {
     // The aborted `sync` did not touch the home.
     assert!(!files::file_exists_in_home(".gitconfig"));
 }
+
+#[test]
+fn sync_dry_run_does_not_touch_home() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("new"));
+    conf::create_file_in_configs(".config/nvim/init.lua", None);
+    conf::create_symlink_in_configs(".config/ghostty/config", None);
+
+    conf::create_file_in_home(".gitconfig", Some("old"));
+
+    let output = run(&["sync", "--dry-run", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert!(output.stdout.contains("overwrite        .gitconfig\n"));
+    assert!(
+        output
+            .stdout
+            .contains("create           .config/nvim/init.lua\n")
+    );
+    assert!(
+        output
+            .stdout
+            .contains("create           .config/ghostty/config\n")
+    );
+    assert!(output.stdout.contains("Would sync 3 files (dry run)."));
+
+    assert_eq!(files::read_in_home(".gitconfig"), "old");
+    assert!(!files::file_exists_in_home(".config/nvim/init.lua"));
+    assert!(!files::symlink_exists_in_home(".config/ghostty/config"));
+}
+
+#[test]
+fn sync_dry_run_reports_unchanged_files_as_no_op() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("same"));
+    conf::create_file_in_home(".gitconfig", Some("same"));
+
+    let output = run(&["sync", "-n", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert!(output.stdout.contains("no-op            .gitconfig\n"));
+}
+
+#[test]
+fn sync_dry_run_reports_non_empty_directory_as_error() {
+    conf::init();
+
+    conf::create_file_in_configs("foo.txt", None);
+    conf::create_file_in_home("foo.txt/baz.log", None);
+
+    let output = run(&["sync", "--dry-run", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+
+    assert!(output.stderr.contains("Directory not empty"));
+    assert!(files::file_exists_in_home("foo.txt/baz.log"));
+}
+
+#[test]
+fn sync_dry_run_sets_env_var_for_hooks() {
+    conf::init();
+
+    conf::create_executable_file_in_configs("pre-sync.sh", Some(r"echo dry_run=$DEEZ_DRY_RUN"));
+
+    let output = run(&["sync", "--dry-run", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.contains("dry_run=true\n"));
+
+    let output = run(&["sync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.contains("dry_run=\n"));
+}
+
+/// Hooks run even in a dry-run, so an unmarked root must be confirmed.
+#[test]
+fn sync_dry_run_prompts_for_non_config_root() {
+    conf::init();
+
+    let root = conf::create_dir_in_configs("unmarked");
+    let root = root.display().to_string();
+
+    conf::create_executable_file_in_configs("unmarked/pre-sync.sh", Some("echo hook ran"));
+
+    let output = run_with_input(&["sync", "--dry-run", &root], "n\n");
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 2);
+    assert_eq!(output.stdout.matches("Proceed? (y/N) ").count(), 1);
+    assert!(!output.stdout.contains("hook ran"));
+}
+
+/// The journal is tab-separated, a file that can't be recorded in it
+/// can't be undone, and must be left alone.
+#[test]
+fn sync_leaves_files_the_journal_cannot_record_untouched() {
+    conf::init();
+
+    conf::create_file_in_configs("tab\tfile", Some("from root"));
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run(&["sync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stderr.contains("Could not record change to '"));
+    assert!(!files::file_exists_in_home("tab\tfile"));
+    assert!(files::file_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn sync_backs_up_overwritten_files() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("from root"));
+    conf::create_file_in_configs(".config/nvim/init.lua", Some("same"));
+
+    conf::create_file_in_home(".gitconfig", Some("local edits"));
+    conf::create_file_in_home(".config/nvim/init.lua", Some("same"));
+
+    let output = run(&["--verbose", "sync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert_eq!(files::read_in_home(".gitconfig"), "from root");
+    assert_eq!(files::read_in_latest_backup(".gitconfig"), "local edits");
+    // Same content, nothing to lose.
+    assert!(!files::backup_exists(".config/nvim/init.lua"));
+
+    let backup_dir = files::backup_runs().pop().unwrap();
+    assert!(
+        output
+            .stdout
+            .contains(&format!("Backed up 1 file to '{}'.", backup_dir.display()))
+    );
+}
+
+#[test]
+fn sync_backs_up_replaced_symlinks_as_symlinks() {
+    conf::init();
+
+    conf::create_file_in_configs("config.conf", Some("from root"));
+    let (_, target) = conf::create_symlink_in_home("config.conf", None);
+
+    let output = run(&["sync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    let backup_dir = files::backup_runs().pop().unwrap();
+    let backup = files::backup_of(&backup_dir, &Path::new(HOME).join("config.conf"));
+    assert!(backup.is_symlink());
+    assert_eq!(std::fs::read_link(backup).unwrap(), target);
+}
+
+#[test]
+fn sync_does_not_back_up_files_if_nothing_is_overwritten() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("same"));
+    conf::create_file_in_configs(".bashrc", None);
+
+    conf::create_file_in_home(".gitconfig", Some("same"));
+
+    let output = run(&["--verbose", "sync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert!(!files::backup_exists(".gitconfig"));
+    assert!(!files::backup_exists(".bashrc"));
+    assert!(!output.stdout.contains("Backed up"));
+}
+
+#[test]
+fn sync_dry_run_does_not_create_backups() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("from root"));
+    conf::create_file_in_home(".gitconfig", Some("local edits"));
+
+    let output = run(&["sync", "--dry-run", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert!(files::backup_runs().is_empty());
+}
+
+#[test]
+fn sync_records_deployed_files_in_manifest() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("from root"));
+    conf::create_symlink_in_configs(".config/ghostty/config", None);
+
+    let output = run(&["sync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    let manifest = files::read_manifest().unwrap();
+    dbg!(&manifest);
+
+    assert!(manifest.contains(&format!("# home: {HOME}\n")));
+    assert!(manifest.contains(&format!(
+        "\t.gitconfig\t{}\n",
+        Path::new(HOME).join(".gitconfig").display()
+    )));
+    assert!(manifest.contains("copy\t100644\t"));
+    // Symlinks are recorded as such.
+    assert!(manifest.contains("copy\t120000\t"));
+}
+
+#[test]
+fn sync_merges_manifest_entries() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+    conf::create_file_in_configs(".bashrc", None);
+
+    let output = run(&["sync", &conf::root(), "--", ".gitconfig"]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["sync", &conf::root(), "--", ".bashrc"]);
+    assert_eq!(output.exit_code, 0);
+
+    let manifest = files::read_manifest().unwrap();
+    dbg!(&manifest);
+
+    assert!(manifest.contains("\t.gitconfig\t"));
+    assert!(manifest.contains("\t.bashrc\t"));
+}
+
+#[test]
+fn sync_dry_run_does_not_record_manifest() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run(&["sync", "--dry-run", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    assert!(files::read_manifest().is_none());
+}
+
+#[test]
+fn sync_prune_removes_files_deleted_from_root() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+    let bashrc = conf::create_file_in_configs(".config/bash/bashrc", Some("root"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    std::fs::remove_file(bashrc).unwrap();
+
+    let output = run(&["--verbose", "sync", "--prune", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert!(output.stdout.contains(".config/bash/bashrc\n"));
+    assert!(output.stdout.contains("Pruned 1 file.\n"));
+
+    assert!(files::file_exists_in_home(".gitconfig"));
+    assert!(!files::file_exists_in_home(".config/bash/bashrc"));
+    // Empty parent directories are cleaned up too.
+    assert!(!files::dir_exists_in_home(".config"));
+
+    // Pruned files are backed up, and forgotten by the manifest.
+    assert_eq!(files::read_in_latest_backup(".config/bash/bashrc"), "root");
+    let manifest = files::read_manifest().unwrap();
+    assert!(manifest.contains("\t.gitconfig\t"));
+    assert!(!manifest.contains("\t.config/bash/bashrc\t"));
+}
+
+#[test]
+fn sync_without_prune_keeps_files_deleted_from_root() {
+    conf::init();
+
+    let bashrc = conf::create_file_in_configs(".bashrc", None);
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    std::fs::remove_file(bashrc).unwrap();
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    assert!(files::file_exists_in_home(".bashrc"));
+}
+
+#[test]
+fn sync_prune_keeps_files_edited_since_deployed() {
+    conf::init();
+
+    let bashrc = conf::create_file_in_configs(".bashrc", Some("root"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    std::fs::remove_file(bashrc).unwrap();
+    conf::create_file_in_home(".bashrc", Some("edited"));
+
+    let output = run(&["sync", "--prune", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert!(output.stderr.contains("Not pruning"));
+    assert_eq!(files::read_in_home(".bashrc"), "edited");
+}
+
+#[test]
+fn sync_prune_does_not_touch_files_deez_did_not_deploy() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+    conf::create_file_in_home(".bashrc", None);
+
+    let output = run(&["sync", "--prune", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    assert!(files::file_exists_in_home(".bashrc"));
+}
+
+#[test]
+fn sync_prune_respects_pathspec() {
+    conf::init();
+
+    let gitconfig = conf::create_file_in_configs(".gitconfig", None);
+    let bashrc = conf::create_file_in_configs(".bashrc", None);
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    std::fs::remove_file(gitconfig).unwrap();
+    std::fs::remove_file(bashrc).unwrap();
+
+    let output = run(&["sync", "--prune", &conf::root(), "--", ".bashrc"]);
+    assert_eq!(output.exit_code, 0);
+
+    assert!(files::file_exists_in_home(".gitconfig"));
+    assert!(!files::file_exists_in_home(".bashrc"));
+}
+
+#[test]
+fn sync_prune_dry_run_does_not_touch_home() {
+    conf::init();
+
+    let bashrc = conf::create_file_in_configs(".bashrc", None);
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    std::fs::remove_file(bashrc).unwrap();
+
+    let output = run(&["sync", "--prune", "--dry-run", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert!(output.stdout.contains("delete           .bashrc\n"));
+    assert!(output.stdout.contains("Would prune 1 file (dry run).\n"));
+
+    assert!(files::file_exists_in_home(".bashrc"));
+    assert!(files::read_manifest().unwrap().contains("\t.bashrc\t"));
+}
+
+#[test]
+fn sync_prune_can_be_undone() {
+    conf::init();
+
+    let bashrc = conf::create_file_in_configs(".bashrc", Some("root"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    std::fs::remove_file(bashrc).unwrap();
+
+    let output = run(&["sync", "--prune", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::file_exists_in_home(".bashrc"));
+
+    let output = run(&["undo"]);
+    assert_eq!(output.exit_code, 0);
+
+    assert_eq!(files::read_in_home(".bashrc"), "root");
+}
+
+#[test]
+fn sync_overwrites_files_changed_in_root_only() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("v1"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    conf::create_file_in_configs(".gitconfig", Some("v2"));
+
+    let output = run(&["sync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "v2");
+}
+
+#[test]
+fn sync_keeps_files_changed_in_home_only() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("v1"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    conf::create_file_in_home(".gitconfig", Some("home edits"));
+
+    let output = run(&["sync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stderr.contains("Skipping '.gitconfig'"));
+    assert_eq!(files::read_in_home(".gitconfig"), "home edits");
+}
+
+#[test]
+fn sync_refuses_files_changed_on_both_ends() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("v1"));
+    conf::create_file_in_configs(".bashrc", Some("v1"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    conf::create_file_in_configs(".gitconfig", Some("root edits"));
+    conf::create_file_in_home(".gitconfig", Some("home edits"));
+    conf::create_file_in_configs(".bashrc", Some("v2"));
+
+    let output = run(&["sync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stderr.contains(
+        "1 file changed in both the root and the home since the last sync:\n  .gitconfig\n"
+    ));
+    assert!(output.stdout.contains("Synced 1 file, 1 error."));
+
+    assert_eq!(files::read_in_home(".gitconfig"), "home edits");
+    // Other files are synced regardless.
+    assert_eq!(files::read_in_home(".bashrc"), "v2");
+
+    // Still a conflict until resolved.
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 1);
+}
+
+#[test]
+fn sync_dry_run_reports_conflicts() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("v1"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    conf::create_file_in_configs(".gitconfig", Some("root edits"));
+    conf::create_file_in_home(".gitconfig", Some("home edits"));
+
+    let output = run(&["sync", "--dry-run", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stderr.contains("  .gitconfig\n"));
+    assert_eq!(files::read_in_home(".gitconfig"), "home edits");
+}
+
+#[test]
+fn sync_theirs_resolves_conflicts_with_root() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("v1"));
+    conf::create_file_in_configs(".bashrc", Some("v1"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    conf::create_file_in_configs(".gitconfig", Some("root edits"));
+    conf::create_file_in_home(".gitconfig", Some("home edits"));
+    conf::create_file_in_home(".bashrc", Some("home edits"));
+
+    let output = run(&["sync", "--theirs", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "root edits");
+    assert_eq!(files::read_in_latest_backup(".gitconfig"), "home edits");
+    // Not a conflict, only the home changed.
+    assert_eq!(files::read_in_home(".bashrc"), "home edits");
+}
+
+#[test]
+fn sync_ours_keeps_conflicts_in_home() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("v1"));
+    conf::create_file_in_configs(".bashrc", Some("v1"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    conf::create_file_in_configs(".gitconfig", Some("root edits"));
+    conf::create_file_in_home(".gitconfig", Some("home edits"));
+
+    let output = run(&["sync", "--ours", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "home edits");
+    // Skipped files are not synced.
+    assert!(output.stdout.contains("Synced 1 file, 1 skipped."));
+
+    // Nothing was written, the base didn't move: still a conflict.
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 1);
+    assert_eq!(files::read_in_home(".gitconfig"), "home edits");
+}
+
+#[test]
+fn sync_skipped_files_keep_their_base() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("v1"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    let manifest = files::read_manifest().unwrap();
+
+    conf::create_file_in_home(".gitconfig", Some("home edits"));
+
+    let output = run(&["sync", "--dry-run", &conf::root()]);
+    dbg!(&output.stdout);
+    assert!(
+        output
+            .stdout
+            .contains("Would sync 0 files, 1 skipped (dry run).")
+    );
+
+    let output = run(&["sync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "Synced 0 files, 1 skipped.\n");
+    assert_eq!(files::read_manifest().unwrap(), manifest);
+}
+
+#[test]
+fn sync_force_overwrites_everything() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("v1"));
+    conf::create_file_in_configs(".bashrc", Some("v1"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    conf::create_file_in_configs(".gitconfig", Some("root edits"));
+    conf::create_file_in_home(".gitconfig", Some("home edits"));
+    conf::create_file_in_home(".bashrc", Some("home edits"));
+
+    let output = run(&["sync", "--force", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "root edits");
+    assert_eq!(files::read_in_home(".bashrc"), "v1");
+}
+
+#[test]
+fn sync_interactive_asks_before_each_file() {
+    conf::init();
+
+    conf::create_file_in_configs(".bashrc", Some("from root"));
+    conf::create_file_in_configs(".gitconfig", Some("from root"));
+    conf::create_file_in_home(".gitconfig", Some("home"));
+
+    // Files come in order: `.bashrc`, then `.gitconfig`.
+    let output = run_with_input(&["sync", "--interactive", &conf::root()], "n\ny\n");
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.contains("create '.bashrc'? [y,n,d,e,a,q,?]"));
+    assert!(
+        output
+            .stdout
+            .contains("overwrite '.gitconfig'? [y,n,d,e,a,q,?]")
+    );
+    // The diff is shown before asking.
+    assert!(output.stdout.contains("-home\n+from root\n"));
+    assert!(output.stdout.contains("Synced 1 file."));
+
+    assert!(!files::file_exists_in_home(".bashrc"));
+    assert_eq!(files::read_in_home(".gitconfig"), "from root");
+}
+
+#[test]
+fn sync_interactive_does_not_ask_about_unchanged_files() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("same"));
+    conf::create_file_in_home(".gitconfig", Some("same"));
+
+    let output = run_with_input(&["sync", "-i", &conf::root()], "");
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!output.stdout.contains("'.gitconfig'?"));
+}
+
+#[test]
+fn sync_interactive_asks_again_on_invalid_answer() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("from root"));
+
+    let output = run_with_input(&["sync", "-i", &conf::root()], "maybe\ny\n");
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout.matches("create '.gitconfig'?").count(), 2);
+    assert_eq!(files::read_in_home(".gitconfig"), "from root");
+}
+
+#[test]
+fn sync_interactive_apply_all() {
+    conf::init();
+
+    conf::create_file_in_configs(".bashrc", None);
+    conf::create_file_in_configs(".gitconfig", None);
+    conf::create_file_in_configs(".config/nvim/init.lua", None);
+
+    let output = run_with_input(&["sync", "-i", &conf::root()], "a\n");
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    // Asked once, for the first file.
+    assert_eq!(output.stdout.matches("? [y,n,d,e,a,q,?]").count(), 1);
+
+    assert!(files::file_exists_in_home(".bashrc"));
+    assert!(files::file_exists_in_home(".gitconfig"));
+    assert!(files::file_exists_in_home(".config/nvim/init.lua"));
+}
+
+#[test]
+fn sync_interactive_quit_skips_remaining_files() {
+    conf::init();
+
+    conf::create_file_in_configs(".bashrc", None);
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run_with_input(&["sync", "-i", &conf::root()], "y\nq\n");
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(files::file_exists_in_home(".bashrc"));
+    assert!(!files::file_exists_in_home(".gitconfig"));
+
+    // Skipped files are not recorded as deployed.
+    let manifest = files::read_manifest().unwrap();
+    assert!(manifest.contains(".bashrc"));
+    assert!(!manifest.contains(".gitconfig"));
+}
+
+#[test]
+fn sync_interactive_end_of_input_skips_remaining_files() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run_with_input(&["sync", "-i", &conf::root()], "");
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::file_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn sync_interactive_edit_before_applying() {
+    conf::init();
+
+    mock_bin("fake-editor", "bin_editor_append");
+    unsafe {
+        env::set_var("EDITOR", "fake-editor");
+    }
+
+    conf::create_file_in_configs(".gitconfig", Some("from root\n"));
+
+    let output = run_with_input(&["sync", "-i", &conf::root()], "e\ny\n");
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    // The diff is shown again, with the edits.
+    assert!(output.stdout.contains("+edited\n"));
+
+    assert_eq!(files::read_in_home(".gitconfig"), "from root\nedited\n");
+    // The root is left untouched.
+    assert_eq!(files::read_in_configs(".gitconfig"), "from root\n");
+}
+
+#[test]
+fn sync_interactive_dry_run_does_not_ask() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run_with_input(&["sync", "-i", "--dry-run", &conf::root()], "");
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!output.stdout.contains("'.gitconfig'?"));
+    assert!(output.stdout.contains("create           .gitconfig"));
+}
+
+#[cfg(unix)]
+#[test]
+fn sync_preserves_permissions() {
+    conf::init();
+
+    conf::create_file_in_configs(".ssh/config", None);
+    conf::set_mode_in_configs(".ssh/config", 0o600);
+    conf::create_executable_file_in_configs(".local/bin/script", None);
+    // Already there, but with different permissions.
+    conf::create_file_in_configs(".bashrc", Some("content"));
+    conf::create_file_in_home(".bashrc", Some("old content"));
+    conf::set_mode_in_home(".bashrc", 0o666);
+
+    let output = run(&["sync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::mode_in_home(".ssh/config"), 0o600);
+    assert_eq!(
+        files::mode_in_home(".local/bin/script"),
+        files::mode_in_configs(".local/bin/script")
+    );
+    assert_eq!(
+        files::mode_in_home(".bashrc"),
+        files::mode_in_configs(".bashrc")
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn sync_updates_permissions_of_unchanged_files() {
+    conf::init();
+
+    conf::create_executable_file_in_configs(".local/bin/script", Some("echo hi"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    // The script lost its `+x`.
+    conf::set_mode_in_home(".local/bin/script", 0o644);
+
+    let output = run(&["sync", "--dry-run", &conf::root()]);
+    dbg!(&output.stdout);
+    assert!(output.stdout.contains("update mode      .local/bin/script"));
+
+    let output = run(&["sync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        files::mode_in_home(".local/bin/script"),
+        files::mode_in_configs(".local/bin/script")
+    );
+
+    // The old permissions are backed up, so it can be undone.
+    let output = run(&["undo"]);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::mode_in_home(".local/bin/script"), 0o644);
+}
+
+/// Files are written next to their destination, then renamed over it.
+/// Read-only files get replaced like any other, and a temporary file
+/// left over by an interrupted run in the root is not deployed.
+#[cfg(unix)]
+#[test]
+fn sync_replaces_files_atomically() {
+    conf::init();
+
+    conf::create_file_in_configs(".bashrc", Some("from root"));
+    conf::create_file_in_configs(".bashrc.1234.deez-tmp", Some("partial"));
+    conf::create_file_in_home(".bashrc", Some("from home"));
+    conf::set_mode_in_home(".bashrc", 0o444);
+
+    let output = run(&["sync", "--force", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".bashrc"), "from root");
+    assert!(!files::file_exists_in_home(".bashrc.1234.deez-tmp"));
+
+    let leftovers = std::fs::read_dir(HOME)
+        .unwrap()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name().to_string_lossy().ends_with(".deez-tmp"))
+        .count();
+    assert_eq!(leftovers, 0);
+}
+
+#[test]
+fn sync_atomic_rolls_back_every_file_if_one_fails() {
+    conf::init();
+
+    conf::create_file_in_configs(".bashrc", Some("from root"));
+    conf::create_file_in_configs(".gitconfig", Some("from root"));
+    conf::create_file_in_home(".gitconfig", Some("from home"));
+    // `foo.txt` directory is not empty, it can't be replaced.
+    conf::create_file_in_configs("foo.txt", None);
+    conf::create_file_in_home("foo.txt/baz.log", None);
+
+    let output = run(&["sync", "--atomic", "--force", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stdout.contains("Rolled back 2 files."));
+
+    assert!(!files::file_exists_in_home(".bashrc"));
+    assert_eq!(files::read_in_home(".gitconfig"), "from home");
+    assert!(files::read_manifest().is_none());
+
+    // The rolled back run is already undone.
+    let output = run(&["undo"]);
+    assert_eq!(output.stdout, "Nothing to undo.\n");
+}
+
+#[test]
+fn sync_atomic_rolls_back_if_post_sync_hook_fails() {
+    conf::init();
+
+    conf::create_file_in_configs(".bashrc", Some("v1"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    let manifest = files::read_manifest();
+
+    conf::create_file_in_configs(".bashrc", Some("v2"));
+    conf::create_file_in_configs(".gitconfig", Some("from root"));
+    conf::create_executable_file_in_configs("post-sync.sh", Some("exit 1"));
+
+    let output = run(&["sync", "--atomic", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stdout.contains("Rolled back 2 files."));
+
+    assert_eq!(files::read_in_home(".bashrc"), "v1");
+    assert!(!files::file_exists_in_home(".gitconfig"));
+    // As if the run never happened.
+    assert_eq!(files::read_manifest(), manifest);
+}
+
+#[test]
+fn sync_without_atomic_keeps_files_synced_before_failure() {
+    conf::init();
+
+    conf::create_file_in_configs(".bashrc", Some("from root"));
+    conf::create_file_in_configs("foo.txt", None);
+    conf::create_file_in_home("foo.txt/baz.log", None);
+
+    let output = run(&["sync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(!output.stdout.contains("Rolled back"));
+    assert_eq!(files::read_in_home(".bashrc"), "from root");
+}
+
+#[test]
+fn sync_leaves_files_declared_as_links_alone() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/fish link\n"));
+    conf::create_file_in_configs(".config/fish/config.fish", None);
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run(&["sync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.starts_with("Synced 1 file"));
+    assert!(files::file_exists_in_home(".gitconfig"));
+    assert!(!files::file_exists_in_home(".config/fish/config.fish"));
+}
+
+/// A clean directory to deploy into, other than the home.
+fn staging_dir() -> PathBuf {
+    let staging = Path::new(HOME).with_file_name("staging");
+    if staging.exists() {
+        fs::remove_dir_all(&staging).unwrap();
+    }
+    fs::create_dir_all(&staging).unwrap();
+    staging
+}
+
+#[test]
+fn sync_into_given_home() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("gitconfig"));
+
+    let staging = staging_dir();
+
+    let output = run(&["--home", &staging.display().to_string(), "sync", CONFIGS]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        fs::read_to_string(staging.join(".gitconfig")).unwrap(),
+        "gitconfig"
+    );
+    assert!(!files::file_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn sync_into_home_from_environment() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let staging = staging_dir();
+
+    let output = run_with_env(
+        &["sync", CONFIGS],
+        CONFIGS,
+        &[("DEEZ_HOME", Some(&staging.display().to_string()))],
+    );
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(staging.join(".gitconfig").is_file());
+    assert!(!files::file_exists_in_home(".gitconfig"));
+
+    // `--home` takes precedence.
+    let output = run_with_env(
+        &["--home", HOME, "sync", CONFIGS],
+        CONFIGS,
+        &[("DEEZ_HOME", Some(&staging.display().to_string()))],
+    );
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(files::file_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn sync_into_given_home_passes_it_to_hooks() {
+    conf::init();
+
+    conf::create_executable_file_in_configs(
+        "pre-sync.sh",
+        Some("echo \"deez home: $DEEZ_HOME\"; echo \"home: $HOME\""),
+    );
+
+    let staging = staging_dir();
+
+    let output = run(&["--home", &staging.display().to_string(), "sync", CONFIGS]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.contains(&format!(
+        "deez home: {}\n",
+        staging.canonicalize().unwrap().display()
+    )));
+    // The hooks' own home is left alone.
+    assert!(output.stdout.contains(&format!("home: {HOME}\n")));
+}
+
+#[test]
+fn sync_into_missing_home_errors() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let missing = Path::new(HOME).with_file_name("missing");
+
+    let output = run(&["--home", &missing.display().to_string(), "sync", CONFIGS]);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert_eq!(
+        output.stderr,
+        format!(
+            "fatal: Home directory '{}' does not exist.\n",
+            missing.display()
+        )
+    );
+}
+
+#[test]
+fn sync_into_mapped_targets() {
+    conf::init();
+
+    let staging = staging_dir();
+
+    conf::create_file_in_configs(
+        ".deeztargets",
+        Some(&format!("etc {}\nshare .local/share\n", staging.display())),
+    );
+    conf::create_file_in_configs("etc/hosts", Some("hosts"));
+    conf::create_file_in_configs("share/fonts/font.ttf", None);
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run(&["sync", CONFIGS]);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(fs::read_to_string(staging.join("hosts")).unwrap(), "hosts");
+    assert!(!files::file_exists_in_home("etc/hosts"));
+    assert!(files::file_exists_in_home(".local/share/fonts/font.ttf"));
+    assert!(files::file_exists_in_home(".gitconfig"));
+    // The targets file itself is not deployed.
+    assert!(!files::file_exists_in_home(".deeztargets"));
+}
+
+#[test]
+fn sync_into_target_from_environment() {
+    conf::init();
+
+    let staging = staging_dir();
+
+    conf::create_file_in_configs(".deeztargets", Some(".config ${DEEZ_TEST_XDG}/\n"));
+    conf::create_file_in_configs(".config/fish/config.fish", None);
+
+    let output = run_with_env(
+        &["sync", CONFIGS],
+        CONFIGS,
+        &[("DEEZ_TEST_XDG", Some(&staging.display().to_string()))],
+    );
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(staging.join("fish/config.fish").is_file());
+    assert!(!files::file_exists_in_home(".config/fish/config.fish"));
+}
+
+#[test]
+fn sync_with_unset_target_variable_falls_back_to_home() {
+    conf::init();
+
+    conf::create_file_in_configs(".deeztargets", Some(".config $DEEZ_TEST_XDG/\n"));
+    conf::create_file_in_configs(".config/fish/config.fish", None);
+
+    let output = run_with_env(&["sync", CONFIGS], CONFIGS, &[("DEEZ_TEST_XDG", None)]);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(files::file_exists_in_home(".config/fish/config.fish"));
+}
+
+#[test]
+fn sync_with_invalid_targets_errors() {
+    conf::init();
+
+    conf::create_file_in_configs(".deeztargets", Some("../etc /etc\n"));
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run(&["sync", CONFIGS]);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stderr.contains("It must be inside the root"));
+    assert!(!files::file_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn sync_single_file_root() {
+    conf::init();
+
+    conf::create_file_in_configs(".config/git/config", Some("git"));
+    conf::create_file_in_configs(".config/fish/config.fish", None);
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let file = Path::new(CONFIGS).join(".config/git/config");
+    let output = run_in_dir(&["--verbose", "sync", &file.display().to_string()], HOME);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.starts_with(".config/git/config\n"));
+    assert_eq!(files::read_in_home(".config/git/config"), "git");
+    assert!(!files::file_exists_in_home(".config/fish/config.fish"));
+    assert!(!files::file_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn sync_single_file_root_rejects_pathspecs() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let file = Path::new(CONFIGS).join(".gitconfig");
+    let output = run(&["sync", &file.display().to_string(), "--", ".config"]);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 2);
+    assert!(
+        output
+            .stderr
+            .contains("Cannot use pathspecs with a root that is a file.")
+    );
+    assert!(!files::file_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn sync_single_file_outside_root_errors() {
+    conf::init();
+
+    conf::create_file_in_home(".gitconfig", None);
+
+    let file = Path::new(HOME).join(".gitconfig");
+    let output = run(&["sync", &file.display().to_string()]);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stderr.contains("is not inside a config root."));
+}
+
+#[test]
+fn sync_single_file_from_remote_root() {
+    conf::init();
+
+    mock_bin("git", "bin_git_clone");
+
+    let output = run(&["--verbose", "sync", "git:success[sub/root/.gitconfig]"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.contains("\n.gitconfig\n"));
+    assert!(files::file_exists_in_home(".gitconfig"));
+    // The root is the enclosing `sub/root`, not the repository root.
+    assert!(!files::file_exists_in_home("sub/root/.gitconfig"));
+}
+
+#[test]
+fn sync_picks_alternates_for_this_machine() {
+    conf::init();
+
+    conf::create_file_in_configs(".bashrc", Some("plain"));
+    conf::create_file_in_configs(&format!(".bashrc##os.{}", env::consts::OS), Some("os"));
+    conf::create_file_in_configs(".gitconfig", Some("plain"));
+    conf::create_file_in_configs(".gitconfig##host.work-laptop", Some("host"));
+    conf::create_file_in_configs(".zshrc##os.nowhere", Some("nowhere"));
+
+    let output = run_with_env(
+        &["sync", CONFIGS],
+        CONFIGS,
+        &[("DEEZ_HOST", Some("work-laptop"))],
+    );
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".bashrc"), "os");
+    assert_eq!(files::read_in_home(".gitconfig"), "host");
+    assert!(!files::file_exists_in_home(".zshrc"));
+    assert!(!files::file_exists_in_home(".gitconfig##host.work-laptop"));
+
+    // On another host, the plain file is back.
+    let output = run_with_env(
+        &["sync", "--force", CONFIGS],
+        CONFIGS,
+        &[("DEEZ_HOST", Some("desktop"))],
+    );
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "plain");
+}
+
+#[test]
+fn sync_alternate_with_pathspec_of_plain_file() {
+    conf::init();
+
+    conf::create_file_in_configs(&format!(".bashrc##os.{}", env::consts::OS), Some("os"));
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run(&["sync", CONFIGS, "--", ".bashrc"]);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".bashrc"), "os");
+    assert!(!files::file_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn sync_renders_templates() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezvars", Some("# Mine.\nemail = deez@example.com\n"));
+    conf::create_file_in_configs(
+        ".gitconfig.tmpl",
+        Some("email = {{ email }}\nhost = {{host}}\nos = {{ os }}\n"),
+    );
+
+    let output = run_with_env(
+        &["sync", CONFIGS],
+        CONFIGS,
+        &[("DEEZ_HOST", Some("work-laptop"))],
+    );
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        files::read_in_home(".gitconfig"),
+        format!(
+            "email = deez@example.com\nhost = work-laptop\nos = {}\n",
+            env::consts::OS
+        )
+    );
+    assert!(!files::file_exists_in_home(".gitconfig.tmpl"));
+    assert!(!files::file_exists_in_home(".deezvars"));
+}
+
+#[test]
+fn sync_template_with_unknown_variable_errors() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig.tmpl", Some("email = {{ email }}\n"));
+    conf::create_file_in_configs(".bashrc", None);
+
+    let output = run(&["sync", CONFIGS]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(
+        output
+            .stderr
+            .contains("Could not render '.gitconfig.tmpl': Unknown variable 'email' on line 1")
+    );
+    assert!(!files::file_exists_in_home(".gitconfig"));
+    assert!(files::file_exists_in_home(".bashrc"));
+}
+
+#[test]
+fn sync_copies_templates_declared_as_links() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some("* link\n"));
+    conf::create_file_in_configs(".gitconfig.tmpl", Some("os = {{ os }}\n"));
+
+    let output = run(&["sync", CONFIGS]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        files::read_in_home(".gitconfig"),
+        format!("os = {}\n", env::consts::OS)
+    );
+}
@@ -0,0 +1,259 @@
+mod utils;
+
+use utils::conf;
+use utils::files;
+use utils::run::run;
+
+// Warning: These tests MUST be run sequentially. Running them in
+// parallel threads may cause conflicts with environment variables,
+// as a variable may be overridden before it is used.
+
+#[test]
+fn undo_reverts_sync() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("from root"));
+    conf::create_file_in_configs(".config/nvim/init.lua", None);
+
+    conf::create_file_in_home(".gitconfig", Some("local edits"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "from root");
+
+    let output = run(&["--verbose", "undo"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    // Overwritten files are restored.
+    assert_eq!(files::read_in_home(".gitconfig"), "local edits");
+    // Created files are removed, and so are the dirs they leave empty.
+    assert!(!files::file_exists_in_home(".config/nvim/init.lua"));
+    assert!(!files::dir_exists_in_home(".config"));
+
+    assert!(output.stdout.contains("Reverted 2 files from `sync` run '"));
+}
+
+#[test]
+fn undo_reverts_link() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("from root"));
+    conf::create_file_in_home(".gitconfig", Some("local edits"));
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    assert!(files::symlink_exists_in_home(".gitconfig"));
+
+    let output = run(&["undo"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert!(files::file_exists_in_home(".gitconfig"));
+    assert_eq!(files::read_in_home(".gitconfig"), "local edits");
+}
+
+#[test]
+fn undo_reverts_rsync() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("old"));
+    conf::create_file_in_home(".gitconfig", Some("new"));
+
+    let output = run(&["rsync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_configs(".gitconfig"), "new");
+
+    let output = run(&["undo"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert_eq!(files::read_in_configs(".gitconfig"), "old");
+    assert_eq!(files::read_in_home(".gitconfig"), "new");
+}
+
+#[test]
+fn undo_reverts_clean() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+    conf::create_file_in_configs(".config/nvim/init.lua", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["clean", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::symlink_exists_in_home(".gitconfig"));
+
+    let output = run(&["undo"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    // Symlinks are re-created as symlinks.
+    assert!(files::symlink_exists_in_home(".gitconfig"));
+    assert!(files::symlink_exists_in_home(".config/nvim/init.lua"));
+}
+
+#[test]
+fn undo_twice_reverts_the_run_before() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("v1"));
+    conf::create_file_in_home(".gitconfig", Some("original"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    conf::create_file_in_configs(".gitconfig", Some("v2"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "v2");
+
+    let output = run(&["undo"]);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "v1");
+
+    let output = run(&["undo"]);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "original");
+
+    let output = run(&["undo"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "Nothing to undo.\n");
+}
+
+#[test]
+fn undo_with_nothing_to_undo() {
+    conf::init();
+
+    let output = run(&["undo"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "Nothing to undo.\n");
+}
+
+#[test]
+fn undo_backs_up_files_changed_since_the_run() {
+    conf::init();
+
+    conf::create_file_in_configs(".bashrc", Some("from root"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    // Edited after the sync, the edits must not be lost.
+    conf::create_file_in_home(".bashrc", Some("edited since"));
+
+    let output = run(&["undo"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+
+    assert!(!files::file_exists_in_home(".bashrc"));
+    assert_eq!(files::read_in_latest_backup(".bashrc"), "edited since");
+}
+
+#[test]
+fn undo_does_not_revert_dry_runs() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("v1"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    conf::create_file_in_configs(".gitconfig", Some("v2"));
+
+    let output = run(&["sync", "--dry-run", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["undo"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    // The dry-run left no trace, so the real run is undone.
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::file_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn undo_dry_run_does_not_revert() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("v1"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    conf::create_file_in_configs(".gitconfig", Some("v2"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["-n", "undo"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "v2");
+    assert!(output.stdout.contains("overwrite        "));
+    assert!(
+        output
+            .stdout
+            .contains("Would revert 1 file from `sync` run '")
+    );
+    assert!(output.stdout.ends_with("' (dry run).\n"));
+
+    // The run was not marked as undone, the real `undo` reverts it.
+    let output = run(&["undo"]);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "v1");
+}
+
+#[test]
+fn undo_then_sync_deploys_again() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("v1"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    conf::create_file_in_configs(".gitconfig", Some("v2"));
+    conf::create_file_in_configs(".bashrc", Some("bashrc"));
+
+    let output = run(&["sync", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["undo"]);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(files::read_in_home(".gitconfig"), "v1");
+    assert!(!files::file_exists_in_home(".bashrc"));
+
+    // The undone versions are no longer the base, the files are not
+    // taken for edits made in the home.
+    let output = run(&["sync", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "Synced 2 files.\n");
+    assert_eq!(files::read_in_home(".gitconfig"), "v2");
+    assert_eq!(files::read_in_home(".bashrc"), "bashrc");
+}
@@ -0,0 +1,220 @@
+mod utils;
+
+use utils::conf;
+use utils::files;
+use utils::run::run;
+
+// Warning: These tests MUST be run sequentially. Running them in
+// parallel threads may cause conflicts with environment variables,
+// as a variable may be overridden before it is used.
+
+#[test]
+fn unlink_replaces_links_with_copies() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", Some("gitconfig"));
+    conf::create_file_in_configs(".config/fish/config.fish", Some("fish"));
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["--verbose", "unlink", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(
+        output
+            .stdout
+            .starts_with(".config/fish/config.fish\n.gitconfig\n")
+    );
+    assert!(
+        output
+            .stdout
+            .ends_with(&format!("Unlinked 2 files from '{}'.\n", conf::root()))
+    );
+
+    assert!(!files::symlink_exists_in_home(".gitconfig"));
+    assert_eq!(files::read_in_home(".gitconfig"), "gitconfig");
+    assert!(!files::symlink_exists_in_home(".config/fish/config.fish"));
+    assert_eq!(files::read_in_home(".config/fish/config.fish"), "fish");
+    // The root is left as is.
+    assert_eq!(files::read_in_configs(".gitconfig"), "gitconfig");
+
+    // They are synced now.
+    let output = run(&["status", &conf::root()]);
+    dbg!(&output.stdout);
+    assert!(output.stdout.contains("  S  .gitconfig\n"));
+}
+
+#[test]
+fn unlink_records_copies_in_manifest() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["unlink", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let manifest = files::read_manifest().unwrap();
+    assert!(manifest.contains("copy\t"));
+    assert!(!manifest.contains("link\t"));
+}
+
+#[test]
+fn unlink_leaves_foreign_links_and_files_alone() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+    conf::create_file_in_configs(".bashrc", None);
+    conf::create_file_in_home(".bashrc", Some("home"));
+    conf::create_file_in_home("elsewhere", Some("elsewhere"));
+    conf::create_symlink_in_home(".gitconfig", Some("elsewhere"));
+
+    let output = run(&["unlink", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        output.stdout,
+        format!("Unlinked 0 files from '{}'.\n", conf::root())
+    );
+    assert!(files::symlink_exists_in_home(".gitconfig"));
+    assert_eq!(files::read_in_home(".bashrc"), "home");
+}
+
+#[test]
+fn unlink_with_pathspec() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+    conf::create_file_in_configs(".config/fish/config.fish", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["unlink", &conf::root(), "--", ".config/fish"]);
+    dbg!(&output.stdout);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::symlink_exists_in_home(".config/fish/config.fish"));
+    assert!(files::file_exists_in_home(".config/fish/config.fish"));
+    assert!(files::symlink_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn unlink_unfolds_folded_directories() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/nvim fold\n"));
+    conf::create_file_in_configs(".config/nvim/init.lua", Some("init"));
+    conf::create_file_in_configs(".config/nvim/lua/plugins.lua", Some("plugins"));
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    assert!(files::symlink_exists_in_home(".config/nvim"));
+
+    let output = run(&["unlink", &conf::root()]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::symlink_exists_in_home(".config/nvim"));
+    assert!(files::dir_exists_in_home(".config/nvim"));
+    assert_eq!(files::read_in_home(".config/nvim/init.lua"), "init");
+    assert_eq!(
+        files::read_in_home(".config/nvim/lua/plugins.lua"),
+        "plugins"
+    );
+    // The root is left as is.
+    assert!(files::file_exists_in_configs(".config/nvim/init.lua"));
+}
+
+#[test]
+fn unlink_refuses_part_of_folded_directory() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/nvim fold\n"));
+    conf::create_file_in_configs(".config/nvim/init.lua", None);
+    conf::create_file_in_configs(".config/nvim/lua/plugins.lua", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["unlink", &conf::root(), "--", ".config/nvim/init.lua"]);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stderr.contains("unlink the whole directory instead"));
+    assert!(files::symlink_exists_in_home(".config/nvim"));
+}
+
+#[test]
+fn unlink_dry_run_changes_nothing() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["--dry-run", "unlink", &conf::root()]);
+    dbg!(&output.stdout);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        output.stdout,
+        format!(
+            "replace symlink  .gitconfig\nWould unlink 1 file from '{}' (dry run).\n",
+            conf::root()
+        )
+    );
+    assert!(files::symlink_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn unlink_can_be_undone() {
+    conf::init();
+
+    conf::create_file_in_configs(".gitconfig", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["unlink", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::symlink_exists_in_home(".gitconfig"));
+
+    let output = run(&["undo"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(files::symlink_exists_in_home(".gitconfig"));
+}
+
+#[test]
+fn unlink_folded_directory_can_be_undone() {
+    conf::init();
+
+    conf::create_file_in_configs(".deezattributes", Some(".config/nvim fold\n"));
+    conf::create_file_in_configs(".config/nvim/lua/plugins.lua", None);
+
+    let output = run(&["link", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["unlink", &conf::root()]);
+    assert_eq!(output.exit_code, 0);
+    assert!(!files::symlink_exists_in_home(".config/nvim"));
+
+    let output = run(&["undo"]);
+    dbg!(&output.stdout);
+    dbg!(&output.stderr);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(files::symlink_exists_in_home(".config/nvim"));
+}
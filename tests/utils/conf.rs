@@ -5,6 +5,7 @@ use std::path::{Path, PathBuf};
 const TMP_DIR: &str = env!("CARGO_TARGET_TMPDIR");
 pub const CONFIGS: &str = concat!(env!("CARGO_TARGET_TMPDIR"), "/configs");
 pub const HOME: &str = concat!(env!("CARGO_TARGET_TMPDIR"), "/home");
+pub const STATE: &str = concat!(env!("CARGO_TARGET_TMPDIR"), "/state");
 
 pub fn root() -> String {
     PathBuf::from(CONFIGS).display().to_string()
@@ -24,8 +25,17 @@ pub fn init() {
         #[cfg(windows)]
         env::set_var("USERPROFILE", HOME);
 
-        // Can't test defaults if `DEEZ_ROOT` is set.
+        // Can't test defaults if `DEEZ_ROOT` (or `DEEZ_HOME`) is set.
         env::remove_var("DEEZ_ROOT");
+        env::remove_var("DEEZ_HOME");
+
+        // Keep backups and such out of the real state directory.
+        env::set_var("XDG_STATE_HOME", STATE);
+    }
+
+    // Clean new state dir.
+    if Path::new(STATE).exists() {
+        fs::remove_dir_all(STATE).unwrap();
     }
 
     // Clean new config dir.
@@ -56,6 +66,25 @@ pub fn create_executable_file_in_configs(file_path: &str, content: Option<&str>)
     f
 }
 
+/// Set the permission bits of a file (e.g., `0o600`).
+#[cfg(unix)]
+pub fn set_mode_in_configs(file_path: &str, mode: u32) {
+    set_mode(CONFIGS, file_path, mode);
+}
+
+/// Set the permission bits of a file (e.g., `0o600`).
+#[cfg(unix)]
+pub fn set_mode_in_home(file_path: &str, mode: u32) {
+    set_mode(HOME, file_path, mode);
+}
+
+#[cfg(unix)]
+fn set_mode(dir: &str, file_path: &str, mode: u32) {
+    use std::os::unix::fs::PermissionsExt;
+    let file = PathBuf::from(dir).join(file_path);
+    fs::set_permissions(file, fs::Permissions::from_mode(mode)).unwrap();
+}
+
 pub fn create_file_in_home(file_path: &str, content: Option<&str>) -> PathBuf {
     create_file(HOME, file_path, content)
 }
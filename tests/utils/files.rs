@@ -1,7 +1,7 @@
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use super::conf::{CONFIGS, HOME};
+use super::conf::{CONFIGS, HOME, STATE};
 
 pub fn file_exists_in_configs(file_path: &str) -> bool {
     let file = PathBuf::from(CONFIGS).join(file_path);
@@ -13,6 +13,11 @@ pub fn file_exists_in_home(file_path: &str) -> bool {
     file.is_file() && !file.is_symlink()
 }
 
+pub fn symlink_exists_in_configs(symlink_path: &str) -> bool {
+    let symlink = PathBuf::from(CONFIGS).join(symlink_path);
+    symlink.is_symlink()
+}
+
 pub fn symlink_exists_in_home(symlink_path: &str) -> bool {
     let symlink = PathBuf::from(HOME).join(symlink_path);
     symlink.is_symlink()
@@ -51,3 +56,64 @@ pub fn read_symlink_in_home(symlink_path: &str) -> PathBuf {
     let file = PathBuf::from(HOME).join(symlink_path);
     fs::read_link(file).unwrap()
 }
+
+/// Permission bits of a file (e.g., `0o644`).
+#[cfg(unix)]
+pub fn mode_in_configs(file_path: &str) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    let file = PathBuf::from(CONFIGS).join(file_path);
+    fs::metadata(file).unwrap().permissions().mode() & 0o7777
+}
+
+/// Permission bits of a file (e.g., `0o644`).
+#[cfg(unix)]
+pub fn mode_in_home(file_path: &str) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    let file = PathBuf::from(HOME).join(file_path);
+    fs::metadata(file).unwrap().permissions().mode() & 0o7777
+}
+
+/// Run directories of the backups, oldest first.
+pub fn backup_runs() -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(PathBuf::from(STATE).join("deez/backups")) else {
+        return Vec::new();
+    };
+    let mut runs: Vec<PathBuf> = entries.map(|entry| entry.unwrap().path()).collect();
+    runs.sort();
+    runs
+}
+
+/// Backups mirror the full, absolute path of the files.
+pub fn backup_of(run: &Path, file: &Path) -> PathBuf {
+    run.join("files")
+        .join(file.strip_prefix("/").unwrap_or(file))
+}
+
+pub fn backup_exists(file_path: &str) -> bool {
+    let file = PathBuf::from(HOME).join(file_path);
+    backup_runs().iter().any(|run| {
+        let backup = backup_of(run, &file);
+        backup.is_file() || backup.is_symlink()
+    })
+}
+
+pub fn read_in_latest_backup(file_path: &str) -> String {
+    let run = backup_runs().pop().expect("there should be a backup");
+    fs::read_to_string(backup_of(&run, &PathBuf::from(HOME).join(file_path))).unwrap()
+}
+
+pub fn read_journal(run: &Path) -> String {
+    fs::read_to_string(run.join("journal")).unwrap()
+}
+
+/// Read the manifest, assuming there is a single one.
+pub fn read_manifest() -> Option<String> {
+    let dir = PathBuf::from(STATE).join("deez/manifests");
+    let mut manifests = fs::read_dir(dir).ok()?;
+    let manifest = manifests.next()?.unwrap().path();
+    assert!(
+        manifests.next().is_none(),
+        "there should be a single manifest"
+    );
+    Some(fs::read_to_string(manifest).unwrap())
+}
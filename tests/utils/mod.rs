@@ -94,7 +94,7 @@ pub fn remove_output_file(file: &str) {
 
 // Every command with pre- and post-command hooks.
 // Keep in sync with `HOOKS` in `src/hooks.rs`.
-pub const HOOK_COMMANDS: &[&str] = &["sync", "rsync", "link", "status", "diff", "clean"];
+pub const HOOK_COMMANDS: &[&str] = &["sync", "rsync", "link", "deploy", "status", "diff", "clean"];
 
 /// Create all command hooks in configs.
 pub fn create_all_command_hooks(content: Option<&str>) {